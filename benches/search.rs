@@ -0,0 +1,48 @@
+use dropstf::{rank_results, SearchRanking, SearchResult, SteamId};
+use iai::black_box;
+
+const EMPTY: SearchResult = SearchResult {
+    steam_id: SteamId::new(0),
+    name: String::new(),
+    count: 0,
+    sim: 0.0,
+};
+
+/// Search rows as the database returns them, every player matching with two names
+///
+/// The rows are built at compile time so only ranking them is measured, moving them into a
+/// `Vec` is the only setup left in the benchmarks.
+const fn rows<const N: usize>() -> [SearchResult; N] {
+    let mut rows = [EMPTY; N];
+    let mut i = 0;
+    while i < N {
+        rows[i].steam_id = SteamId::new(76561197960265728 + i as u64 / 2);
+        rows[i].count = (i % 7) as i64;
+        rows[i].sim = (i % 10) as f64 / 10.0;
+        i += 1;
+    }
+    rows
+}
+
+const SINGLE_MATCH: [SearchResult; 1] = rows();
+const FULL_PAGE: [SearchResult; 50] = rows();
+
+fn rank_single_match() {
+    let _ = black_box(rank_results(
+        black_box(Vec::from(SINGLE_MATCH)),
+        SearchRanking::Popular,
+        10,
+        0,
+    ));
+}
+
+fn rank_full_page() {
+    let _ = black_box(rank_results(
+        black_box(Vec::from(FULL_PAGE)),
+        SearchRanking::Popular,
+        10,
+        0,
+    ));
+}
+
+iai::main!(rank_single_match, rank_full_page);
@@ -1,5 +1,5 @@
 use askama::Template;
-use dropstf::{DropStats, PlayerTemplate, SmolStr, SteamId};
+use dropstf::{DropStats, IdFormat, PlayerTemplate, RateInterval, SmolStr, SteamId};
 use iai::black_box;
 
 const PLAYER: PlayerTemplate = PlayerTemplate {
@@ -14,7 +14,13 @@ const PLAYER: PlayerTemplate = PlayerTemplate {
         dpu_rank: 2,
         dps_rank: 3,
         dpg_rank: 4,
+        last_active: None,
     },
+    aliases: Vec::new(),
+    similar: Vec::new(),
+    milestones: Vec::new(),
+    id_format: IdFormat::Steam64,
+    rate: RateInterval::Hour,
 };
 
 fn render_player() {
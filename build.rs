@@ -0,0 +1,65 @@
+//! Embed the files from `assets/` with their content hash in the file name
+//!
+//! Every asset is also gzipped here so it doesn't need to be compressed on every request.
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let out_dir = Path::new(&out_dir);
+
+    println!("cargo:rerun-if-changed=assets");
+
+    let mut files: Vec<_> = fs::read_dir("assets")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+
+    let mut assets = String::from("pub static ASSETS: &[Asset] = &[\n");
+    for path in files {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let data = fs::read(&path).unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&data).unwrap();
+        let gzip_path = out_dir.join(format!("{}.gz", name));
+        fs::write(&gzip_path, encoder.finish().unwrap()).unwrap();
+
+        let (stem, extension) = name.rsplit_once('.').unwrap();
+        let content_type = match extension {
+            "js" => "text/javascript",
+            "css" => "text/css",
+            "svg" => "image/svg+xml",
+            _ => "application/octet-stream",
+        };
+        writeln!(
+            assets,
+            "    Asset {{ name: {:?}, hashed_name: \"{}.{:016x}.{}\", content_type: {:?}, data: include_bytes!({:?}), gzip: include_bytes!({:?}) }},",
+            name,
+            stem,
+            fnv1a(&data),
+            extension,
+            content_type,
+            fs::canonicalize(&path).unwrap(),
+            gzip_path,
+        )
+        .unwrap();
+    }
+    assets.push_str("];\n");
+
+    fs::write(out_dir.join("assets.rs"), assets).unwrap();
+}
+
+/// Stable across builds and compiler versions, unlike the std hasher
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
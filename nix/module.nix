@@ -31,12 +31,25 @@ in {
       description = "port to listen on";
     };
 
+    bindAddress = mkOption {
+      type = types.str;
+      default = "0.0.0.0";
+      example = "::";
+      description = "ip address to listen on, \"::\" listens on both ipv6 and ipv4";
+    };
+
     enableUnixSocket = mkOption {
       type = types.bool;
       default = false;
       description = "listen to a unix socket instead of TCP";
     };
 
+    persistPlayerCache = mkOption {
+      type = types.bool;
+      default = false;
+      description = "keep the cached player stats across restarts";
+    };
+
     tracingEndpoint = mkOption {
       type = types.nullOr types.str;
       default = null;
@@ -64,10 +77,14 @@ in {
           }
           else {
             PORT = toString cfg.port;
+            BIND_ADDRESS = cfg.bindAddress;
           }
         )
         // (attrsets.optionalAttrs (cfg.tracingEndpoint != null) {
           TRACING_ENDPOINT = cfg.tracingEndpoint;
+        })
+        // (attrsets.optionalAttrs cfg.persistPlayerCache {
+          PLAYER_CACHE_SNAPSHOT = "/var/lib/dropstf/player_cache.json";
         });
 
       serviceConfig = {
@@ -102,6 +119,7 @@ in {
         PrivateUsers = true;
         ProcSubset = "pid";
         RuntimeDirectory = "dropstf";
+        StateDirectory = "dropstf";
         RestrictSUIDSGID = true;
       };
     };
@@ -4,7 +4,7 @@
   lib,
 }: let
   inherit (lib.sources) sourceByRegex;
-  src = sourceByRegex ../. ["Cargo.*" "(src|templates|benches|.sqlx)(/.*)?"];
+  src = sourceByRegex ../. ["Cargo.*" "build.rs" "(src|templates|assets|fonts|benches|tests|sql|.sqlx)(/.*)?"];
 in
   rustPlatform.buildRustPackage rec {
     pname = "dropstf";
@@ -0,0 +1,6 @@
+-- players that have been removed on request or whose account was deleted
+CREATE TABLE IF NOT EXISTS deleted_players
+(
+    steam_id   TEXT PRIMARY KEY,
+    deleted_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
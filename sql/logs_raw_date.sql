@@ -0,0 +1,2 @@
+-- the date a log was played at, for summing the logs of a date range without reading every log
+CREATE INDEX IF NOT EXISTS logs_raw_date ON logs_raw (((json->'info'->>'date')::float8));
@@ -0,0 +1,2 @@
+-- allows finding the logs a player appears in, used for the "last seen" date on profiles
+CREATE INDEX IF NOT EXISTS logs_raw_players ON logs_raw USING gin ((json -> 'players'));
@@ -0,0 +1,5 @@
+-- names are searched NFKC normalized, the same as the search itself, so names stored with
+-- fullwidth letters or other compatibility characters are found by their plain spelling.
+-- Needs pg_trgm and a UTF8 database
+CREATE INDEX IF NOT EXISTS medic_names_normalized ON medic_names
+    USING gist (normalize(name, NFKC) gist_trgm_ops);
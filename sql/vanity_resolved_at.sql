@@ -0,0 +1,3 @@
+-- when a vanity url was last resolved through steam, older entries are checked again since
+-- steam lets a released vanity url be claimed by someone else
+ALTER TABLE vanity_urls ADD COLUMN IF NOT EXISTS resolved_at TIMESTAMPTZ NOT NULL DEFAULT now();
@@ -0,0 +1,101 @@
+use crate::{DataSource, DataSourceSettings, DropsError, RedactedConfig, SteamId};
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::instrument;
+
+/// Shared secret required for the `/admin` endpoints, the endpoints are disabled when unset
+#[derive(Clone, Default)]
+pub struct AdminToken(Option<Arc<str>>);
+
+impl AdminToken {
+    pub fn new(token: Option<String>) -> Self {
+        AdminToken(token.map(Arc::from))
+    }
+}
+
+/// Only allow requests with an `Authorization: Bearer <token>` header matching the admin token
+pub async fn require_admin(
+    State(token): State<AdminToken>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(expected) = token.0 else {
+        return DropsError::NotFound.into_response();
+    };
+    let provided = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "));
+    match provided {
+        Some(provided) if constant_time_eq(provided.as_bytes(), expected.as_bytes()) => {
+            next.run(req).await
+        }
+        _ => DropsError::Unauthorized.into_response(),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Mark a player as deleted, their profile will respond with `410 Gone`
+#[instrument(skip(data_source))]
+pub async fn admin_flag_deleted(
+    Extension(data_source): Extension<DataSource>,
+    Path(steam_id): Path<String>,
+) -> Result<impl IntoResponse, DropsError> {
+    let steam_id = SteamId::from_any(&steam_id)?;
+    data_source.set_deleted(steam_id, true).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[instrument(skip(data_source))]
+pub async fn admin_unflag_deleted(
+    Extension(data_source): Extension<DataSource>,
+    Path(steam_id): Path<String>,
+) -> Result<impl IntoResponse, DropsError> {
+    let steam_id = SteamId::from_any(&steam_id)?;
+    data_source.set_deleted(steam_id, false).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the current name of a player from steam, for profiles that still show an old name
+#[instrument(skip(data_source))]
+pub async fn admin_refresh_player(
+    Extension(data_source): Extension<DataSource>,
+    Path(steam_id): Path<String>,
+) -> Result<impl IntoResponse, DropsError> {
+    let steam_id = SteamId::from_any(&steam_id)?;
+    let refresh = data_source.refresh_player_name(steam_id).await?;
+    Ok(Json(refresh))
+}
+
+#[derive(Debug, Serialize)]
+pub struct EffectiveConfig {
+    /// The configuration as read at startup, `None` when the router wasn't started from a config
+    pub config: Option<RedactedConfig>,
+    /// Maintenance can be toggled at runtime, so the configured value might not be the current one
+    pub maintenance: bool,
+    pub data_source: DataSourceSettings,
+}
+
+/// The configuration the process is running with, secrets are redacted
+#[instrument(skip_all)]
+pub async fn admin_config(
+    Extension(data_source): Extension<DataSource>,
+    Extension(config): Extension<Option<RedactedConfig>>,
+) -> Json<EffectiveConfig> {
+    Json(EffectiveConfig {
+        config,
+        maintenance: data_source.maintenance().is_enabled(),
+        data_source: data_source.settings(),
+    })
+}
@@ -0,0 +1,50 @@
+use crate::{preferred_encoding, DropsError, Encoding};
+use axum::extract::Path;
+use axum::http::header::{CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, VARY};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+
+/// A file from `assets/`, embedded in the binary by the build script
+pub struct Asset {
+    pub name: &'static str,
+    /// The name with the content hash added before the extension, `autocomplete.min.<hash>.js`
+    pub hashed_name: &'static str,
+    pub content_type: &'static str,
+    pub data: &'static [u8],
+    pub gzip: &'static [u8],
+}
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+/// The url an asset is served on
+///
+/// Because the url changes whenever the content does, browsers can cache the assets forever.
+pub fn asset_url(name: &str) -> String {
+    match ASSETS.iter().find(|asset| asset.name == name) {
+        Some(asset) => format!("/assets/{}", asset.hashed_name),
+        None => panic!("unknown asset {}", name),
+    }
+}
+
+pub async fn serve_asset(
+    Path(file): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, DropsError> {
+    let asset = ASSETS
+        .iter()
+        .find(|asset| asset.hashed_name == file)
+        .ok_or(DropsError::NotFound)?;
+    // only the gzip version is stored, clients preferring deflate get it from the compression layer
+    let accepts_gzip = preferred_encoding(&headers) == Some(Encoding::Gzip);
+
+    let headers = [
+        (CONTENT_TYPE, asset.content_type),
+        (CACHE_CONTROL, "public, max-age=31536000, immutable"),
+        (VARY, "Accept-Encoding"),
+    ];
+    Ok(if accepts_gzip {
+        (headers, [(CONTENT_ENCODING, "gzip")], asset.gzip).into_response()
+    } else {
+        (headers, asset.data).into_response()
+    })
+}
@@ -0,0 +1,165 @@
+use moka::future::Cache;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// A cache that keeps serving entries while they are being refreshed.
+///
+/// Once an entry is older than `refresh_after` the next lookup still returns the cached value
+/// immediately, but spawns a single background task to recompute it. Only a lookup for a key
+/// that isn't cached at all has to wait for the computation.
+///
+/// Because the inner cache still expires entries by its own ttl, the oldest value that can be
+/// served is bounded by that ttl.
+#[derive(Clone)]
+pub struct RefreshingCache<K, V> {
+    cache: Cache<K, Entry<V>>,
+    refreshing: Arc<Mutex<HashSet<K>>>,
+    refresh_after: Duration,
+    /// Lookups answered from the cache and lookups that had to wait for the value, for diagnostics
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+#[derive(Clone)]
+pub struct Entry<V> {
+    value: V,
+    created: Instant,
+}
+
+impl<V> Entry<V> {
+    fn new(value: V) -> Self {
+        Entry {
+            value,
+            created: Instant::now(),
+        }
+    }
+}
+
+impl<K, V> RefreshingCache<K, V>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new(cache: Cache<K, Entry<V>>, refresh_after: Duration) -> Self {
+        RefreshingCache {
+            cache,
+            refreshing: Arc::default(),
+            refresh_after,
+            hits: Arc::default(),
+            misses: Arc::default(),
+        }
+    }
+
+    pub async fn try_get_with<F, Fut, E>(&self, key: K, init: F) -> Result<V, Arc<E>>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<V, E>> + Send + 'static,
+        E: Display + Send + Sync + 'static,
+    {
+        if let Some(entry) = self.cache.get(&key).await {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            if entry.created.elapsed() > self.refresh_after && self.start_refresh(&key) {
+                self.spawn_refresh(key, init());
+            }
+            return Ok(entry.value);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let entry = self
+            .cache
+            .try_get_with(key, async { init().await.map(Entry::new) })
+            .await?;
+        Ok(entry.value)
+    }
+
+    /// The cache the entries are stored in, for inspecting its settings
+    pub fn inner(&self) -> &Cache<K, Entry<V>> {
+        &self.cache
+    }
+
+    /// Share of the lookups that were answered from the cache, `None` before the first lookup
+    ///
+    /// Lookups served while the entry is refreshed in the background count as hits.
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let lookups = hits + self.misses.load(Ordering::Relaxed);
+        (lookups > 0).then(|| hits as f64 / lookups as f64)
+    }
+
+    /// Number of entries being refreshed in the background right now
+    pub fn refreshing(&self) -> usize {
+        self.refreshing.lock().unwrap().len()
+    }
+
+    /// All cached entries, along with how long ago they were computed
+    pub fn entries(&self) -> Vec<(K, V, Duration)> {
+        self.cache
+            .iter()
+            .map(|(key, entry)| (K::clone(&key), entry.value, entry.created.elapsed()))
+            .collect()
+    }
+
+    /// Store a value that was computed `age` ago, like one restored from a snapshot
+    ///
+    /// The value is refreshed on the first lookup once it's older than `refresh_after`.
+    pub async fn insert_aged(&self, key: K, value: V, age: Duration) {
+        // shortly after boot the monotonic clock can't go back that far, the value is dropped then
+        if let Some(created) = Instant::now().checked_sub(age) {
+            self.cache.insert(key, Entry { value, created }).await;
+        }
+    }
+
+    /// Drop the cached entry, the next lookup waits for a fresh value
+    ///
+    /// A background refresh that is already running can still store the value it computed.
+    pub async fn invalidate(&self, key: &K) {
+        self.cache.invalidate(key).await;
+    }
+
+    /// Mark the key as being refreshed, returns false if a refresh is already running
+    fn start_refresh(&self, key: &K) -> bool {
+        self.refreshing.lock().unwrap().insert(key.clone())
+    }
+
+    fn spawn_refresh<Fut, E>(&self, key: K, fut: Fut)
+    where
+        Fut: Future<Output = Result<V, E>> + Send + 'static,
+        E: Display + Send + Sync + 'static,
+    {
+        let cache = self.cache.clone();
+        let guard = RefreshGuard {
+            refreshing: self.refreshing.clone(),
+            key: key.clone(),
+        };
+        tokio::spawn(async move {
+            let _guard = guard;
+            match fut.await {
+                Ok(value) => cache.insert(key, Entry::new(value)).await,
+                Err(e) => warn!(error = display(e), "failed to refresh cache entry"),
+            }
+        });
+    }
+}
+
+/// Unmarks the key as being refreshed when the refresh ends
+///
+/// Done on drop so a refresh that panics doesn't leave the key marked, which would keep it from
+/// ever being refreshed again.
+struct RefreshGuard<K: Hash + Eq> {
+    refreshing: Arc<Mutex<HashSet<K>>>,
+    key: K,
+}
+
+impl<K: Hash + Eq> Drop for RefreshGuard<K> {
+    fn drop(&mut self) {
+        if let Ok(mut refreshing) = self.refreshing.lock() {
+            refreshing.remove(&self.key);
+        }
+    }
+}
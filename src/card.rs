@@ -0,0 +1,117 @@
+use crate::data::{DataSource, TopOrder, TopStats};
+use crate::page::{page, with_page};
+use crate::DropsError;
+use askama::Template;
+use axum::body::Bytes;
+use axum::extract::Path;
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use axum::response::IntoResponse;
+use axum::Extension;
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{fontdb, Options, Tree};
+use std::sync::{Arc, LazyLock};
+use thiserror::Error;
+use tracing::instrument;
+
+/// Size of the cards, the aspect ratio social sites expect for large previews
+pub const CARD_WIDTH: u32 = 1200;
+pub const CARD_HEIGHT: u32 = 630;
+
+/// Number of players shown on a leaderboard card
+const CARD_PLAYERS: usize = 5;
+
+/// Longer names are cut off so they don't run into the stats
+const MAX_CARD_NAME_LENGTH: usize = 24;
+
+/// The cards only use the bundled font, so they look the same regardless of the fonts installed
+static FONTS: LazyLock<Arc<fontdb::Database>> = LazyLock::new(|| {
+    let mut fonts = fontdb::Database::new();
+    fonts.load_font_data(include_bytes!("../fonts/DejaVuSans.ttf").to_vec());
+    Arc::new(fonts)
+});
+
+#[derive(Debug, Error)]
+pub enum CardError {
+    #[error(transparent)]
+    Template(#[from] askama::Error),
+    #[error(transparent)]
+    Svg(#[from] resvg::usvg::Error),
+    #[error("Failed to rasterize card")]
+    Rasterize,
+}
+
+#[derive(Template)]
+#[template(path = "leaderboard_card.svg", escape = "html")]
+pub struct LeaderboardCardTemplate<'a> {
+    pub order: TopOrder,
+    pub top: &'a [TopStats],
+}
+
+impl LeaderboardCardTemplate<'_> {
+    fn value(&self, medic: &TopStats) -> String {
+        medic.value(self.order)
+    }
+
+    fn short_name(&self, name: &str) -> String {
+        if name.chars().count() > MAX_CARD_NAME_LENGTH {
+            let mut short: String = name.chars().take(MAX_CARD_NAME_LENGTH - 1).collect();
+            short.push('…');
+            short
+        } else {
+            name.into()
+        }
+    }
+}
+
+/// Rasterize an svg into a png
+///
+/// Text is always rendered in DejaVu Sans, the only font available.
+pub fn render_png(svg: &str) -> Result<Vec<u8>, CardError> {
+    let options = Options {
+        font_family: "DejaVu Sans".into(),
+        fontdb: FONTS.clone(),
+        ..Options::default()
+    };
+    let tree = Tree::from_str(svg, &options)?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = Pixmap::new(size.width(), size.height()).ok_or(CardError::Rasterize)?;
+    resvg::render(&tree, Transform::default(), &mut pixmap.as_mut());
+    pixmap.encode_png().map_err(|_| CardError::Rasterize)
+}
+
+pub fn render_leaderboard_card(order: TopOrder, top: &[TopStats]) -> Result<Vec<u8>, CardError> {
+    let template = LeaderboardCardTemplate {
+        order,
+        top: &top[..top.len().min(CARD_PLAYERS)],
+    };
+    render_png(&crate::render(&template)?)
+}
+
+/// Preview image of a leaderboard for links shared on social sites
+#[instrument(skip(data_source))]
+pub async fn leaderboard_card(
+    Extension(data_source): Extension<DataSource>,
+    Path(order): Path<TopOrder>,
+) -> Result<impl IntoResponse, DropsError> {
+    let top = data_source.top_stats(data_source.top_query(order)).await?;
+    let png = data_source
+        .card_cache()
+        .try_get_with(order, async move {
+            // rasterizing takes long enough that it shouldn't block the other requests
+            let page = page();
+            tokio::task::spawn_blocking(move || {
+                with_page(page, || render_leaderboard_card(order, &top))
+            })
+            .await
+            .map_err(|_| CardError::Rasterize)?
+            .map(Bytes::from)
+        })
+        .await?;
+    Ok((
+        [
+            (CONTENT_TYPE, "image/png"),
+            (CACHE_CONTROL, "public, max-age=900"),
+        ],
+        png,
+    ))
+}
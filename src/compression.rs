@@ -0,0 +1,174 @@
+use axum::body::{to_bytes, Body, HttpBody};
+use axum::extract::Request;
+use axum::http::header::{
+    ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY,
+};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use std::io::Write;
+use tracing::warn;
+
+/// Responses smaller than this aren't worth compressing
+pub const MIN_COMPRESS_SIZE: u64 = 1024;
+
+/// The content encodings responses can be compressed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    /// The zlib format, which is what `deflate` means in http
+    Deflate,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// The encoding from `Accept-Encoding` with the highest quality, gzip if it's a tie
+///
+/// `*` only stands for the encodings that aren't listed themselves, so `gzip;q=0, *` still
+/// refuses gzip. `None` if the client accepts neither, in which case the response is sent as is.
+pub fn preferred_encoding(headers: &HeaderMap) -> Option<Encoding> {
+    let mut gzip = None;
+    let mut deflate = None;
+    let mut any = None;
+    for item in headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+    {
+        let mut parts = item.split(';');
+        let quality = match parts.next().unwrap_or_default().trim() {
+            "gzip" | "x-gzip" => &mut gzip,
+            "deflate" => &mut deflate,
+            "*" => &mut any,
+            _ => continue,
+        };
+        *quality = Some(
+            parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0),
+        );
+    }
+    let gzip = gzip.or(any).unwrap_or(0.0);
+    let deflate = deflate.or(any).unwrap_or(0.0);
+    if gzip > 0.0 && gzip >= deflate {
+        Some(Encoding::Gzip)
+    } else if deflate > 0.0 {
+        Some(Encoding::Deflate)
+    } else {
+        None
+    }
+}
+
+/// Whether compressing a body of this type makes it smaller
+///
+/// Images other than svg and archives are compressed already, compressing them again only costs
+/// cpu time.
+fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    match essence {
+        "image/svg+xml" => true,
+        "application/gzip" | "application/x-gzip" | "application/zip" | "font/woff2" => false,
+        _ => !["image/", "audio/", "video/"]
+            .iter()
+            .any(|prefix| essence.starts_with(prefix)),
+    }
+}
+
+/// Whether the response can be sent compressed, regardless of what the client accepts
+///
+/// Streamed bodies are left alone, they would have to be buffered completely before they
+/// can be compressed.
+fn should_compress(response: &Response) -> bool {
+    let headers = response.headers();
+    let no_transform = headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"));
+    let compressible = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(is_compressible);
+    let size = response.body().size_hint().exact();
+
+    !headers.contains_key(CONTENT_ENCODING)
+        && !no_transform
+        && compressible
+        && size.is_some_and(|size| size >= MIN_COMPRESS_SIZE)
+}
+
+fn compress(data: &[u8], encoding: Encoding) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        Encoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+    }
+}
+
+/// Compress the response with the encoding the client prefers
+///
+/// Responses that already have a `Content-Encoding`, like the pre-compressed assets, are
+/// passed through untouched, as are responses marked `no-transform`.
+pub async fn compress_response(req: Request, next: Next) -> Response {
+    let encoding = preferred_encoding(req.headers());
+    let response = next.run(req).await;
+    if !should_compress(&response) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let vary = parts
+        .headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|header| header.trim().eq_ignore_ascii_case("accept-encoding"));
+    if !vary {
+        parts
+            .headers
+            .append(VARY, HeaderValue::from_static("Accept-Encoding"));
+    }
+    let Some(encoding) = encoding else {
+        return Response::from_parts(parts, body);
+    };
+
+    // the size is known, so the body is in memory already
+    let data = match to_bytes(body, usize::MAX).await {
+        Ok(data) => data,
+        Err(e) => {
+            warn!(error = %e, "failed to read the response to compress");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    match compress(&data, encoding) {
+        Ok(compressed) => {
+            parts.headers.remove(CONTENT_LENGTH);
+            parts.headers.insert(
+                CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.as_str()),
+            );
+            Response::from_parts(parts, Body::from(compressed))
+        }
+        Err(_) => Response::from_parts(parts, Body::from(data)),
+    }
+}
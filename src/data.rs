@@ -1,30 +1,176 @@
+use crate::cache::RefreshingCache;
+use crate::diagnostics::Check;
+use crate::log::{
+    LogDetail, LogRow, MapStats, RawLog, RawLogId, SharedLog, SharedLogPlayer, MAX_EXPORT_LOGS,
+    MAX_EXPORT_MAP_LOGS, MAX_SHARED_LOGS,
+};
+use crate::maintenance::Maintenance;
+use crate::rate_limit::{ClientIp, RateLimiter};
+use crate::ratio::{count_to_f64, format_ratio, per_hour, ratio, RateInterval, Rounding};
+use crate::retry::{retry, Backoff, RetryError};
+use crate::site::Site;
+use crate::snapshot::{PlayerCacheSnapshot, SnapshotEntry, SnapshotError};
 use crate::steam_id::SteamId;
 use crate::str::SmolStr;
 use crate::DropsError;
+use axum::body::Bytes;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeDelta, Utc};
+use futures_util::stream::{self, Stream, StreamExt};
+use futures_util::TryFutureExt;
+use metrics::histogram;
 use moka::future::Cache;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sqlx::types::JsonValue;
-use sqlx::PgPool;
-use std::collections::HashSet;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::Display;
-use std::sync::Arc;
-use std::time::Duration;
-use tracing::instrument;
+use std::future::Future;
+use std::hash::Hash;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::time::timeout;
+use tracing::{info, instrument, warn};
+use unicode_normalization::UnicodeNormalization;
 
 #[derive(Clone)]
 pub struct DataSource {
-    global_cache: Cache<(), GlobalStats>,
-    top_cache: Cache<TopOrder, Arc<Vec<TopStats>>>,
-    player_cache: Cache<SteamId, DropStats>,
+    global_cache: RefreshingCache<(), GlobalStats>,
+    ranked_global_cache: Cache<(), GlobalStats>,
+    top_cache: Cache<TopStatsQuery, Arc<Vec<TopStats>>>,
+    /// The last two leaderboards that were loaded into `top_cache`, to show how ranks moved
+    top_generations: Arc<Mutex<HashMap<TopStatsQuery, TopGenerations>>>,
+    neighbor_cache: Cache<(SteamId, TopOrder, i64), Arc<Vec<RankedStats>>>,
+    similar_cache: Cache<SteamId, Arc<Vec<TopStats>>>,
+    records_cache: Cache<(), Arc<Records>>,
+    rank_cache: Cache<(TopOrder, i64), Option<RankedStats>>,
+    /// Number of ranked players with more than a number of drops
+    drops_threshold_cache: Cache<i64, i64>,
+    /// Number of ranked players with at least a number of ubers
+    leaderboard_size_cache: Cache<i64, i64>,
+    player_cache: RefreshingCache<SteamId, DropStats>,
+    deleted_cache: Cache<SteamId, bool>,
+    alias_cache: Cache<SteamId, Arc<Vec<Alias>>>,
+    export_cache: Cache<SteamId, Arc<PlayerExport>>,
+    featured_cache: Cache<NaiveDate, Option<SteamId>>,
+    distribution_cache: Cache<DistributionQuery, Arc<Distribution>>,
+    /// Totals summed from the logs of a date range, see [`Self::global_stats_range`]
+    range_stats_cache: Cache<(NaiveDate, NaiveDate), GlobalStats>,
+    shared_logs_cache: Cache<(SteamId, SteamId), Arc<Vec<SharedLog>>>,
+    /// Rendered preview images of the leaderboards, see [`crate::card::leaderboard_card`]
+    card_cache: Cache<TopOrder, Bytes>,
+    /// Owners of recently reverified vanity urls, see [`Self::reverify_vanity_url`]
+    vanity_reverify_cache: Cache<String, Option<SteamId>>,
+    trigram_available: Arc<AtomicBool>,
+    /// The primary database, only used for writes
     database: PgPool,
+    /// Used for all reads, the same as the primary if there is no replica
+    replica: PgPool,
     api_key: String,
+    http_client: reqwest::Client,
+    player_summaries_url: String,
+    min_dpu_ubers: i64,
+    /// Medics need more than this many drops to be ranked
+    rank_cutoff: i64,
+    featured_pool: i64,
+    /// Players need at least this many drops to show up in name searches
+    search_min_drops: i64,
+    recompute_limit: Arc<Semaphore>,
+    /// Full leaderboard exports that can stream at the same time
+    export_limit: Arc<Semaphore>,
+    /// Date ranges whose logs can be summed at the same time
+    range_stats_limit: Arc<Semaphore>,
+    /// Limits how often a client can make us sum the logs of a date range that isn't cached
+    range_stats_rate_limit: RateLimiter,
+    /// Limits how often a client can make us resolve an unknown vanity url through the steam api
+    vanity_limit: RateLimiter,
+    /// Stored vanity urls older than this are checked against steam again, never when `None`
+    vanity_max_age: Option<TimeDelta>,
+    maintenance: Maintenance,
 }
 
+/// Medics need more than this many drops to be ranked by default
+///
+/// This has to match the cutoff used by the `ranked_medic_stats` view.
+pub const DEFAULT_RANK_CUTOFF: i64 = 100;
+
+/// Number of rank recomputes for unranked players that can run at the same time by default
+pub const DEFAULT_RECOMPUTE_CONCURRENCY: usize = 8;
+/// How long a recompute waits for its turn before the request is refused
+const RECOMPUTE_QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of full leaderboard exports that can stream at the same time
+const EXPORT_CONCURRENCY: usize = 2;
+/// Longest a full leaderboard export can take, so a client that reads slowly can't hold on to a
+/// database connection forever
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// Rows read ahead of the client during a full leaderboard export
+const EXPORT_BUFFER: usize = 64;
+
+/// Number of date ranges whose logs can be summed at the same time
+const RANGE_STATS_CONCURRENCY: usize = 2;
+/// Number of date ranges that aren't cached a client can have summed per minute
+const RANGE_STATS_RATE_LIMIT: u32 = 10;
+
+/// A vanity url that's known to exist, resolved to check that the steam api can be reached
+const DIAGNOSTICS_VANITY: &str = "gabelogannewell";
+/// Number of vanity urls resolved through the steam api at the same time
+const VANITY_CONCURRENCY: usize = 4;
+/// Steam is asked a few times before a vanity url is given up on, a blip shouldn't fail the page
+const VANITY_BACKOFF: Backoff = Backoff {
+    attempts: 3,
+    delay: Duration::from_millis(100),
+    timeout: Duration::from_secs(5),
+};
+/// A stored vanity url is only checked once and briefly, the stored player is a good enough
+/// answer while steam is slow
+const VANITY_REVERIFY_BACKOFF: Backoff = Backoff {
+    attempts: 1,
+    delay: Duration::ZERO,
+    timeout: Duration::from_secs(1),
+};
+/// How long the result of checking a stored vanity url is kept, also when steam couldn't be asked
+const VANITY_REVERIFY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Number of unknown vanity urls a client can have resolved through the steam api per minute by
+/// default
+pub const DEFAULT_VANITY_RATE_LIMIT: u32 = 20;
+
+/// Number of days after which a stored vanity url is checked against steam again by default
+pub const DEFAULT_VANITY_MAX_AGE_DAYS: u32 = 30;
+
+/// Minimum number of ubers to appear on the drops per uber leaderboard by default
+pub const DEFAULT_MIN_DPU_UBERS: i64 = 50;
+
+/// Number of top medics the featured player is picked from by default
+pub const DEFAULT_FEATURED_POOL: i64 = 50;
+
+/// Minimum number of drops for a player to show up in name searches by default
+pub const DEFAULT_SEARCH_MIN_DROPS: i64 = 0;
+
+/// Number of similar medics shown on a profile
+const SIMILAR_PLAYERS: i64 = 5;
+
 impl DataSource {
     pub fn new(database: PgPool, api_key: String) -> Self {
         DataSource {
-            global_cache: Cache::builder()
+            // every homepage render needs the global stats, so they're refreshed in the background
+            // instead of making the renders wait for the query once they expire
+            global_cache: RefreshingCache::new(
+                Cache::builder()
+                    .time_to_live(Duration::from_secs(15 * 60))
+                    .time_to_idle(Duration::from_secs(5 * 60))
+                    .build(),
+                Duration::from_secs(10 * 60),
+            ),
+            ranked_global_cache: Cache::builder()
                 .time_to_live(Duration::from_secs(15 * 60))
                 .time_to_idle(Duration::from_secs(5 * 60))
                 .build(),
@@ -32,18 +178,319 @@ impl DataSource {
                 .time_to_live(Duration::from_secs(15 * 60))
                 .time_to_idle(Duration::from_secs(5 * 60))
                 .build(),
-            player_cache: Cache::builder()
+            top_generations: Arc::default(),
+            rank_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(15 * 60))
+                .time_to_idle(Duration::from_secs(5 * 60))
+                .max_capacity(1024)
+                .build(),
+            drops_threshold_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(15 * 60))
+                .time_to_idle(Duration::from_secs(5 * 60))
+                .max_capacity(1024)
+                .build(),
+            leaderboard_size_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(15 * 60))
+                .build(),
+            neighbor_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(15 * 60))
+                .time_to_idle(Duration::from_secs(5 * 60))
+                .max_capacity(1024)
+                .build(),
+            similar_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(15 * 60))
+                .time_to_idle(Duration::from_secs(5 * 60))
+                .max_capacity(1024)
+                .build(),
+            // the single game record goes through every log, so it's only looked up once an hour
+            records_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(60 * 60))
+                .build(),
+            // popular profiles are served from cache while being refreshed in the background,
+            // so a served entry can be up to the 15 minute ttl old
+            player_cache: RefreshingCache::new(
+                Cache::builder()
+                    .time_to_live(Duration::from_secs(15 * 60))
+                    .time_to_idle(Duration::from_secs(5 * 60))
+                    .max_capacity(1024)
+                    .build(),
+                Duration::from_secs(10 * 60),
+            ),
+            deleted_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(15 * 60))
+                .max_capacity(1024)
+                .build(),
+            alias_cache: Cache::builder()
                 .time_to_live(Duration::from_secs(15 * 60))
                 .time_to_idle(Duration::from_secs(5 * 60))
                 .max_capacity(1024)
                 .build(),
+            export_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(60))
+                .max_capacity(256)
+                .build(),
+            featured_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(24 * 60 * 60))
+                .max_capacity(2)
+                .build(),
+            distribution_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(15 * 60))
+                .max_capacity(64)
+                .build(),
+            range_stats_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(10 * 60))
+                .max_capacity(256)
+                .build(),
+            shared_logs_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(10 * 60))
+                .max_capacity(256)
+                .build(),
+            card_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(15 * 60))
+                .build(),
+            vanity_reverify_cache: Cache::builder()
+                .time_to_live(VANITY_REVERIFY_INTERVAL)
+                .max_capacity(1024)
+                .build(),
+            trigram_available: Arc::new(AtomicBool::new(true)),
+            replica: database.clone(),
             database,
             api_key,
+            http_client: reqwest::Client::new(),
+            player_summaries_url: STEAM_PLAYER_SUMMARIES_URL.into(),
+            min_dpu_ubers: DEFAULT_MIN_DPU_UBERS,
+            rank_cutoff: DEFAULT_RANK_CUTOFF,
+            featured_pool: DEFAULT_FEATURED_POOL,
+            search_min_drops: DEFAULT_SEARCH_MIN_DROPS,
+            recompute_limit: Arc::new(Semaphore::new(DEFAULT_RECOMPUTE_CONCURRENCY)),
+            export_limit: Arc::new(Semaphore::new(EXPORT_CONCURRENCY)),
+            range_stats_limit: Arc::new(Semaphore::new(RANGE_STATS_CONCURRENCY)),
+            range_stats_rate_limit: RateLimiter::new(RANGE_STATS_RATE_LIMIT),
+            vanity_limit: RateLimiter::new(DEFAULT_VANITY_RATE_LIMIT),
+            vanity_max_age: Some(TimeDelta::days(DEFAULT_VANITY_MAX_AGE_DAYS.into())),
+            maintenance: Maintenance::default(),
+        }
+    }
+
+    /// Read from a replica instead of the primary database
+    ///
+    /// The replica can lag behind the primary a bit, which only delays newly stored vanity urls.
+    pub fn with_replica(mut self, replica: PgPool) -> Self {
+        self.replica = replica;
+        self
+    }
+
+    /// Set the minimum number of ubers for a medic to appear on the drops per uber leaderboard
+    pub fn with_min_dpu_ubers(mut self, min_dpu_ubers: i64) -> Self {
+        self.min_dpu_ubers = min_dpu_ubers;
+        self
+    }
+
+    /// Ask another url than the steam api for the names of players, for testing the name refresh
+    pub fn with_player_summaries_url(mut self, url: impl Into<String>) -> Self {
+        self.player_summaries_url = url.into();
+        self
+    }
+
+    /// Start in maintenance mode, see [`Maintenance`]
+    pub fn with_maintenance(self, enabled: bool) -> Self {
+        self.maintenance.set(enabled);
+        self
+    }
+
+    /// The maintenance mode of the site, toggled at runtime by the admin endpoints
+    pub fn maintenance(&self) -> &Maintenance {
+        &self.maintenance
+    }
+
+    pub(crate) fn card_cache(&self) -> &Cache<TopOrder, Bytes> {
+        &self.card_cache
+    }
+
+    /// Set the number of drops a medic needs to have more than to be ranked
+    ///
+    /// This has to match the cutoff of the `ranked_medic_stats` view, which only has the ranked
+    /// medics in it.
+    pub fn with_rank_cutoff(mut self, rank_cutoff: i64) -> Self {
+        self.rank_cutoff = rank_cutoff;
+        self
+    }
+
+    /// Medics need more than this many drops to be ranked
+    pub fn rank_cutoff(&self) -> i64 {
+        self.rank_cutoff
+    }
+
+    /// Set the number of top medics the featured player is picked from, 0 disables the featured player
+    pub fn with_featured_pool(mut self, featured_pool: i64) -> Self {
+        self.featured_pool = featured_pool;
+        self
+    }
+
+    /// Set the number of drops a player needs to show up in name searches, 0 includes everyone
+    ///
+    /// Searching by steam id always finds the player.
+    pub fn with_search_min_drops(mut self, search_min_drops: i64) -> Self {
+        self.search_min_drops = search_min_drops;
+        self
+    }
+
+    /// Set the number of unknown vanity urls a client can resolve per minute, 0 disables the limit
+    pub fn with_vanity_rate_limit(mut self, per_minute: u32) -> Self {
+        self.vanity_limit = RateLimiter::new(per_minute);
+        self
+    }
+
+    /// Set the age after which a stored vanity url is checked against steam again, 0 disables the check
+    pub fn with_vanity_max_age(mut self, max_age: Duration) -> Self {
+        self.vanity_max_age = Some(max_age)
+            .filter(|max_age| !max_age.is_zero())
+            .and_then(|max_age| TimeDelta::from_std(max_age).ok());
+        self
+    }
+
+    /// Whether a vanity url that was resolved at `resolved_at` should be checked against steam again
+    pub fn is_vanity_stale(&self, resolved_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        self.vanity_max_age
+            .is_some_and(|max_age| now - resolved_at > max_age)
+    }
+
+    /// Set the number of rank recomputes for unranked players that can run at the same time
+    pub fn with_recompute_concurrency(mut self, concurrency: usize) -> Self {
+        self.recompute_limit = Arc::new(Semaphore::new(concurrency.max(1)));
+        self
+    }
+
+    /// Probe the databases and the steam api and collect the state of the caches
+    ///
+    /// Steam is asked only once, without the retries of a real lookup, so a failing check shows
+    /// steam is unreliable right now.
+    #[instrument(skip(self))]
+    pub async fn diagnostics(&self) -> DataSourceDiagnostics {
+        let (database, replica, steam) = tokio::join!(
+            Check::run(sqlx::query("SELECT 1").execute(&self.database)),
+            Check::run(sqlx::query("SELECT 1").execute(&self.replica)),
+            // the raw request errors include the url with the api key
+            Check::run(
+                steam_resolve_vanity::resolve_vanity_url(DIAGNOSTICS_VANITY, &self.api_key)
+                    .map_err(DropsError::from)
+            ),
+        );
+        let mut caches: BTreeMap<&'static str, CacheHealth> = self
+            .settings()
+            .caches
+            .into_iter()
+            .map(|(name, settings)| {
+                let health = CacheHealth {
+                    entries: settings.entries,
+                    hit_ratio: None,
+                };
+                (name, health)
+            })
+            .collect();
+        // only the refreshing caches count their hits
+        for (name, hit_ratio) in [
+            ("global", self.global_cache.hit_ratio()),
+            ("player", self.player_cache.hit_ratio()),
+        ] {
+            if let Some(cache) = caches.get_mut(name) {
+                cache.hit_ratio = hit_ratio;
+            }
+        }
+        DataSourceDiagnostics {
+            database,
+            replica,
+            steam,
+            caches,
+            refreshing: self.global_cache.refreshing() + self.player_cache.refreshing(),
         }
     }
 
+    /// The settings the data source runs with, for `/admin/config`
+    pub fn settings(&self) -> DataSourceSettings {
+        DataSourceSettings {
+            min_dpu_ubers: self.min_dpu_ubers,
+            rank_cutoff: self.rank_cutoff,
+            vanity_rate_limit: self.vanity_limit.per_minute(),
+            vanity_max_age_secs: self
+                .vanity_max_age
+                .map_or(0, |max_age| max_age.num_seconds()),
+            featured_pool: self.featured_pool,
+            search_min_drops: self.search_min_drops,
+            trigram_available: self.trigram_available.load(Ordering::Relaxed),
+            available_recomputes: self.recompute_limit.available_permits(),
+            database: PoolSettings::new(&self.database),
+            replica: PoolSettings::new(&self.replica),
+            caches: BTreeMap::from([
+                ("global", CacheSettings::new(self.global_cache.inner())),
+                (
+                    "ranked_global",
+                    CacheSettings::new(&self.ranked_global_cache),
+                ),
+                ("top", CacheSettings::new(&self.top_cache)),
+                ("neighbor", CacheSettings::new(&self.neighbor_cache)),
+                ("similar", CacheSettings::new(&self.similar_cache)),
+                ("records", CacheSettings::new(&self.records_cache)),
+                ("rank", CacheSettings::new(&self.rank_cache)),
+                (
+                    "drops_threshold",
+                    CacheSettings::new(&self.drops_threshold_cache),
+                ),
+                (
+                    "leaderboard_size",
+                    CacheSettings::new(&self.leaderboard_size_cache),
+                ),
+                ("player", CacheSettings::new(self.player_cache.inner())),
+                ("deleted", CacheSettings::new(&self.deleted_cache)),
+                ("alias", CacheSettings::new(&self.alias_cache)),
+                ("export", CacheSettings::new(&self.export_cache)),
+                (
+                    "vanity_reverify",
+                    CacheSettings::new(&self.vanity_reverify_cache),
+                ),
+                ("featured", CacheSettings::new(&self.featured_cache)),
+                ("distribution", CacheSettings::new(&self.distribution_cache)),
+                ("range_stats", CacheSettings::new(&self.range_stats_cache)),
+                ("shared_logs", CacheSettings::new(&self.shared_logs_cache)),
+                ("card", CacheSettings::new(&self.card_cache)),
+            ]),
+        }
+    }
+
+    /// Check the database for optional features we rely on
+    ///
+    /// If the `pg_trgm` extension is missing, searching falls back to a plain `ILIKE` without
+    /// similarity ranking.
+    #[instrument(skip(self))]
+    pub async fn verify_schema(&self) -> Result<(), DropsError> {
+        let trigram = sqlx::query!(
+            r#"SELECT EXISTS(SELECT 1 FROM pg_extension WHERE extname = 'pg_trgm') AS "trigram!""#
+        )
+        .fetch_one(&self.replica)
+        .await?
+        .trigram;
+        if !trigram {
+            warn!(
+                "pg_trgm extension is not installed, search results won't be ranked by similarity"
+            );
+        }
+        self.trigram_available.store(trigram, Ordering::Relaxed);
+        Ok(())
+    }
+
     #[instrument(skip(self))]
-    pub async fn player_search(&self, search: &str) -> Result<Vec<SearchResult>, DropsError> {
+    pub async fn player_search(
+        &self,
+        search: &str,
+        ranking: SearchRanking,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<SearchResult>, DropsError> {
+        let search = normalize_search(search);
+        let search = search.as_str();
+        if search.chars().count() > MAX_SEARCH_LENGTH {
+            return Err(DropsError::SearchTooLong);
+        }
         if let Ok(steam_id) = search.parse() {
             if let Some(name) = self.get_user_name(steam_id).await? {
                 return Ok(vec![SearchResult {
@@ -51,10 +498,14 @@ impl DataSource {
                     name,
                     count: 1,
                     sim: 1.0,
-                }]);
+                }]
+                .into_iter()
+                .skip(offset)
+                .collect());
             }
         }
-        self.player_wildcard_search(search).await
+        self.player_wildcard_search(search, ranking, limit, offset)
+            .await
     }
 
     #[instrument(skip(self))]
@@ -63,253 +514,1972 @@ impl DataSource {
             r#"SELECT name FROM user_names WHERE steam_id=$1"#,
             steam_id.steam3()
         )
-        .fetch_one(&self.database)
+        .fetch_optional(&self.replica)
         .await?;
 
-        Ok(result.name)
+        Ok(result.and_then(|row| row.name))
     }
 
     #[instrument(skip(self))]
-    async fn player_wildcard_search(&self, search: &str) -> Result<Vec<SearchResult>, DropsError> {
-        let mut players: Vec<SearchResult> = sqlx::query_as!(
-            SearchResult,
-            r#"SELECT steam_id as "steam_id!: _", name as "name!", count as "count!", (1 - (name  <-> $1)) AS "sim!" 
-            FROM medic_names
-            WHERE name ~* $1
-            ORDER BY count DESC
-            LIMIT 50"#,
-            search
-        )
-            .fetch_all(&self.database)
+    async fn player_wildcard_search(
+        &self,
+        search: &str,
+        ranking: SearchRanking,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<SearchResult>, DropsError> {
+        // players can have multiple names, so fetch enough rows to fill the page after de-duplicating,
+        // for precise searches these are the closest names instead of the most used ones.
+        // The candidates don't depend on the page, otherwise re-ranking them could shift players
+        // between pages
+        let row_limit = SEARCH_CANDIDATES as i64;
+
+        // the timeout only applies to this transaction, so an expensive pattern can't hold on to a
+        // replica connection for long
+        let mut tx = self.replica.begin().await?;
+        sqlx::query("SELECT set_config('statement_timeout', $1, true)")
+            .bind(format!("{}ms", SEARCH_STATEMENT_TIMEOUT.as_millis()))
+            .execute(&mut *tx)
             .await?;
 
-        players.sort_by(|a, b| b.weight().partial_cmp(&a.weight()).unwrap());
+        // the stored names are compared in the same normalized form as the search, see
+        // `sql/medic_names_normalized.sql`. Ties are broken by steam id in numeric order like
+        // `SearchResult::cmp_relevance` does, so the candidates that make the cut are the ones that
+        // come first after ranking. Shorter `[U:1:n]` ids have the lower account number.
+        let players: Vec<SearchResult> = if self.trigram_available.load(Ordering::Relaxed) {
+            timed("player_search", sqlx::query_as!(
+                SearchResult,
+                r#"SELECT steam_id as "steam_id!: _", name as "name!", count as "count!", (1 - (normalize(name, NFKC) <-> $1)) AS "sim!"
+            FROM medic_names
+            WHERE normalize(name, NFKC) ~* $1
+            AND ($4 <= 0 OR EXISTS(SELECT 1 FROM medic_stats WHERE medic_stats.steam_id = medic_names.steam_id AND drops >= $4))
+            ORDER BY CASE WHEN $3 THEN normalize(name, NFKC) <-> $1 END, count DESC, length(steam_id), steam_id
+            LIMIT $2"#,
+                search,
+                row_limit,
+                ranking == SearchRanking::Precise,
+                self.search_min_drops
+            )
+                .fetch_all(&mut *tx))
+                .await
+                .map_err(search_error)?
+        } else {
+            // without pg_trgm there is no similarity, so results are only ordered by count
+            timed("player_search", sqlx::query_as!(
+                SearchResult,
+                r#"SELECT steam_id as "steam_id!: _", name as "name!", count as "count!", 0::float8 AS "sim!"
+                FROM medic_names
+                WHERE normalize(name, NFKC) ILIKE '%' || $1 || '%'
+                AND ($3 <= 0 OR EXISTS(SELECT 1 FROM medic_stats WHERE medic_stats.steam_id = medic_names.steam_id AND drops >= $3))
+                ORDER BY count DESC, length(steam_id), steam_id
+                LIMIT $2"#,
+                escape_like(search),
+                row_limit,
+                self.search_min_drops
+            )
+                .fetch_all(&mut *tx))
+                .await
+                .map_err(search_error)?
+        };
+        tx.commit().await?;
+
+        Ok(rank_results(players, ranking, limit, offset))
+    }
 
-        let mut found = HashSet::new();
+    #[instrument(skip(self))]
+    pub async fn stats_for_user(&self, steam_id: SteamId) -> Result<DropStats, DropsError> {
+        if self.is_deleted(steam_id).await? {
+            return Err(DropsError::PlayerGone);
+        }
 
-        Ok(players
-            .into_iter()
-            .filter(|player| {
-                if found.contains(&player.steam_id) {
-                    false
-                } else {
-                    found.insert(player.steam_id);
-                    true
+        let data_source = self.clone();
+        let result = match self
+            .player_cache
+            .try_get_with(steam_id, move || {
+                let data_source = data_source.clone();
+                async move {
+                    let stats = timed(
+                        "stats_for_user",
+                        data_source.fetch_stats_for_user(&data_source.replica, steam_id),
+                    )
+                    .await?;
+                    if stats.is_implausible() {
+                        warn!(
+                            steam_id = ?steam_id,
+                            drops = stats.drops,
+                            ubers = stats.ubers,
+                            games = stats.games,
+                            medic_time = stats.medic_time,
+                            "implausible stats for player"
+                        );
+                    }
+                    Ok::<_, StatsError>(stats)
                 }
             })
-            .collect())
+            .await
+        {
+            Ok(stats) => stats,
+            Err(e) => {
+                return match &*e {
+                    StatsError::Overloaded => Err(DropsError::Overloaded),
+                    // players can show up in logs without ever playing medic
+                    StatsError::Database(e) if matches!(**e, sqlx::Error::RowNotFound) => {
+                        match self.get_user_name(steam_id).await? {
+                            Some(name) => Err(DropsError::NoMedicStats { steam_id, name }),
+                            None => Err(e.clone().into()),
+                        }
+                    }
+                    StatsError::Database(e) => Err(e.clone().into()),
+                };
+            }
+        };
+        Ok(result)
     }
 
-    #[instrument(skip(self))]
-    pub async fn stats_for_user(&self, steam_id: SteamId) -> Result<DropStats, DropsError> {
-        let result = self.player_cache.try_get_with(steam_id, async {
-            // for medics with more than 100 drops we have cached info
-            if let Ok(result) = sqlx::query_as!(
-                DropStats,
-                r#"SELECT steam_id as "steam_id!: _", name as "name!: _", games as "games!", ubers as "ubers!", drops as "drops!",
-                medic_time as "medic_time!", drops_rank as "drops_rank!", dpu_rank as "dpu_rank!", dps_rank as "dps_rank!", dpg_rank as "dpg_rank!"
+    async fn fetch_stats_for_user(
+        &self,
+        pool: &PgPool,
+        steam_id: SteamId,
+    ) -> Result<DropStats, StatsError> {
+        // for medics with more than `rank_cutoff` drops we have cached info, the last time the player
+        // was seen comes from the dates of the raw logs they appear in. The stored ranks count the
+        // deleted players, the medics with too few ubers for the dpu leaderboard and everyone the
+        // import ranked under its own cutoff, so the ranks are counted here with the same filters
+        // as the leaderboards
+        if let Ok(result) = sqlx::query_as!(
+            DropStats,
+            r#"SELECT steam_id as "steam_id!: _", name as "name!: _", games as "games!", ubers as "ubers!", drops as "drops!",
+                medic_time as "medic_time!",
+                (SELECT COUNT(*) FROM ranked_medic_stats m2 WHERE m2.drops > ranked_medic_stats.drops AND m2.drops > $3 AND m2.steam_id NOT IN (SELECT steam_id FROM deleted_players)) + 1 AS "drops_rank!",
+                (SELECT COUNT(*) FROM ranked_medic_stats m2 WHERE m2.dpu > COALESCE(ranked_medic_stats.dpu, 0) AND m2.ubers >= $2 AND m2.drops > $3 AND m2.steam_id NOT IN (SELECT steam_id FROM deleted_players)) + 1 AS "dpu_rank!",
+                (SELECT COUNT(*) FROM ranked_medic_stats m2 WHERE m2.dps > COALESCE(ranked_medic_stats.dps, 0) AND m2.drops > $3 AND m2.steam_id NOT IN (SELECT steam_id FROM deleted_players)) + 1 AS "dps_rank!",
+                (SELECT COUNT(*) FROM ranked_medic_stats m2 WHERE m2.dpg > COALESCE(ranked_medic_stats.dpg, 0) AND m2.drops > $3 AND m2.steam_id NOT IN (SELECT steam_id FROM deleted_players)) + 1 AS "dpg_rank!",
+                (SELECT MAX(to_timestamp((json->'info'->>'date')::float8)) FROM logs_raw WHERE json->'players' ? $1) AS "last_active"
                 FROM ranked_medic_stats
-                WHERE steam_id=$1"#,
-                steam_id.steam3()
-            )
-                .fetch_one(&self.database)
-                .await {
-                Ok(result)
-            } else {
-                // for other we need to recalculate
-                sqlx::query_as!(
-                    DropStats,
-                    r#"SELECT user_names.steam_id as "steam_id!: _", name as "name!: _", games as "games!", ubers as "ubers!", drops as "drops!", medic_time as "medic_time!",
-                    (SELECT COUNT(*) FROM ranked_medic_stats m2 WHERE m2.drops > medic_stats.drops AND m2.drops > 100) + 1 AS "drops_rank!",
-                    (SELECT COUNT(*) FROM ranked_medic_stats m2 WHERE m2.dpu > medic_stats.dpu AND m2.drops > 100) + 1 AS "dpu_rank!",
-                    (SELECT COUNT(*) FROM ranked_medic_stats m2 WHERE m2.dps > medic_stats.dps AND m2.drops > 100) + 1 AS "dps_rank!",
-                    (SELECT COUNT(*) FROM ranked_medic_stats m2 WHERE m2.dpg > medic_stats.dpg AND m2.drops > 100) + 1 AS "dpg_rank!"
+                WHERE steam_id=$1 AND drops > $3"#,
+            steam_id.steam3(),
+            self.min_dpu_ubers,
+            self.rank_cutoff
+        )
+            .fetch_one(pool)
+            .await {
+            Ok(result)
+        } else {
+            // recomputing is expensive, so only a limited number run at once and the rest waits
+            // for a bit before giving up
+            let Ok(Ok(_permit)) =
+                tokio::time::timeout(RECOMPUTE_QUEUE_TIMEOUT, self.recompute_limit.acquire()).await
+            else {
+                return Err(StatsError::Overloaded);
+            };
+
+            // for other we need to recalculate, the ratios are computed from the raw totals the
+            // same way they are displayed, so players without ubers, time or games rank last
+            Ok(sqlx::query_as!(
+                DropStats,
+                r#"SELECT user_names.steam_id as "steam_id!: _", name as "name!: _", games as "games!", ubers as "ubers!", drops as "drops!", medic_time as "medic_time!",
+                    (SELECT COUNT(*) FROM ranked_medic_stats m2 WHERE m2.drops > medic_stats.drops AND m2.drops > $2 AND m2.steam_id NOT IN (SELECT steam_id FROM deleted_players)) + 1 AS "drops_rank!",
+                    (SELECT COUNT(*) FROM ranked_medic_stats m2 WHERE m2.drops::float8 / NULLIF(m2.ubers, 0) > COALESCE(medic_stats.drops::float8 / NULLIF(medic_stats.ubers, 0), 0) AND m2.drops > $2 AND m2.ubers >= $3 AND m2.steam_id NOT IN (SELECT steam_id FROM deleted_players)) + 1 AS "dpu_rank!",
+                    (SELECT COUNT(*) FROM ranked_medic_stats m2 WHERE m2.drops::float8 / NULLIF(m2.medic_time, 0) > COALESCE(medic_stats.drops::float8 / NULLIF(medic_stats.medic_time, 0), 0) AND m2.drops > $2 AND m2.steam_id NOT IN (SELECT steam_id FROM deleted_players)) + 1 AS "dps_rank!",
+                    (SELECT COUNT(*) FROM ranked_medic_stats m2 WHERE m2.drops::float8 / NULLIF(m2.games, 0) > COALESCE(medic_stats.drops::float8 / NULLIF(medic_stats.games, 0), 0) AND m2.drops > $2 AND m2.steam_id NOT IN (SELECT steam_id FROM deleted_players)) + 1 AS "dpg_rank!",
+                    (SELECT MAX(to_timestamp((json->'info'->>'date')::float8)) FROM logs_raw WHERE json->'players' ? $1) AS "last_active"
                     FROM medic_stats
                     INNER JOIN user_names ON user_names.steam_id = medic_stats.steam_id
                     WHERE medic_stats.steam_id=$1"#,
-                    steam_id.steam3()
-                )
-                    .fetch_one(&self.database)
-                    .await
-            }
-        }).await?;
-        Ok(result)
+                steam_id.steam3(),
+                self.rank_cutoff,
+                self.min_dpu_ubers
+            )
+                .fetch_one(pool)
+                .await?)
+        }
+    }
+
+    /// The query for a leaderboard as shown on the site
+    pub fn top_query(&self, order: TopOrder) -> TopStatsQuery {
+        let query = TopStatsQuery::new(order);
+        // medics with only a handful of ubers would dominate the per uber leaderboard
+        match order {
+            TopOrder::Dpu => query.with_min_ubers(self.min_dpu_ubers),
+            _ => query,
+        }
     }
 
+    /// Load a page of a leaderboard
+    ///
+    /// Pages within the first [`TOP_WINDOW`] medics are sliced from a single cached query for the
+    /// whole window, so browsing the first pages or changing the page size doesn't query the
+    /// database again. Pages past the window are queried and cached on their own.
     #[instrument(skip(self))]
-    pub async fn top_stats(&self, order: TopOrder) -> Result<Arc<Vec<TopStats>>, DropsError> {
-        let result = self.top_cache.try_get_with::<_, sqlx::Error>(order, async {
-            let result = match order {
-                TopOrder::Drops => {
-                    sqlx::query_as!(
-                        TopStats,
-                        r#"SELECT steam_id as "steam_id!: _", games as "games!", ubers as "ubers!", drops as "drops!", medic_time as "medic_time!", name as "name!"
-                        FROM ranked_medic_stats
-                        ORDER BY drops DESC LIMIT 25"#
-                    )
-                        .fetch_all(&self.database)
-                        .await?
-                }
-                TopOrder::Dps => {
-                    sqlx::query_as!(
-                        TopStats,
-                        r#"SELECT steam_id as "steam_id!: _", games as "games!", ubers as "ubers!", drops as "drops!", medic_time as "medic_time!", name as "name!"
-                        FROM ranked_medic_stats
-                        ORDER BY dps DESC LIMIT 25"#
-                    )
-                        .fetch_all(&self.database)
-                        .await?
-                }
-                TopOrder::Dpu => {
-                    sqlx::query_as!(
-                        TopStats,
-                        r#"SELECT steam_id as "steam_id!: _", games as "games!", ubers as "ubers!", drops as "drops!", medic_time as "medic_time!", name as "name!"
-                        FROM ranked_medic_stats
-                        ORDER BY dpu DESC LIMIT 25"#
-                    )
-                        .fetch_all(&self.database)
-                        .await?
-                }
-                TopOrder::Dpg => {
-                    sqlx::query_as!(
-                        TopStats,
-                        r#"SELECT steam_id as "steam_id!: _", games as "games!", ubers as "ubers!", drops as "drops!", medic_time as "medic_time!", name as "name!"
-                        FROM ranked_medic_stats
-                        ORDER BY dpg DESC LIMIT 25"#
-                    )
-                        .fetch_all(&self.database)
-                        .await?
-                }
-            };
-            Ok(Arc::new(result))
-        }).await?;
+    pub async fn top_stats(&self, query: TopStatsQuery) -> Result<Arc<Vec<TopStats>>, DropsError> {
+        let key = query.window().unwrap_or(query);
+        let result = self
+            .top_cache
+            .try_get_with::<_, sqlx::Error>(
+                key,
+                timed("top_stats", async {
+                    let top = Arc::new(
+                        key.build()
+                            .build_query_as()
+                            .fetch_all(&self.replica)
+                            .await?,
+                    );
+                    self.top_generations
+                        .lock()
+                        .unwrap()
+                        .entry(key)
+                        .or_default()
+                        .push(top.clone());
+                    Ok(top)
+                }),
+            )
+            .await?;
 
-        Ok(result)
+        if key == query {
+            Ok(result)
+        } else {
+            Ok(Arc::new(query.slice(&result).to_vec()))
+        }
+    }
+
+    /// How the ranks on a leaderboard from [`Self::top_stats`] moved since the board before it
+    ///
+    /// Empty if there is no earlier board to compare with yet, like right after startup.
+    pub fn rank_movements(
+        &self,
+        query: &TopStatsQuery,
+        top: &Arc<Vec<TopStats>>,
+    ) -> Vec<RankMovement> {
+        let key = query.window().unwrap_or(*query);
+        let generations = self.top_generations.lock().unwrap();
+        match generations.get(&key) {
+            Some(TopGenerations {
+                current: Some(current),
+                previous: Some(previous),
+            }) if same_medics(query.slice(current), top) => {
+                // compared over the whole window, so the ranks are the same as on the first page
+                let movements = rank_movements(previous, current);
+                query.slice(&movements).to_vec()
+            }
+            _ => Vec::new(),
+        }
     }
 
+    /// How the ranked medics are spread over the range of a stat
+    ///
+    /// The range between the lowest and highest value is split into `buckets` buckets of equal
+    /// width, the highest value is counted in the last bucket. Drops per hour are reported per
+    /// hour instead of per second like they're stored.
     #[instrument(skip(self))]
-    pub async fn global_stats(&self) -> Result<GlobalStats, DropsError> {
-        let result = self.global_cache
-            .try_get_with(
-                (),
-                sqlx::query_as!(
-                        GlobalStats,
-                        r#"SELECT drops as "drops!", ubers as "ubers!", games as "games!" FROM global_stats"#
-                    )
-                    .fetch_one(&self.database),
+    pub async fn distribution(
+        &self,
+        metric: TopOrder,
+        buckets: i32,
+    ) -> Result<Arc<Distribution>, DropsError> {
+        // the same medics as on the leaderboard for the stat
+        let query = match metric {
+            TopOrder::Dpu => {
+                DistributionQuery::new(metric, buckets).with_min_ubers(self.min_dpu_ubers)
+            }
+            _ => DistributionQuery::new(metric, buckets),
+        };
+        let result = self
+            .distribution_cache
+            .try_get_with::<_, sqlx::Error>(
+                query,
+                timed("distribution", async {
+                    let rows: Vec<DistributionRow> = query
+                        .build()
+                        .build_query_as()
+                        .fetch_all(&self.replica)
+                        .await?;
+                    Ok(Arc::new(Distribution::from_rows(metric, buckets, &rows)))
+                }),
             )
             .await?;
 
         Ok(result)
     }
 
+    /// The medic featured on the homepage for the day
+    ///
+    /// The player is picked from the top medics by drops, ordered by steam id so the order doesn't
+    /// shift with the ranks. Every day moves one place further through the pool, so the same
+    /// player is only featured two days running if they're the only one in it.
     #[instrument(skip(self))]
-    pub async fn resolve_vanity_url(&self, url: &str) -> Result<Option<SteamId>, DropsError> {
-        if let Ok(row) = sqlx::query!(
-            r#"SELECT steam_id as "steam_id!: SteamId" FROM vanity_urls WHERE url=$1"#,
-            url
-        )
-        .fetch_one(&self.database)
-        .await
-        {
-            Ok(Some(row.steam_id))
-        } else if let Some(steam_id) =
-            steam_resolve_vanity::resolve_vanity_url(url, &self.api_key).await?
-        {
-            sqlx::query!(
-                r#"INSERT INTO vanity_urls(url, steam_id) VALUES($1, $2)"#,
-                url,
-                steam_id.steam3()
+    pub async fn featured_player(&self, date: NaiveDate) -> Result<Option<DropStats>, DropsError> {
+        if self.featured_pool <= 0 {
+            return Ok(None);
+        }
+        let featured = self
+            .featured_cache
+            .try_get_with::<_, sqlx::Error>(
+                date,
+                timed("featured_player", async {
+                    let pool = sqlx::query_scalar!(
+                        r#"SELECT steam_id as "steam_id!: SteamId" FROM (
+                        SELECT steam_id FROM ranked_medic_stats
+                        WHERE steam_id NOT IN (SELECT steam_id FROM deleted_players)
+                        ORDER BY drops_rank LIMIT $1
+                    ) pool
+                    ORDER BY steam_id"#,
+                        self.featured_pool
+                    )
+                    .fetch_all(&self.replica)
+                    .await?;
+                    if pool.is_empty() {
+                        return Ok(None);
+                    }
+                    let day = date.num_days_from_ce() as usize;
+                    Ok(Some(pool[day % pool.len()]))
+                }),
             )
-            .execute(&self.database)
             .await?;
-
-            Ok(Some(SteamId::from(steam_id)))
-        } else {
-            Ok(None)
+        match featured {
+            Some(steam_id) => Ok(Some(self.stats_for_user(steam_id).await?)),
+            None => Ok(None),
         }
     }
 
+    /// The player currently at the given position of a leaderboard, if there are that many
+    /// medics on it
+    ///
+    /// The player is read from the leaderboard itself with the same filters, like
+    /// [`Self::rank_neighbors`].
     #[instrument(skip(self))]
-    pub async fn raw_log(&self, id: u64) -> Result<JsonValue, DropsError> {
-        let result = sqlx::query_as!(
-            RawLog,
-            r#"SELECT json FROM logs_raw WHERE id = $1"#,
-            id as i32
-        )
-        .fetch_optional(&self.database)
-        .await?
-        .ok_or(DropsError::NotFound)?;
-
-        Ok(result.json)
+    pub async fn player_at_rank(
+        &self,
+        order: TopOrder,
+        rank: i64,
+    ) -> Result<Option<RankedStats>, DropsError> {
+        if rank < 1 {
+            return Ok(None);
+        }
+        let query = self.top_query(order).with_offset(rank - 1).with_limit(1);
+        let result = self
+            .rank_cache
+            .try_get_with::<_, sqlx::Error>(
+                (order, rank),
+                timed("player_at_rank", async {
+                    let row: Option<TopStats> = query
+                        .build()
+                        .build_query_as()
+                        .fetch_optional(&self.replica)
+                        .await?;
+                    Ok(row.map(|row| RankedStats::new(row, rank)))
+                }),
+            )
+            .await?;
+        Ok(result)
     }
 
-    #[instrument(skip(self))]
-    pub async fn last_log(&self) -> Result<u64, DropsError> {
-        let result = sqlx::query_as!(
-            RawLogId,
-            r#"SELECT id FROM logs_raw ORDER BY id DESC LIMIT 1"#
-        )
-        .fetch_optional(&self.database)
-        .await?
-        .ok_or(DropsError::NotFound)?;
-
-        Ok(result.id as u64)
+    /// How far a player is from a target rank or drop count on the drops leaderboard
+    ///
+    /// A target rank is reached by getting one drop more than the player currently at that rank,
+    /// the rank for a target drop count is one more than the number of other ranked players with
+    /// more drops.
+    #[instrument(skip(self, stats), fields(steam_id = ?stats.steam_id))]
+    pub async fn player_target(
+        &self,
+        stats: &DropStats,
+        target: Target,
+    ) -> Result<PlayerTarget, DropsError> {
+        let (target_rank, target_drops) = match target {
+            Target::Rank(rank) if stats.drops_rank <= rank => (rank, stats.drops),
+            Target::Rank(rank) => {
+                let threshold = self
+                    .player_at_rank(TopOrder::Drops, rank)
+                    .await?
+                    .ok_or(DropsError::NotFound)?;
+                (rank, stats.drops.max(threshold.drops + 1))
+            }
+            Target::Drops(drops) => {
+                let mut above = self.players_with_more_drops(drops).await?;
+                // the player is on the leaderboard already and shouldn't be counted as ahead of themselves
+                if stats.drops > drops && stats.drops > self.rank_cutoff {
+                    above -= 1;
+                }
+                (above + 1, drops)
+            }
+        };
+        Ok(PlayerTarget {
+            steam_id: stats.steam_id,
+            drops: stats.drops,
+            drops_rank: stats.drops_rank,
+            target_rank,
+            target_drops,
+            drops_needed: (target_drops - stats.drops).max(0),
+        })
     }
-}
-
-#[derive(Debug, Deserialize)]
-pub struct SearchParams {
-    pub search: String,
-}
-
-#[derive(Debug, Serialize)]
-pub struct SearchResult {
-    pub steam_id: SteamId,
-    pub name: String,
-    pub count: i64,
-    pub sim: f64,
-}
 
-impl SearchResult {
-    pub fn weight(&self) -> f64 {
-        self.sim * 5.0 + self.count as f64
+    /// The player's placement on every leaderboard
+    ///
+    /// The ranks come straight from the stats, only the size of every leaderboard to compute the
+    /// percentiles against is looked up.
+    #[instrument(skip(self, stats), fields(steam_id = ?stats.steam_id))]
+    pub async fn player_ranks(&self, stats: &DropStats) -> Result<PlayerRanks, DropsError> {
+        let ranked_players = self.leaderboard_size(TopOrder::Drops).await?;
+        let dpu_players = self.leaderboard_size(TopOrder::Dpu).await?;
+        // players that aren't on a leaderboard still get a rank for where they would be, but no
+        // share of the leaderboard they'd be ahead of
+        let ranked = stats.drops > self.rank_cutoff;
+        let dpu_ranked = ranked && stats.ubers >= self.min_dpu_ubers;
+        let percentile = |on_leaderboard: bool, rank: i64, players: i64| {
+            on_leaderboard.then(|| rank_percentile(rank, players))
+        };
+        Ok(PlayerRanks {
+            steam_id: stats.steam_id,
+            ranked,
+            ranked_players,
+            dpu_players,
+            drops_rank: stats.drops_rank,
+            dpu_rank: stats.dpu_rank,
+            dps_rank: stats.dps_rank,
+            dpg_rank: stats.dpg_rank,
+            drops_percentile: percentile(ranked, stats.drops_rank, ranked_players),
+            dpu_percentile: percentile(dpu_ranked, stats.dpu_rank, dpu_players),
+            dps_percentile: percentile(ranked, stats.dps_rank, ranked_players),
+            dpg_percentile: percentile(ranked, stats.dpg_rank, ranked_players),
+        })
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct DropStats {
-    pub steam_id: SteamId,
-    pub name: SmolStr,
-    pub drops: i64,
-    pub ubers: i64,
-    pub games: i64,
-    pub medic_time: i64,
-    pub drops_rank: i64,
-    pub dpu_rank: i64,
-    pub dps_rank: i64,
-    pub dpg_rank: i64,
-}
+    /// Number of medics on a leaderboard, with the same filters as the leaderboard itself
+    ///
+    /// Medics without a ratio are still on the leaderboard, ranked last, so only the drops per
+    /// uber leaderboard is smaller than the others. The sizes are cached by the minimum number of
+    /// ubers, so the other leaderboards share a single count.
+    async fn leaderboard_size(&self, order: TopOrder) -> Result<i64, DropsError> {
+        let min_ubers = self.top_query(order).min_ubers;
+        let result = self
+            .leaderboard_size_cache
+            .try_get_with::<_, sqlx::Error>(
+                min_ubers,
+                timed("leaderboard_size", async {
+                    let row = sqlx::query!(
+                        r#"SELECT COUNT(*) as "count!" FROM ranked_medic_stats
+                        WHERE drops > $1 AND ubers >= $2 AND steam_id NOT IN (SELECT steam_id FROM deleted_players)"#,
+                        self.rank_cutoff,
+                        min_ubers
+                    )
+                    .fetch_one(&self.replica)
+                    .await?;
+                    Ok(row.count)
+                }),
+            )
+            .await?;
+        Ok(result)
+    }
 
-impl DropStats {
-    pub fn dpm(&self) -> f64 {
-        self.drops as f64 / (self.medic_time as f64 / 3600.0)
+    /// Number of ranked players with more than `drops` drops, deleted players aren't counted like
+    /// they aren't on the leaderboard
+    async fn players_with_more_drops(&self, drops: i64) -> Result<i64, DropsError> {
+        let result = self
+            .drops_threshold_cache
+            .try_get_with::<_, sqlx::Error>(
+                drops,
+                timed("players_with_more_drops", async {
+                    let row = sqlx::query!(
+                        r#"SELECT COUNT(*) as "count!" FROM ranked_medic_stats
+                        WHERE drops > $1 AND steam_id NOT IN (SELECT steam_id FROM deleted_players)"#,
+                        drops
+                    )
+                    .fetch_one(&self.replica)
+                    .await?;
+                    Ok(row.count)
+                }),
+            )
+            .await?;
+        Ok(result)
     }
 
-    pub fn dpu(&self) -> f64 {
-        self.drops as f64 / self.ubers as f64
+    /// The ranked players within `radius` ranks above and below the player
+    ///
+    /// The players are read from the leaderboard itself with the same filters, the ranks are their
+    /// positions on it. Near the top of the leaderboard the window is cut off instead of shifted.
+    #[instrument(skip(self, stats), fields(steam_id = ?stats.steam_id))]
+    pub async fn rank_neighbors(
+        &self,
+        stats: &DropStats,
+        order: TopOrder,
+        radius: i64,
+    ) -> Result<Arc<Vec<RankedStats>>, DropsError> {
+        let rank = stats.rank(order);
+        let first = (rank - radius).max(1);
+        let query = self
+            .top_query(order)
+            .with_offset(first - 1)
+            .with_limit(rank + radius - first + 1);
+        let result = self
+            .neighbor_cache
+            .try_get_with::<_, sqlx::Error>(
+                (stats.steam_id, order, radius),
+                timed("rank_neighbors", async {
+                    let rows: Vec<TopStats> = query
+                        .build()
+                        .build_query_as()
+                        .fetch_all(&self.replica)
+                        .await?;
+                    Ok(Arc::new(
+                        rows.into_iter()
+                            .zip(first..)
+                            .map(|(row, rank)| RankedStats::new(row, rank))
+                            .collect(),
+                    ))
+                }),
+            )
+            .await?;
+        Ok(result)
     }
 
-    pub fn dpg(&self) -> f64 {
-        self.drops as f64 / self.games as f64
+    /// The ranked medics with the play style closest to the player
+    ///
+    /// The drops per uber, drops per hour and drops per game ratios and the number of games played
+    /// are each divided by their standard deviation over all ranked medics, so each weighs the same
+    /// regardless of its scale. The distance between two medics is the sum of the squared
+    /// differences of these normalized stats, a ratio that is missing for either medic adds
+    /// nothing. Ties are broken by steam id so the list is stable. The player themselves and
+    /// deleted players are left out.
+    #[instrument(skip(self))]
+    pub async fn similar_players(
+        &self,
+        steam_id: SteamId,
+    ) -> Result<Arc<Vec<TopStats>>, DropsError> {
+        let result = self
+            .similar_cache
+            .try_get_with::<_, sqlx::Error>(steam_id, timed("similar_players", async {
+                let result = sqlx::query_as!(
+                    TopStats,
+                    r#"WITH player AS (
+                        SELECT dpu, dps, dpg, games FROM medic_stats WHERE steam_id = $1
+                    ), spread AS (
+                        SELECT stddev_pop(dpu) AS dpu, stddev_pop(dps) AS dps, stddev_pop(dpg) AS dpg, stddev_pop(games) AS games FROM ranked_medic_stats
+                    )
+                    SELECT ranked.steam_id as "steam_id!: _", ranked.name as "name!", ranked.games as "games!", ranked.ubers as "ubers!", ranked.drops as "drops!", ranked.medic_time as "medic_time!"
+                    FROM ranked_medic_stats ranked, player, spread
+                    WHERE ranked.steam_id != $1 AND ranked.steam_id NOT IN (SELECT steam_id FROM deleted_players)
+                    ORDER BY
+                        COALESCE(power((ranked.dpu - player.dpu) / NULLIF(spread.dpu, 0), 2), 0)
+                        + COALESCE(power((ranked.dps - player.dps) / NULLIF(spread.dps, 0), 2), 0)
+                        + COALESCE(power((ranked.dpg - player.dpg) / NULLIF(spread.dpg, 0), 2), 0)
+                        + COALESCE(power((ranked.games - player.games) / NULLIF(spread.games, 0), 2), 0),
+                        ranked.steam_id
+                    LIMIT $2"#,
+                    steam_id.steam3(),
+                    SIMILAR_PLAYERS
+                )
+                    .fetch_all(&self.replica)
+                    .await?;
+                Ok(Arc::new(result))
+            }))
+            .await?;
+        Ok(result)
     }
 
-    pub fn steam_id64(&self) -> u64 {
-        self.steam_id.into()
+    /// The all-time records, loaded and cached together for the records page
+    #[instrument(skip(self))]
+    pub async fn records(&self) -> Result<Arc<Records>, DropsError> {
+        let result = self
+            .records_cache
+            .try_get_with::<_, sqlx::Error>((), async {
+                let (
+                    most_drops_single_game,
+                    most_drops,
+                    highest_dpu,
+                    lowest_dpu,
+                    longest_medic_time,
+                ) = tokio::try_join!(
+                    self.most_drops_single_game(),
+                    self.most_drops(),
+                    self.highest_dpu(),
+                    self.lowest_dpu(),
+                    self.longest_medic_time(),
+                )?;
+                Ok(Arc::new(Records {
+                    most_drops_single_game,
+                    most_drops,
+                    highest_dpu,
+                    lowest_dpu,
+                    longest_medic_time,
+                }))
+            })
+            .await?;
+        Ok(result)
     }
-}
 
-#[derive(Debug, Clone)]
+    /// The medic that dropped the most ubers in a single log, and the log they did it in
+    async fn most_drops_single_game(&self) -> Result<Option<Record>, sqlx::Error> {
+        timed(
+            "most_drops_single_game",
+            sqlx::query_as!(
+                Record,
+                r#"SELECT player.key as "steam_id!: _", COALESCE(user_names.name, player.key) as "name!",
+                    (player.value->>'drops')::float8 as "value!", logs_raw.id as "log_id?"
+                FROM logs_raw
+                CROSS JOIN LATERAL jsonb_each(logs_raw.json->'players') AS player
+                LEFT JOIN user_names ON user_names.steam_id = player.key
+                WHERE player.value->'class_stats' @> '[{"type": "medic"}]'
+                AND player.value ? 'drops'
+                AND player.key NOT IN (SELECT steam_id FROM deleted_players)
+                ORDER BY (player.value->>'drops')::int8 DESC, logs_raw.id
+                LIMIT 1"#
+            )
+            .fetch_optional(&self.replica),
+        )
+        .await
+    }
+
+    async fn most_drops(&self) -> Result<Option<Record>, sqlx::Error> {
+        timed(
+            "most_drops",
+            sqlx::query_as!(
+                Record,
+                r#"SELECT steam_id as "steam_id!: _", name as "name!", drops::float8 as "value!", NULL::int4 as "log_id?"
+                FROM ranked_medic_stats
+                WHERE steam_id NOT IN (SELECT steam_id FROM deleted_players)
+                ORDER BY drops DESC, steam_id
+                LIMIT 1"#
+            )
+            .fetch_optional(&self.replica),
+        )
+        .await
+    }
+
+    /// Like the leaderboard, only medics with enough ubers count for the drops per uber records
+    async fn highest_dpu(&self) -> Result<Option<Record>, sqlx::Error> {
+        timed(
+            "highest_dpu",
+            sqlx::query_as!(
+                Record,
+                r#"SELECT steam_id as "steam_id!: _", name as "name!", dpu as "value!", NULL::int4 as "log_id?"
+                FROM ranked_medic_stats
+                WHERE dpu IS NOT NULL AND ubers >= $1
+                AND steam_id NOT IN (SELECT steam_id FROM deleted_players)
+                ORDER BY dpu DESC, steam_id
+                LIMIT 1"#,
+                self.min_dpu_ubers
+            )
+            .fetch_optional(&self.replica),
+        )
+        .await
+    }
+
+    async fn lowest_dpu(&self) -> Result<Option<Record>, sqlx::Error> {
+        timed(
+            "lowest_dpu",
+            sqlx::query_as!(
+                Record,
+                r#"SELECT steam_id as "steam_id!: _", name as "name!", dpu as "value!", NULL::int4 as "log_id?"
+                FROM ranked_medic_stats
+                WHERE dpu IS NOT NULL AND ubers >= $1
+                AND steam_id NOT IN (SELECT steam_id FROM deleted_players)
+                ORDER BY dpu ASC, steam_id
+                LIMIT 1"#,
+                self.min_dpu_ubers
+            )
+            .fetch_optional(&self.replica),
+        )
+        .await
+    }
+
+    async fn longest_medic_time(&self) -> Result<Option<Record>, sqlx::Error> {
+        timed(
+            "longest_medic_time",
+            sqlx::query_as!(
+                Record,
+                r#"SELECT steam_id as "steam_id!: _", name as "name!", medic_time::float8 as "value!", NULL::int4 as "log_id?"
+                FROM ranked_medic_stats
+                WHERE steam_id NOT IN (SELECT steam_id FROM deleted_players)
+                ORDER BY medic_time DESC, steam_id
+                LIMIT 1"#
+            )
+            .fetch_optional(&self.replica),
+        )
+        .await
+    }
+
+    /// Every ranked medic ordered by drops rank, streamed from the database instead of loaded at once
+    ///
+    /// The ranks are counted with the same filters as the leaderboards instead of using the stored
+    /// ranks, medics with too few ubers for the dpu leaderboard have no dpu rank.
+    ///
+    /// The rows are read by a task of its own, a few rows ahead of the consumer, which stops when
+    /// the stream is dropped. Only [`EXPORT_CONCURRENCY`] exports can run at the same time and each
+    /// is cut off after [`EXPORT_TIMEOUT`], in which case the stream ends with
+    /// [`DropsError::ExportIncomplete`]. Deleted players are left out.
+    pub fn ranked_medics(
+        &self,
+    ) -> Result<impl Stream<Item = Result<RankedMedic, DropsError>> + Send + 'static, DropsError>
+    {
+        let permit = self
+            .export_limit
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| DropsError::Overloaded)?;
+        let replica = self.replica.clone();
+        let min_dpu_ubers = self.min_dpu_ubers;
+        let finished = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel(EXPORT_BUFFER);
+
+        let export_finished = finished.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let export = async {
+                let mut rows = sqlx::query_as!(
+                    RankedMedic,
+                    r#"SELECT steam_id as "steam_id!: _", name as "name!", games as "games!", ubers as "ubers!", drops as "drops!", medic_time as "medic_time!",
+                    RANK() OVER (ORDER BY drops DESC) as "drops_rank!",
+                    CASE WHEN ubers >= $1 THEN RANK() OVER (PARTITION BY ubers >= $1 ORDER BY COALESCE(dpu, 0) DESC) END as "dpu_rank",
+                    RANK() OVER (ORDER BY COALESCE(dps, 0) DESC) as "dps_rank!",
+                    RANK() OVER (ORDER BY COALESCE(dpg, 0) DESC) as "dpg_rank!"
+                    FROM ranked_medic_stats
+                    WHERE steam_id NOT IN (SELECT steam_id FROM deleted_players)
+                    ORDER BY "drops_rank!", steam_id"#,
+                    min_dpu_ubers
+                )
+                .fetch(&replica);
+                while let Some(row) = rows.next().await {
+                    let failed = row.is_err();
+                    if sender.send(row.map_err(DropsError::from)).await.is_err() || failed {
+                        return;
+                    }
+                }
+                export_finished.store(true, Ordering::Release);
+            };
+            if timeout(EXPORT_TIMEOUT, export).await.is_err() {
+                warn!("ranked medic export timed out");
+            }
+        });
+
+        // a channel that's closed without the export finishing means it was cut off, which has
+        // to be reported so the client doesn't mistake the partial export for a complete one
+        Ok(stream::unfold(Some(receiver), move |receiver| {
+            let finished = finished.clone();
+            async move {
+                let mut receiver = receiver?;
+                match receiver.recv().await {
+                    Some(row) => Some((row, Some(receiver))),
+                    None if finished.load(Ordering::Acquire) => None,
+                    None => Some((Err(DropsError::ExportIncomplete), None)),
+                }
+            }
+        }))
+    }
+
+    #[instrument(skip(self))]
+    pub async fn global_stats(&self) -> Result<GlobalStats, DropsError> {
+        let database = self.replica.clone();
+        let result = self.global_cache
+            .try_get_with((), move || {
+                let database = database.clone();
+                timed("global_stats", async move {
+                    sqlx::query_as!(
+                        GlobalStats,
+                        r#"SELECT drops as "drops!", ubers as "ubers!", games as "games!" FROM global_stats"#
+                    )
+                    // the totals aren't computed yet on a fresh database
+                    .fetch_optional(&database)
+                    .await
+                    .map(Option::unwrap_or_default)
+                })
+            })
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Totals over only the medics that appear on the leaderboards
+    #[instrument(skip(self))]
+    pub async fn ranked_global_stats(&self) -> Result<GlobalStats, DropsError> {
+        let result = self.ranked_global_cache
+            .try_get_with(
+                (),
+                timed(
+                    "ranked_global_stats",
+                    sqlx::query_as!(
+                        GlobalStats,
+                        r#"SELECT COALESCE(SUM(drops), 0)::int8 as "drops!", COALESCE(SUM(ubers), 0)::int8 as "ubers!", COALESCE(SUM(games), 0)::int8 as "games!" FROM ranked_medic_stats"#
+                    )
+                    .fetch_one(&self.replica),
+                ),
+            )
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Totals of the medics in the logs played from `since` up to and including `until`
+    ///
+    /// Unlike [`Self::global_stats`] this isn't precomputed, the logs of the range are summed on
+    /// every cache miss, which is why the range is limited to [`MAX_STATS_RANGE_DAYS`]. Cache
+    /// misses count towards the rate limit of the client and only a few ranges are summed at the
+    /// same time. Ranges are whole days and end today at the latest, so requests for the same
+    /// days share a cache entry.
+    #[instrument(skip(self))]
+    pub async fn global_stats_range(
+        &self,
+        since: NaiveDate,
+        until: NaiveDate,
+        client: ClientIp,
+    ) -> Result<GlobalStats, DropsError> {
+        validate_stats_range(since, until)?;
+        let until = until.min(Utc::now().date_naive());
+        if since > until {
+            // no logs from the future yet
+            return Ok(GlobalStats::default());
+        }
+        if let Some(stats) = self.range_stats_cache.get(&(since, until)).await {
+            return Ok(stats);
+        }
+        if let Err(e) = self.range_stats_rate_limit.check(client).await {
+            warn!(client = ?client.0, "date range rate limit exceeded");
+            return Err(e);
+        }
+        let _permit = self
+            .range_stats_limit
+            .try_acquire()
+            .map_err(|_| DropsError::Overloaded)?;
+
+        let start = since.and_time(NaiveTime::MIN).and_utc().timestamp() as f64;
+        let end = (until + TimeDelta::days(1))
+            .and_time(NaiveTime::MIN)
+            .and_utc()
+            .timestamp() as f64;
+        let result = self
+            .range_stats_cache
+            .try_get_with(
+                (since, until),
+                timed(
+                    "global_stats_range",
+                    sqlx::query_as!(
+                        GlobalStats,
+                        r#"SELECT COALESCE(SUM((player.value->>'drops')::int8), 0)::int8 AS "drops!",
+                            COALESCE(SUM((player.value->>'ubers')::int8), 0)::int8 AS "ubers!",
+                            COUNT(*) AS "games!"
+                        FROM logs_raw, jsonb_each(logs_raw.json->'players') AS player
+                        WHERE (json->'info'->>'date')::float8 >= $1 AND (json->'info'->>'date')::float8 < $2
+                        AND player.value->'class_stats' @> '[{"type": "medic"}]'"#,
+                        start,
+                        end
+                    )
+                    .fetch_one(&self.replica),
+                ),
+            )
+            .await?;
+
+        Ok(result)
+    }
+
+    /// The logs both players played in with at least one of them as medic, newest first
+    ///
+    /// Only the newest [`MAX_SHARED_LOGS`] logs are returned, the players are in the order they
+    /// were passed in.
+    #[instrument(skip(self))]
+    pub async fn shared_logs(
+        &self,
+        a: SteamId,
+        b: SteamId,
+    ) -> Result<Arc<Vec<SharedLog>>, DropsError> {
+        let result = self
+            .shared_logs_cache
+            .try_get_with::<_, sqlx::Error>((a, b), async {
+                let rows = timed(
+                    "shared_logs",
+                    sqlx::query!(
+                        r#"SELECT id AS "log_id!", to_timestamp((json->'info'->>'date')::float8) AS "date!",
+                            COALESCE(json->'info'->>'map', '') AS "map!",
+                            COALESCE(json->'players'->$1->'class_stats' @> '[{"type": "medic"}]', false) AS "a_medic!",
+                            COALESCE((json->'players'->$1->>'drops')::int8, 0) AS "a_drops!",
+                            COALESCE((json->'players'->$1->>'ubers')::int8, 0) AS "a_ubers!",
+                            COALESCE(json->'players'->$2->'class_stats' @> '[{"type": "medic"}]', false) AS "b_medic!",
+                            COALESCE((json->'players'->$2->>'drops')::int8, 0) AS "b_drops!",
+                            COALESCE((json->'players'->$2->>'ubers')::int8, 0) AS "b_ubers!"
+                        FROM logs_raw
+                        WHERE json->'players' ?& ARRAY[$1, $2]
+                        AND (json->'players'->$1->'class_stats' @> '[{"type": "medic"}]'
+                            OR json->'players'->$2->'class_stats' @> '[{"type": "medic"}]')
+                        ORDER BY id DESC
+                        LIMIT $3"#,
+                        a.steam3(),
+                        b.steam3(),
+                        MAX_SHARED_LOGS
+                    )
+                    .fetch_all(&self.replica),
+                )
+                .await?;
+                Ok(Arc::new(
+                    rows.into_iter()
+                        .map(|row| SharedLog {
+                            log_id: row.log_id,
+                            date: row.date,
+                            map: row.map,
+                            a: SharedLogPlayer {
+                                medic: row.a_medic,
+                                drops: row.a_drops,
+                                ubers: row.a_ubers,
+                            },
+                            b: SharedLogPlayer {
+                                medic: row.b_medic,
+                                drops: row.b_drops,
+                                ubers: row.b_ubers,
+                            },
+                        })
+                        .collect(),
+                ))
+            })
+            .await?;
+        Ok(result)
+    }
+
+    /// Whether the player has been flagged as deleted, see `sql/deleted_players.sql`
+    #[instrument(skip(self))]
+    pub async fn is_deleted(&self, steam_id: SteamId) -> Result<bool, DropsError> {
+        let result = self
+            .deleted_cache
+            .try_get_with(steam_id, async {
+                sqlx::query!(
+                    r#"SELECT EXISTS(SELECT 1 FROM deleted_players WHERE steam_id=$1) AS "deleted!""#,
+                    steam_id.steam3()
+                )
+                .fetch_one(&self.replica)
+                .await
+                .map(|row| row.deleted)
+            })
+            .await?;
+        Ok(result)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn set_deleted(&self, steam_id: SteamId, deleted: bool) -> Result<(), DropsError> {
+        self.maintenance.ensure_writable()?;
+        if deleted {
+            sqlx::query!(
+                r#"INSERT INTO deleted_players(steam_id) VALUES($1) ON CONFLICT DO NOTHING"#,
+                steam_id.steam3()
+            )
+            .execute(&self.database)
+            .await?;
+        } else {
+            sqlx::query!(
+                r#"DELETE FROM deleted_players WHERE steam_id=$1"#,
+                steam_id.steam3()
+            )
+            .execute(&self.database)
+            .await?;
+        }
+        self.deleted_cache.insert(steam_id, deleted).await;
+        Ok(())
+    }
+
+    /// Store the cached player stats, so they can be loaded again after a restart
+    ///
+    /// Returns the number of players stored.
+    #[instrument(skip(self))]
+    pub fn save_player_cache(&self, path: &Path) -> Result<usize, SnapshotError> {
+        let now = Utc::now();
+        let entries: Vec<_> = self
+            .player_cache
+            .entries()
+            .into_iter()
+            .map(|(_, stats, age)| SnapshotEntry {
+                cached_at: now - TimeDelta::from_std(age).unwrap_or_default(),
+                stats,
+            })
+            .collect();
+        let count = entries.len();
+        PlayerCacheSnapshot { entries }.write(path)?;
+        Ok(count)
+    }
+
+    /// Fill the player cache from a snapshot made by [`DataSource::save_player_cache`]
+    ///
+    /// Entries older than the ttl of the cache are skipped, the others keep their age so they're
+    /// refreshed as if the process never restarted. Returns the number of players loaded.
+    #[instrument(skip(self))]
+    pub async fn load_player_cache(&self, path: &Path) -> Result<usize, SnapshotError> {
+        let snapshot = PlayerCacheSnapshot::read(path)?;
+        let ttl = self
+            .player_cache
+            .inner()
+            .policy()
+            .time_to_live()
+            .unwrap_or(Duration::MAX);
+        let now = Utc::now();
+        let mut loaded = 0;
+        for entry in snapshot.entries {
+            // a clock that went backwards makes the entry look newer than it is, treat it as fresh
+            let age = (now - entry.cached_at).to_std().unwrap_or_default();
+            if age < ttl {
+                self.player_cache
+                    .insert_aged(entry.stats.steam_id, entry.stats, age)
+                    .await;
+                loaded += 1;
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// Load the leaderboards and global stats so the first visitors don't have to wait for them
+    #[instrument(skip(self))]
+    pub async fn warm_cache(&self) -> Result<(), DropsError> {
+        self.global_stats().await?;
+        self.ranked_global_stats().await?;
+        for order in TopOrder::ALL {
+            self.top_stats(self.top_query(order)).await?;
+        }
+        Ok(())
+    }
+
+    /// The names the player played under, most used first
+    #[instrument(skip(self))]
+    pub async fn aliases_for_user(&self, steam_id: SteamId) -> Result<Arc<Vec<Alias>>, DropsError> {
+        let result = self
+            .alias_cache
+            .try_get_with::<_, sqlx::Error>(
+                steam_id,
+                timed("aliases_for_user", async {
+                    let result = sqlx::query_as!(
+                        Alias,
+                        r#"SELECT name as "name!", count as "count!"
+                    FROM medic_names
+                    WHERE steam_id=$1
+                    ORDER BY count DESC
+                    LIMIT 100"#,
+                        steam_id.steam3()
+                    )
+                    .fetch_all(&self.replica)
+                    .await?;
+                    Ok(Arc::new(result))
+                }),
+            )
+            .await?;
+        Ok(result)
+    }
+
+    /// The vanity urls we've resolved to the player
+    ///
+    /// Steam lets a vanity url be claimed by someone else after it's released, this shows which
+    /// of the stored urls still point to the old owner.
+    #[instrument(skip(self))]
+    pub async fn vanities_for_user(&self, steam_id: SteamId) -> Result<Vec<String>, DropsError> {
+        Ok(timed("vanities_for_user", self.fetch_vanities(steam_id)).await?)
+    }
+
+    async fn fetch_vanities(&self, steam_id: SteamId) -> Result<Vec<String>, sqlx::Error> {
+        let vanity_urls = sqlx::query!(
+            r#"SELECT url as "url!" FROM vanity_urls WHERE steam_id=$1 ORDER BY url"#,
+            steam_id.steam3()
+        )
+        .fetch_all(&self.replica)
+        .await?
+        .into_iter()
+        .map(|row| row.url)
+        .collect();
+        Ok(vanity_urls)
+    }
+
+    /// Everything known about a player, for downloading their data
+    ///
+    /// Only the newest [`MAX_EXPORT_LOGS`] medic logs are included, and the maps are counted from
+    /// the newest [`MAX_EXPORT_MAP_LOGS`].
+    #[instrument(skip(self, stats), fields(steam_id = ?stats.steam_id))]
+    pub async fn player_export(&self, stats: DropStats) -> Result<Arc<PlayerExport>, DropsError> {
+        let steam_id = stats.steam_id;
+        let aliases = self.aliases_for_user(steam_id).await?;
+        let result = self
+            .export_cache
+            .try_get_with::<_, sqlx::Error>(
+                steam_id,
+                timed("player_export", async {
+                    let vanity_urls = self.fetch_vanities(steam_id).await?;
+                    let recent_logs = sqlx::query_as!(
+                        LogRow,
+                        r#"SELECT id AS "log_id!", to_timestamp((json->'info'->>'date')::float8) AS "date!",
+                            COALESCE(json->'info'->>'map', '') AS "map!",
+                            $1 AS "steam_id!: _", $2::text AS "name!",
+                            COALESCE((json->'players'->$1->>'drops')::int8, 0) AS "drops!",
+                            COALESCE((json->'players'->$1->>'ubers')::int8, 0) AS "ubers!"
+                        FROM logs_raw
+                        WHERE json->'players' ? $1
+                        AND json->'players'->$1->'class_stats' @> '[{"type": "medic"}]'
+                        ORDER BY id DESC
+                        LIMIT $3"#,
+                        steam_id.steam3(),
+                        stats.name.as_str(),
+                        MAX_EXPORT_LOGS
+                    )
+                    .fetch_all(&self.replica)
+                    .await?;
+                    let maps = sqlx::query_as!(
+                        MapStats,
+                        r#"SELECT map AS "map!", COUNT(*) AS "games!", SUM(drops)::int8 AS "drops!", SUM(ubers)::int8 AS "ubers!"
+                        FROM (
+                            SELECT COALESCE(json->'info'->>'map', '') AS map,
+                                COALESCE((json->'players'->$1->>'drops')::int8, 0) AS drops,
+                                COALESCE((json->'players'->$1->>'ubers')::int8, 0) AS ubers
+                            FROM logs_raw
+                            WHERE json->'players' ? $1
+                            AND json->'players'->$1->'class_stats' @> '[{"type": "medic"}]'
+                            ORDER BY id DESC
+                            LIMIT $2
+                        ) AS logs
+                        GROUP BY map
+                        ORDER BY COUNT(*) DESC, map"#,
+                        steam_id.steam3(),
+                        MAX_EXPORT_MAP_LOGS
+                    )
+                    .fetch_all(&self.replica)
+                    .await?;
+                    Ok(Arc::new(PlayerExport {
+                        stats,
+                        aliases: aliases.as_ref().clone(),
+                        vanity_urls,
+                        recent_logs,
+                        maps,
+                    }))
+                }),
+            )
+            .await?;
+        Ok(result)
+    }
+
+    /// Resolve a vanity url, from the database if it's been resolved before or through steam
+    ///
+    /// Only the lookups that go to the steam api count towards the rate limit of the client, as
+    /// those cost api quota. Stored urls past the max age are checked against steam again, see
+    /// [`DataSource::reverify_vanity_url`].
+    #[instrument(skip(self))]
+    pub async fn resolve_vanity_url(
+        &self,
+        url: &str,
+        client: ClientIp,
+    ) -> Result<Option<SteamId>, DropsError> {
+        if let Ok(row) = sqlx::query!(
+            r#"SELECT steam_id as "steam_id!: SteamId", resolved_at FROM vanity_urls WHERE url=$1"#,
+            url
+        )
+        .fetch_one(&self.replica)
+        .await
+        {
+            if self.is_vanity_stale(row.resolved_at, Utc::now()) {
+                self.reverify_vanity_url(url, row.steam_id, client).await
+            } else {
+                Ok(Some(row.steam_id))
+            }
+        } else if let Err(e) = self.vanity_limit.check(client).await {
+            warn!(client = ?client.0, "vanity url rate limit exceeded");
+            Err(e)
+        } else if let Some(steam_id) = self.fetch_vanity_url(url, VANITY_BACKOFF).await? {
+            // during maintenance the url is resolved again next time instead, when reading from a
+            // replica the url might already have been stored without it showing up yet
+            if !self.maintenance.is_enabled() {
+                sqlx::query!(
+                    r#"INSERT INTO vanity_urls(url, steam_id) VALUES($1, $2) ON CONFLICT DO NOTHING"#,
+                    url,
+                    steam_id.steam3()
+                )
+                .execute(&self.database)
+                .await?;
+            }
+
+            Ok(Some(steam_id))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Ask steam who owns a vanity url, trying again when steam fails or doesn't answer in time
+    ///
+    /// `Ok(None)` is steam saying nobody owns the url, which isn't tried again.
+    async fn fetch_vanity_url(
+        &self,
+        url: &str,
+        backoff: Backoff,
+    ) -> Result<Option<SteamId>, DropsError> {
+        // only failed requests can go better the next time, a rejected api key or a malformed
+        // answer won't
+        let retryable =
+            |e: &steam_resolve_vanity::Error| matches!(e, steam_resolve_vanity::Error::Request(_));
+        match retry(backoff, retryable, || {
+            steam_resolve_vanity::resolve_vanity_url(url, &self.api_key)
+        })
+        .await
+        {
+            Ok(steam_id) => Ok(steam_id.map(SteamId::from)),
+            Err(e) => {
+                warn!(error = %e, url, attempts = backoff.attempts, "failed to resolve vanity url through steam");
+                Err(match e {
+                    RetryError::TimedOut => DropsError::SteamTimeout,
+                    RetryError::Failed(e) => DropsError::from(e),
+                })
+            }
+        }
+    }
+
+    /// Check a stored vanity url that's past its max age against steam again
+    ///
+    /// The url might have been released and claimed by someone else since it was stored, in
+    /// which case the stored player is replaced, or removed if nobody has claimed it. When steam
+    /// can't be asked, because it's down or the client is over its rate limit, the stored player
+    /// is kept.
+    ///
+    /// Steam is only asked once per url at a time, every lookup of the url in the meantime waits
+    /// for that answer. The answer is kept for a few minutes, also when steam failed, so a url
+    /// isn't checked again while the replica catches up or while steam is down.
+    async fn reverify_vanity_url(
+        &self,
+        url: &str,
+        stored: SteamId,
+        client: ClientIp,
+    ) -> Result<Option<SteamId>, DropsError> {
+        if let Some(resolved) = self.vanity_reverify_cache.get(url).await {
+            return Ok(resolved);
+        }
+        if self.vanity_limit.check(client).await.is_err() {
+            return Ok(Some(stored));
+        }
+        let result = self
+            .vanity_reverify_cache
+            .try_get_with::<_, sqlx::Error>(url.to_string(), async {
+                let resolved = match self.fetch_vanity_url(url, VANITY_REVERIFY_BACKOFF).await {
+                    Ok(resolved) => resolved,
+                    Err(e) => {
+                        warn!(error = %e, url, "failed to verify stored vanity url, keeping the stored player");
+                        return Ok(Some(stored));
+                    }
+                };
+                if resolved != Some(stored) {
+                    info!(url, ?stored, ?resolved, "vanity url changed owner");
+                }
+
+                if !self.maintenance.is_enabled() {
+                    match resolved {
+                        Some(steam_id) => {
+                            sqlx::query!(
+                                r#"INSERT INTO vanity_urls(url, steam_id) VALUES($1, $2)
+                                ON CONFLICT (url) DO UPDATE SET steam_id = excluded.steam_id, resolved_at = now()"#,
+                                url,
+                                steam_id.steam3()
+                            )
+                            .execute(&self.database)
+                            .await?;
+                        }
+                        None => {
+                            sqlx::query!(r#"DELETE FROM vanity_urls WHERE url=$1"#, url)
+                                .execute(&self.database)
+                                .await?;
+                        }
+                    }
+                }
+                Ok(resolved)
+            })
+            .await?;
+        Ok(result)
+    }
+
+    /// Fetch the current name and vanity url of a player from steam and store them
+    ///
+    /// The cached stats of the player are loaded again from the primary, so the next visit shows
+    /// the new name even if the replica lags behind, and the cached leaderboards and records are
+    /// dropped. `ranked_medic_stats` is a view computed upstream, so the names on the leaderboards
+    /// and the profiles of ranked medics only catch up on the next refresh of the view.
+    #[instrument(skip(self))]
+    pub async fn refresh_player_name(&self, steam_id: SteamId) -> Result<NameRefresh, DropsError> {
+        self.maintenance.ensure_writable()?;
+        let before = sqlx::query!(
+            r#"SELECT name FROM user_names WHERE steam_id=$1"#,
+            steam_id.steam3()
+        )
+        .fetch_optional(&self.database)
+        .await?
+        .and_then(|row| row.name);
+
+        let response: PlayerSummariesResponse = self
+            .http_client
+            .get(&self.player_summaries_url)
+            .query(&[
+                ("key", self.api_key.as_str()),
+                ("steamids", &steam_id.as_u64().to_string()),
+            ])
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)?
+            .json()
+            .await?;
+        let summary = response
+            .response
+            .players
+            .into_iter()
+            .next()
+            .ok_or(DropsError::UserNotFound)?;
+        let vanity_url = summary.vanity_url().map(String::from);
+
+        let mut tx = self.database.begin().await?;
+        sqlx::query!(
+            r#"INSERT INTO user_names(steam_id, name) VALUES($1, $2)
+            ON CONFLICT (steam_id) DO UPDATE SET name = excluded.name"#,
+            steam_id.steam3(),
+            summary.personaname
+        )
+        .execute(&mut *tx)
+        .await?;
+        if let Some(url) = &vanity_url {
+            // vanity urls can be given up and claimed by someone else
+            sqlx::query!(
+                r#"INSERT INTO vanity_urls(url, steam_id) VALUES($1, $2)
+                ON CONFLICT (url) DO UPDATE SET steam_id = excluded.steam_id, resolved_at = now()"#,
+                url,
+                steam_id.steam3()
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        if let Some(url) = &vanity_url {
+            self.vanity_reverify_cache.invalidate(url).await;
+        }
+
+        match self.fetch_stats_for_user(&self.database, steam_id).await {
+            Ok(stats) => {
+                self.player_cache
+                    .insert_aged(steam_id, stats, Duration::ZERO)
+                    .await
+            }
+            // players that never played medic have no stats to cache
+            Err(_) => self.player_cache.invalidate(&steam_id).await,
+        }
+        self.export_cache.invalidate(&steam_id).await;
+        self.top_cache.invalidate_all();
+        self.neighbor_cache.invalidate_all();
+        self.similar_cache.invalidate_all();
+        self.rank_cache.invalidate_all();
+        self.records_cache.invalidate_all();
+        self.card_cache.invalidate_all();
+
+        Ok(NameRefresh {
+            steam_id,
+            before,
+            after: summary.personaname,
+            vanity_url,
+        })
+    }
+
+    /// Resolve multiple vanity urls at once
+    ///
+    /// Urls that have been resolved before are looked up in a single query, the rest is resolved
+    /// through the steam api a few at a time. Every url resolved through steam counts towards the
+    /// rate limit of the client, the batch is refused as a whole when the client doesn't have
+    /// enough of its limit left for all of them. Stored urls past the max age are checked again
+    /// like in [`DataSource::resolve_vanity_url`].
+    ///
+    /// Failing to resolve a url doesn't fail the others, the urls that did resolve are stored
+    /// so trying again only asks steam for the failed ones.
+    #[instrument(skip(self))]
+    pub async fn resolve_vanity_urls(
+        &self,
+        urls: &[&str],
+        client: ClientIp,
+    ) -> Result<HashMap<String, Result<Option<SteamId>, DropsError>>, DropsError> {
+        let mut unique: Vec<String> = urls.iter().map(|url| url.to_string()).collect();
+        unique.sort_unstable();
+        unique.dedup();
+
+        let now = Utc::now();
+        let (fresh, stale): (Vec<_>, Vec<_>) = sqlx::query!(
+            r#"SELECT url as "url!", steam_id as "steam_id!: SteamId", resolved_at FROM vanity_urls WHERE url = ANY($1)"#,
+            &unique
+        )
+        .fetch_all(&self.replica)
+        .await?
+        .into_iter()
+        .partition(|row| !self.is_vanity_stale(row.resolved_at, now));
+
+        let mut resolved: HashMap<String, Result<Option<SteamId>, DropsError>> = fresh
+            .into_iter()
+            .map(|row| (row.url, Ok(Some(row.steam_id))))
+            .collect();
+        let reverified: Vec<_> = stream::iter(stale)
+            .map(|row| async move {
+                let steam_id = self
+                    .reverify_vanity_url(&row.url, row.steam_id, client)
+                    .await;
+                (row.url, steam_id)
+            })
+            .buffer_unordered(VANITY_CONCURRENCY)
+            .collect()
+            .await;
+        resolved.extend(reverified);
+
+        let remaining: Vec<String> = unique
+            .into_iter()
+            .filter(|url| !resolved.contains_key(url))
+            .collect();
+        if let Err(e) = self.vanity_limit.check_many(client, remaining.len()).await {
+            warn!(client = ?client.0, urls = remaining.len(), "vanity url rate limit exceeded");
+            return Err(e);
+        }
+        let fetched: Vec<_> = stream::iter(remaining)
+            .map(|url| async move {
+                let steam_id = self.fetch_vanity_url(&url, VANITY_BACKOFF).await;
+                (url, steam_id)
+            })
+            .buffer_unordered(VANITY_CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut new_urls = Vec::new();
+        let mut new_ids = Vec::new();
+        for (url, steam_id) in fetched {
+            if let Ok(Some(steam_id)) = steam_id {
+                new_urls.push(url.clone());
+                new_ids.push(steam_id.steam3());
+            }
+            resolved.insert(url, steam_id);
+        }
+
+        if !new_urls.is_empty() && !self.maintenance.is_enabled() {
+            sqlx::query!(
+                r#"INSERT INTO vanity_urls(url, steam_id) SELECT * FROM UNNEST($1::text[], $2::text[]) ON CONFLICT DO NOTHING"#,
+                &new_urls,
+                &new_ids
+            )
+            .execute(&self.database)
+            .await?;
+        }
+
+        Ok(resolved)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn raw_log(&self, id: u64) -> Result<JsonValue, DropsError> {
+        // log ids are stored as `INT`, anything larger can't exist
+        let id = i32::try_from(id).map_err(|_| DropsError::NotFound)?;
+        let result = sqlx::query_as!(RawLog, r#"SELECT json FROM logs_raw WHERE id = $1"#, id)
+            .fetch_optional(&self.replica)
+            .await?
+            .ok_or(DropsError::NotFound)?;
+
+        Ok(result.json)
+    }
+
+    /// The medics of a log with their drops, the medics that asked to be removed are left out
+    ///
+    /// A log without any medics left has an empty list of medics.
+    #[instrument(skip(self))]
+    pub async fn log_detail(&self, id: u64) -> Result<LogDetail, DropsError> {
+        // log ids are stored as `INT`, anything larger can't exist
+        let id = i32::try_from(id).map_err(|_| DropsError::NotFound)?;
+        let rows = timed(
+            "log_detail",
+            sqlx::query_as!(
+                LogRow,
+                r#"SELECT logs_raw.id AS "log_id!", to_timestamp((json->'info'->>'date')::float8) AS "date!",
+                    COALESCE(json->'info'->>'map', '') AS "map!",
+                    player.key AS "steam_id!: _", COALESCE(user_names.name, player.key) AS "name!",
+                    COALESCE((player.value->>'drops')::int8, 0) AS "drops!",
+                    COALESCE((player.value->>'ubers')::int8, 0) AS "ubers!"
+                FROM logs_raw
+                CROSS JOIN LATERAL jsonb_each(logs_raw.json->'players') AS player
+                LEFT JOIN user_names ON user_names.steam_id = player.key
+                WHERE logs_raw.id = $1
+                AND player.value->'class_stats' @> '[{"type": "medic"}]'
+                AND player.key NOT IN (SELECT steam_id FROM deleted_players)
+                ORDER BY (player.value->>'drops')::int8 DESC NULLS LAST, player.key"#,
+                id
+            )
+            .fetch_all(&self.replica),
+        )
+        .await?;
+
+        if let Some(detail) = LogDetail::from_rows(rows) {
+            return Ok(detail);
+        }
+        let log = sqlx::query!(
+            r#"SELECT id, to_timestamp((json->'info'->>'date')::float8) AS "date!",
+                COALESCE(json->'info'->>'map', '') AS "map!"
+            FROM logs_raw
+            WHERE id = $1"#,
+            id
+        )
+        .fetch_optional(&self.replica)
+        .await?
+        .ok_or(DropsError::NotFound)?;
+
+        Ok(LogDetail {
+            log_id: log.id,
+            date: log.date,
+            map: log.map,
+            medics: Vec::new(),
+        })
+    }
+
+    #[instrument(skip(self))]
+    pub async fn last_log(&self) -> Result<u64, DropsError> {
+        let result = sqlx::query_as!(
+            RawLogId,
+            r#"SELECT id FROM logs_raw ORDER BY id DESC LIMIT 1"#
+        )
+        .fetch_optional(&self.replica)
+        .await?
+        .ok_or(DropsError::NotFound)?;
+
+        Ok(result.id as u64)
+    }
+}
+
+/// Normalize a search query so copy-pasted names match what's stored
+///
+/// Applies NFKC normalization, to fold compatibility characters like fullwidth letters, trims the
+/// query and collapses internal runs of whitespace into a single space.
+fn normalize_search(search: &str) -> String {
+    search
+        .nfkc()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Why the stats of a player couldn't be loaded, shared between the requests waiting on the cache
+#[derive(Debug, Error)]
+enum StatsError {
+    #[error(transparent)]
+    Database(Arc<sqlx::Error>),
+    #[error("too many ranks are already being recomputed")]
+    Overloaded,
+}
+
+impl From<sqlx::Error> for StatsError {
+    fn from(error: sqlx::Error) -> Self {
+        StatsError::Database(Arc::new(error))
+    }
+}
+
+/// A search cancelled by its statement timeout means the database is too busy to answer it in time
+fn search_error(error: sqlx::Error) -> DropsError {
+    match &error {
+        sqlx::Error::Database(e) if e.code().as_deref() == Some("57014") => DropsError::Overloaded,
+        _ => error.into(),
+    }
+}
+
+/// Escape the wildcards of a `LIKE` pattern
+fn escape_like(search: &str) -> String {
+    search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Record how long a database query took in the `db_query_duration_seconds` histogram
+async fn timed<F: Future>(query: &'static str, fut: F) -> F::Output {
+    let start = Instant::now();
+    let result = fut.await;
+    histogram!("db_query_duration_seconds", "query" => query).record(start.elapsed().as_secs_f64());
+    result
+}
+
+const STEAM_PLAYER_SUMMARIES_URL: &str =
+    "https://api.steampowered.com/ISteamUser/GetPlayerSummaries/v0002/";
+
+#[derive(Debug, Deserialize)]
+struct PlayerSummariesResponse {
+    response: PlayerSummaries,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlayerSummaries {
+    players: Vec<PlayerSummary>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlayerSummary {
+    personaname: String,
+    profileurl: String,
+}
+
+impl PlayerSummary {
+    /// The vanity part of a `https://steamcommunity.com/id/<vanity>/` profile url
+    fn vanity_url(&self) -> Option<&str> {
+        self.profileurl
+            .split_once("/id/")
+            .map(|(_, vanity)| vanity.trim_end_matches('/'))
+            .filter(|vanity| !vanity.is_empty())
+    }
+}
+
+/// The name of a player before and after refreshing it from steam
+#[derive(Debug, Serialize)]
+pub struct NameRefresh {
+    pub steam_id: SteamId,
+    /// `None` if the player wasn't known yet
+    pub before: Option<String>,
+    pub after: String,
+    pub vanity_url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DataSourceSettings {
+    pub min_dpu_ubers: i64,
+    pub rank_cutoff: i64,
+    /// Vanity urls resolved through steam per client per minute
+    pub vanity_rate_limit: u32,
+    /// Age after which stored vanity urls are checked against steam again, 0 if they never are
+    pub vanity_max_age_secs: i64,
+    pub featured_pool: i64,
+    pub search_min_drops: i64,
+    pub trigram_available: bool,
+    /// Number of rank recomputes that can be started right now
+    pub available_recomputes: usize,
+    pub database: PoolSettings,
+    /// The same as `database` if there is no replica
+    pub replica: PoolSettings,
+    pub caches: BTreeMap<&'static str, CacheSettings>,
+}
+
+/// The health of the data source, for `/admin/diagnostics`
+#[derive(Debug, Serialize)]
+pub struct DataSourceDiagnostics {
+    pub database: Check,
+    /// Probed separately even if there is no replica, in which case it's the same as `database`
+    pub replica: Check,
+    pub steam: Check,
+    pub caches: BTreeMap<&'static str, CacheHealth>,
+    /// Cache entries being recomputed in the background right now
+    pub refreshing: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheHealth {
+    pub entries: u64,
+    /// Share of lookups answered from the cache, only tracked for the caches refreshed in the
+    /// background
+    pub hit_ratio: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PoolSettings {
+    pub max_connections: u32,
+    pub connections: u32,
+    pub idle_connections: usize,
+}
+
+impl PoolSettings {
+    fn new(pool: &PgPool) -> Self {
+        PoolSettings {
+            max_connections: pool.options().get_max_connections(),
+            connections: pool.size(),
+            idle_connections: pool.num_idle(),
+        }
+    }
+}
+
+/// Durations are in seconds
+#[derive(Debug, Serialize)]
+pub struct CacheSettings {
+    pub time_to_live: Option<u64>,
+    pub time_to_idle: Option<u64>,
+    pub max_capacity: Option<u64>,
+    pub entries: u64,
+}
+
+impl CacheSettings {
+    fn new<K, V>(cache: &Cache<K, V>) -> Self
+    where
+        K: Hash + Eq + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        let policy = cache.policy();
+        CacheSettings {
+            time_to_live: policy.time_to_live().map(|ttl| ttl.as_secs()),
+            time_to_idle: policy.time_to_idle().map(|tti| tti.as_secs()),
+            max_capacity: policy.max_capacity(),
+            entries: cache.entry_count(),
+        }
+    }
+}
+
+/// Maximum number of players returned by a single search
+pub const MAX_SEARCH_LIMIT: usize = 50;
+/// Paging deeper into the results than this isn't useful and only makes the query slower
+pub const MAX_SEARCH_OFFSET: usize = 500;
+/// Number of names fetched from the database for every search, to be ranked and de-duplicated
+const SEARCH_CANDIDATES: usize = MAX_SEARCH_OFFSET + MAX_SEARCH_LIMIT;
+/// Longest search term in characters, after normalization
+///
+/// The term is matched as a regular expression, so the length is capped to keep the cost of a
+/// single pattern bounded.
+pub const MAX_SEARCH_LENGTH: usize = 64;
+/// Longest the database spends on a single name search before it's cancelled
+const SEARCH_STATEMENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Unknown parameters are rejected so typos don't silently fall back to the defaults
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SearchParams {
+    pub search: String,
+    #[serde(default)]
+    pub rank: SearchRanking,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// How search results are ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchRanking {
+    /// Blend the similarity of the name with how often the player used it, for autocomplete
+    #[default]
+    Popular,
+    /// The closest names first, how often the name is used only breaks ties
+    Precise,
+}
+
+impl SearchParams {
+    pub fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(MAX_SEARCH_LIMIT)
+            .clamp(1, MAX_SEARCH_LIMIT)
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or_default().min(MAX_SEARCH_OFFSET)
+    }
+}
+
+/// Fields are serialized in the order they're declared, api consumers can rely on it
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub steam_id: SteamId,
+    pub name: String,
+    pub count: i64,
+    pub sim: f64,
+}
+
+/// Weight of the name similarity, between 0 and 1, for popular searches
+const POPULAR_SIMILARITY_WEIGHT: f64 = 5.0;
+/// Weight of the number of logs the player used the name in for popular searches
+const POPULAR_COUNT_WEIGHT: f64 = 1.0;
+
+impl SearchResult {
+    /// Relevance of the result for popular searches
+    pub fn weight(&self) -> f64 {
+        self.sim * POPULAR_SIMILARITY_WEIGHT + count_to_f64(self.count) * POPULAR_COUNT_WEIGHT
+    }
+
+    /// Order results with the most relevant first
+    ///
+    /// This is a total order, so the results come out the same no matter what order the database
+    /// returned the rows in:
+    ///
+    /// - popular searches order by [`Self::weight`], precise searches skip this step
+    /// - then by the similarity of the name, highest first
+    /// - then by the number of logs with the name, highest first
+    /// - then by steam id and name, lowest first
+    pub fn cmp_relevance(&self, other: &Self, ranking: SearchRanking) -> std::cmp::Ordering {
+        let by_weight = match ranking {
+            SearchRanking::Popular => other.weight().total_cmp(&self.weight()),
+            SearchRanking::Precise => std::cmp::Ordering::Equal,
+        };
+        by_weight
+            .then(other.sim.total_cmp(&self.sim))
+            .then(other.count.cmp(&self.count))
+            .then(self.steam_id.as_u64().cmp(&other.steam_id.as_u64()))
+            .then_with(|| self.name.cmp(&other.name))
+    }
+}
+
+/// Order search results by relevance and keep the most relevant name of every player
+///
+/// Players can match with more than one name, only their first result is kept. A single result
+/// is returned as is, without sorting or de-duplicating.
+pub fn rank_results(
+    mut players: Vec<SearchResult>,
+    ranking: SearchRanking,
+    limit: usize,
+    offset: usize,
+) -> Vec<SearchResult> {
+    if players.len() <= 1 {
+        return players.into_iter().skip(offset).take(limit).collect();
+    }
+    players.sort_by(|a, b| a.cmp_relevance(b, ranking));
+
+    // the filter stops being called once the page is filled
+    let mut found = HashSet::with_capacity(players.len().min(offset + limit));
+    players
+        .into_iter()
+        .filter(|player| found.insert(player.steam_id))
+        .skip(offset)
+        .take(limit)
+        .collect()
+}
+
+/// More medic time than anyone could reasonably have played, 10 years
+const MAX_PLAUSIBLE_MEDIC_TIME: i64 = 10 * 365 * 24 * 3600;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DropStats {
+    pub steam_id: SteamId,
+    pub name: SmolStr,
+    pub drops: i64,
+    pub ubers: i64,
+    pub games: i64,
+    pub medic_time: i64,
+    pub drops_rank: i64,
+    pub dpu_rank: i64,
+    pub dps_rank: i64,
+    pub dpg_rank: i64,
+    /// Date of the most recent log the player appears in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_active: Option<DateTime<Utc>>,
+}
+
+impl DropStats {
+    /// Names of all fields in the json of the stats
+    pub const FIELDS: &'static [&'static str] = &[
+        "steam_id",
+        "name",
+        "drops",
+        "ubers",
+        "games",
+        "medic_time",
+        "drops_rank",
+        "dpu_rank",
+        "dps_rank",
+        "dpg_rank",
+        "last_active",
+    ];
+
+    pub fn rank(&self, order: TopOrder) -> i64 {
+        match order {
+            TopOrder::Drops => self.drops_rank,
+            TopOrder::Dps => self.dps_rank,
+            TopOrder::Dpg => self.dpg_rank,
+            TopOrder::Dpu => self.dpu_rank,
+        }
+    }
+
+    /// Drops per hour played as medic, the stat of the `dps` leaderboard
+    pub fn dph(&self) -> f64 {
+        per_hour(self.drops, self.medic_time)
+    }
+
+    /// Drops per 30 minutes played as medic
+    pub fn drops_per_30(&self) -> f64 {
+        RateInterval::HalfHour.rate(self.drops, self.medic_time)
+    }
+
+    #[deprecated(note = "this is per hour, not per minute, use `dph`")]
+    pub fn dpm(&self) -> f64 {
+        self.dph()
+    }
+
+    pub fn dpu(&self) -> f64 {
+        ratio(self.drops, self.ubers)
+    }
+
+    pub fn dpg(&self) -> f64 {
+        ratio(self.drops, self.games)
+    }
+
+    /// Drops per hour with the given number of decimals, ties round to even like `{:.2}` does
+    ///
+    /// Use [`format_ratio`] to round ties up instead.
+    pub fn dph_with_precision(&self, precision: usize) -> String {
+        format_ratio(self.dph(), precision, Rounding::HalfEven)
+    }
+
+    #[deprecated(note = "this is per hour, not per minute, use `dph_with_precision`")]
+    pub fn dpm_with_precision(&self, precision: usize) -> String {
+        self.dph_with_precision(precision)
+    }
+
+    /// Drops per uber with the given number of decimals, ties round to even like `{:.2}` does
+    pub fn dpu_with_precision(&self, precision: usize) -> String {
+        format_ratio(self.dpu(), precision, Rounding::HalfEven)
+    }
+
+    /// Drops per game with the given number of decimals, ties round to even like `{:.2}` does
+    pub fn dpg_with_precision(&self, precision: usize) -> String {
+        format_ratio(self.dpg(), precision, Rounding::HalfEven)
+    }
+
+    /// Whether the stats can't be right, which happens when upstream logs contain garbage
+    ///
+    /// The ratios for these players would be misleading, so they aren't shown.
+    pub fn is_implausible(&self) -> bool {
+        self.drops < 0
+            || self.ubers < 0
+            || self.games < 0
+            || self.medic_time < 0
+            || self.medic_time > MAX_PLAUSIBLE_MEDIC_TIME
+            // a drop can only happen while playing medic
+            || (self.drops > 0 && (self.games == 0 || self.medic_time == 0))
+    }
+
+    pub fn steam_id64(&self) -> u64 {
+        self.steam_id.as_u64()
+    }
+
+    /// How long ago the player was last seen in a log, like "3 days ago"
+    pub fn last_active_ago(&self) -> Option<String> {
+        let last_active = self.last_active?;
+        Some(time_ago(Utc::now().signed_duration_since(last_active)))
+    }
+
+    pub fn profile_url(&self, site: &Site) -> String {
+        format!("{}/profile/{}", site.url, self.steam_id)
+    }
+
+    /// schema.org `ProfilePage` structured data for search engines
+    ///
+    /// The result is safe to embed in a `<script>` tag as-is.
+    pub fn json_ld(&self, site: &Site) -> String {
+        let json_ld = json!({
+            "@context": "https://schema.org",
+            "@type": "ProfilePage",
+            "url": self.profile_url(site),
+            "mainEntity": {
+                "@type": "Person",
+                "name": self.name.as_str(),
+                "identifier": self.steam_id.to_string(),
+                "sameAs": format!("https://steamcommunity.com/profiles/{}", self.steam_id),
+                "description": format!(
+                    "{} ubers dropped in {} games, {:.2} drops per game",
+                    self.drops,
+                    self.games,
+                    self.dpg()
+                ),
+            },
+        });
+        // prevent names from closing the script tag
+        json_ld
+            .to_string()
+            .replace('<', "\\u003c")
+            .replace('>', "\\u003e")
+            .replace('&', "\\u0026")
+    }
+}
+
+fn time_ago(elapsed: TimeDelta) -> String {
+    let (count, unit) = if elapsed.num_days() >= 365 {
+        (elapsed.num_days() / 365, "year")
+    } else if elapsed.num_days() >= 30 {
+        (elapsed.num_days() / 30, "month")
+    } else if elapsed.num_days() >= 1 {
+        (elapsed.num_days(), "day")
+    } else if elapsed.num_hours() >= 1 {
+        (elapsed.num_hours(), "hour")
+    } else {
+        return "just now".into();
+    };
+    if count == 1 {
+        format!("1 {} ago", unit)
+    } else {
+        format!("{} {}s ago", count, unit)
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct TopStats {
     pub steam_id: SteamId,
     pub name: String,
@@ -320,48 +2490,683 @@ pub struct TopStats {
 }
 
 impl TopStats {
+    /// The stat the leaderboard is ordered by, formatted like on the leaderboard
+    pub fn value(&self, order: TopOrder) -> String {
+        match order {
+            TopOrder::Drops => self.drops.to_string(),
+            TopOrder::Dps => format!("{:.2}", self.dph()),
+            TopOrder::Dpg => format!("{:.2}", self.dpg()),
+            TopOrder::Dpu => format!("{:.2}", self.dpu()),
+        }
+    }
+
+    /// Drops per hour played as medic, the stat of the `dps` leaderboard
+    pub fn dph(&self) -> f64 {
+        per_hour(self.drops, self.medic_time)
+    }
+
+    /// Drops per 30 minutes played as medic
+    pub fn drops_per_30(&self) -> f64 {
+        RateInterval::HalfHour.rate(self.drops, self.medic_time)
+    }
+
+    #[deprecated(note = "this is per hour, not per minute, use `dph`")]
     pub fn dpm(&self) -> f64 {
-        self.drops as f64 / (self.medic_time as f64 / 3600.0)
+        self.dph()
     }
 
     pub fn dpu(&self) -> f64 {
-        self.drops as f64 / self.ubers as f64
+        ratio(self.drops, self.ubers)
     }
 
     pub fn dpg(&self) -> f64 {
-        self.drops as f64 / self.games as f64
+        ratio(self.drops, self.games)
     }
 
     pub fn steam_id64(&self) -> u64 {
-        self.steam_id.into()
+        self.steam_id.as_u64()
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct GlobalStats {
+/// Number of medics on a leaderboard by default
+pub const DEFAULT_TOP_LIMIT: i64 = 25;
+/// Most medics a leaderboard query returns
+pub const MAX_TOP_LIMIT: i64 = 100;
+/// Number of medics at the top of a leaderboard that are loaded and cached at once
+pub const TOP_WINDOW: i64 = 100;
+
+/// All options for loading a leaderboard, also used as the key of the cached leaderboards
+///
+/// By default the top [`DEFAULT_TOP_LIMIT`] ranked medics are loaded, best first.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct TopStatsQuery {
+    order: TopOrder,
+    ascending: bool,
+    min_ubers: i64,
+    min_games: i64,
+    limit: i64,
+    offset: i64,
+}
+
+impl TopStatsQuery {
+    pub fn new(order: TopOrder) -> Self {
+        TopStatsQuery {
+            order,
+            ascending: false,
+            min_ubers: 0,
+            min_games: 0,
+            limit: DEFAULT_TOP_LIMIT,
+            offset: 0,
+        }
+    }
+
+    pub fn order(&self) -> TopOrder {
+        self.order
+    }
+
+    /// Worst first instead of best first
+    pub fn ascending(mut self) -> Self {
+        self.ascending = true;
+        self
+    }
+
+    pub fn with_min_ubers(mut self, min_ubers: i64) -> Self {
+        self.min_ubers = min_ubers;
+        self
+    }
+
+    pub fn with_min_games(mut self, min_games: i64) -> Self {
+        self.min_games = min_games;
+        self
+    }
+
+    /// Limited to [`MAX_TOP_LIMIT`]
+    pub fn with_limit(mut self, limit: i64) -> Self {
+        self.limit = limit.clamp(1, MAX_TOP_LIMIT);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: i64) -> Self {
+        self.offset = offset.max(0);
+        self
+    }
+
+    pub fn limit(&self) -> i64 {
+        self.limit
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset
+    }
+
+    /// The query for the first [`TOP_WINDOW`] medics with the same options, if this page is
+    /// within them
+    pub fn window(&self) -> Option<TopStatsQuery> {
+        if self.offset + self.limit <= TOP_WINDOW {
+            Some(TopStatsQuery {
+                limit: TOP_WINDOW,
+                offset: 0,
+                ..*self
+            })
+        } else {
+            None
+        }
+    }
+
+    /// The rows of this page out of the rows of its [`Self::window`]
+    pub fn slice<'a, T>(&self, window: &'a [T]) -> &'a [T] {
+        let start = (self.offset as usize).min(window.len());
+        let end = (start + self.limit as usize).min(window.len());
+        &window[start..end]
+    }
+
+    /// The sql for the query, every value is bound as a parameter
+    ///
+    /// The column to order by can't be a parameter, it comes from [`TopOrder::column`] which only
+    /// knows the columns of the leaderboards.
+    pub fn build(&self) -> QueryBuilder<'static, Postgres> {
+        let mut builder = QueryBuilder::new(
+            "SELECT steam_id, games, ubers, drops, medic_time, name FROM ranked_medic_stats WHERE ubers >= ",
+        );
+        builder.push_bind(self.min_ubers);
+        builder.push(" AND games >= ");
+        builder.push_bind(self.min_games);
+        builder.push(" AND steam_id NOT IN (SELECT steam_id FROM deleted_players)");
+        // ties are broken by the steam id, so a page sliced from the window is the same as the
+        // page queried on its own. Medics without ubers, time or games have no ratio, they
+        // count as the worst instead of the best
+        builder.push(format_args!(
+            " ORDER BY {} {}, steam_id LIMIT ",
+            self.order.column(),
+            if self.ascending {
+                "ASC NULLS FIRST"
+            } else {
+                "DESC NULLS LAST"
+            }
+        ));
+        builder.push_bind(self.limit);
+        builder.push(" OFFSET ");
+        builder.push_bind(self.offset);
+        builder
+    }
+}
+
+/// The leaderboard currently cached and the one it replaced
+#[derive(Default)]
+struct TopGenerations {
+    current: Option<Arc<Vec<TopStats>>>,
+    previous: Option<Arc<Vec<TopStats>>>,
+}
+
+impl TopGenerations {
+    fn push(&mut self, top: Arc<Vec<TopStats>>) {
+        self.previous = self.current.replace(top);
+    }
+}
+
+/// How the rank of a player on a leaderboard changed since the previous refresh
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RankMovement {
+    /// Wasn't on the previous leaderboard
+    New,
+    Up(usize),
+    Down(usize),
+    Same,
+}
+
+impl RankMovement {
+    /// Css class for the arrow
+    pub fn class(&self) -> &'static str {
+        match self {
+            RankMovement::New => "new",
+            RankMovement::Up(_) => "up",
+            RankMovement::Down(_) => "down",
+            RankMovement::Same => "same",
+        }
+    }
+}
+
+impl Display for RankMovement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RankMovement::New => f.write_str("new"),
+            RankMovement::Up(places) => write!(f, "▲{}", places),
+            RankMovement::Down(places) => write!(f, "▼{}", places),
+            RankMovement::Same => Ok(()),
+        }
+    }
+}
+
+/// Whether both leaderboards have the same medics in the same order
+fn same_medics(a: &[TopStats], b: &[TopStats]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.steam_id == b.steam_id)
+}
+
+/// The movement of every player on `current` compared to `previous`
+///
+/// Players that dropped off the leaderboard aren't on `current`, so they don't get a movement.
+pub fn rank_movements(previous: &[TopStats], current: &[TopStats]) -> Vec<RankMovement> {
+    let previous_ranks: HashMap<SteamId, usize> = previous
+        .iter()
+        .enumerate()
+        .map(|(rank, medic)| (medic.steam_id, rank))
+        .collect();
+    current
+        .iter()
+        .enumerate()
+        .map(|(rank, medic)| match previous_ranks.get(&medic.steam_id) {
+            None => RankMovement::New,
+            Some(&previous) if previous > rank => RankMovement::Up(previous - rank),
+            Some(&previous) if previous < rank => RankMovement::Down(rank - previous),
+            Some(_) => RankMovement::Same,
+        })
+        .collect()
+}
+
+/// A player on a leaderboard, along with their rank on it
+#[derive(Debug, Clone, Serialize)]
+pub struct RankedStats {
+    pub steam_id: SteamId,
+    pub name: String,
+    pub rank: i64,
     pub drops: i64,
     pub ubers: i64,
     pub games: i64,
+    pub medic_time: i64,
+}
+
+impl RankedStats {
+    pub fn new(stats: TopStats, rank: i64) -> Self {
+        RankedStats {
+            steam_id: stats.steam_id,
+            name: stats.name,
+            rank,
+            drops: stats.drops,
+            ubers: stats.ubers,
+            games: stats.games,
+            medic_time: stats.medic_time,
+        }
+    }
+}
+
+/// A medic holding one of the all-time records
+#[derive(Debug, Clone, Serialize)]
+pub struct Record {
+    pub steam_id: SteamId,
+    pub name: String,
+    pub value: f64,
+    /// The log the record was set in, for the records of a single game
+    pub log_id: Option<i32>,
+}
+
+/// The all-time records, a record is missing when nobody qualifies for it yet
+#[derive(Debug, Clone, Default)]
+pub struct Records {
+    pub most_drops_single_game: Option<Record>,
+    pub most_drops: Option<Record>,
+    pub highest_dpu: Option<Record>,
+    pub lowest_dpu: Option<Record>,
+    /// In seconds
+    pub longest_medic_time: Option<Record>,
+}
+
+/// A ranked medic with the ranks on every leaderboard, for the full leaderboard export
+#[derive(Debug, Clone, Serialize)]
+pub struct RankedMedic {
+    pub steam_id: SteamId,
+    pub name: String,
+    pub games: i64,
+    pub ubers: i64,
+    pub drops: i64,
+    pub medic_time: i64,
+    pub drops_rank: i64,
+    /// Not set for medics with too few ubers to be on the dpu leaderboard
+    pub dpu_rank: Option<i64>,
+    pub dps_rank: i64,
+    pub dpg_rank: i64,
+}
+
+/// What a player is aiming for on the drops leaderboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Rank(i64),
+    Drops(i64),
+}
+
+/// Exactly one of the two has to be set
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetParams {
+    pub rank: Option<i64>,
+    pub drops: Option<i64>,
+}
+
+impl TargetParams {
+    pub fn target(&self) -> Result<Target, DropsError> {
+        match (self.rank, self.drops) {
+            (Some(rank), None) if rank >= 1 => Ok(Target::Rank(rank)),
+            (None, Some(drops)) if drops >= 0 => Ok(Target::Drops(drops)),
+            _ => Err(DropsError::InvalidTarget),
+        }
+    }
+}
+
+/// How far a player is from their target
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerTarget {
+    pub steam_id: SteamId,
+    pub drops: i64,
+    pub drops_rank: i64,
+    /// The rank the player is aiming for, or would have with `target_drops`
+    pub target_rank: i64,
+    /// The drop count the player is aiming for, or needs to reach `target_rank`
+    pub target_drops: i64,
+    /// Drops to go, 0 once the target is reached
+    pub drops_needed: i64,
+}
+
+/// A player's rank on every leaderboard
+///
+/// The percentiles are the share of the medics on each leaderboard the player places at or above,
+/// so the #1 is at 100 and players behind everyone on the leaderboard are at 0. Players that aren't
+/// on a leaderboard have no percentile for it.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerRanks {
+    pub steam_id: SteamId,
+    /// Whether the player has enough drops to show up on the leaderboards themselves
+    pub ranked: bool,
+    /// Number of medics on the drops, dps and dpg leaderboards
+    pub ranked_players: i64,
+    /// Number of medics on the drops per uber leaderboard, which leaves out medics with too few
+    /// ubers
+    pub dpu_players: i64,
+    pub drops_rank: i64,
+    pub dpu_rank: i64,
+    pub dps_rank: i64,
+    pub dpg_rank: i64,
+    pub drops_percentile: Option<f64>,
+    pub dpu_percentile: Option<f64>,
+    pub dps_percentile: Option<f64>,
+    pub dpg_percentile: Option<f64>,
+}
+
+/// Percentage of the `ranked_players` placed at or below `rank`, between 0 and 100
+pub fn rank_percentile(rank: i64, ranked_players: i64) -> f64 {
+    if ranked_players <= 0 {
+        return 0.0;
+    }
+    let at_or_below = (ranked_players - rank + 1).clamp(0, ranked_players);
+    at_or_below as f64 * 100.0 / ranked_players as f64
+}
+
+/// Maximum number of players shown above and below a player
+pub const MAX_NEIGHBOR_RADIUS: i64 = 25;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NeighborParams {
+    #[serde(default)]
+    pub order: TopOrder,
+    pub radius: Option<i64>,
+}
+
+impl NeighborParams {
+    pub fn radius(&self) -> i64 {
+        self.radius.unwrap_or(5).clamp(1, MAX_NEIGHBOR_RADIUS)
+    }
+}
+
+/// Number of buckets in a distribution by default
+pub const DEFAULT_DISTRIBUTION_BUCKETS: i32 = 20;
+/// More buckets than this don't make for a readable histogram
+pub const MAX_DISTRIBUTION_BUCKETS: i32 = 100;
+
+/// Longest date range the global stats can be summed over, in days
+pub const MAX_STATS_RANGE_DAYS: i64 = 366;
+
+/// A date range of logs, `until` is included in the range
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatsRangeParams {
+    pub since: NaiveDate,
+    pub until: NaiveDate,
+}
+
+/// The totals of a date range along with the range they're for
+#[derive(Debug, Clone, Serialize)]
+pub struct RangeStats {
+    pub since: NaiveDate,
+    pub until: NaiveDate,
+    #[serde(flatten)]
+    pub totals: GlobalStats,
+}
+
+fn validate_stats_range(since: NaiveDate, until: NaiveDate) -> Result<(), DropsError> {
+    if since > until {
+        Err(DropsError::InvalidRange("since is after until"))
+    } else if (until - since).num_days() >= MAX_STATS_RANGE_DAYS {
+        Err(DropsError::InvalidRange("the range can be at most a year"))
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DistributionParams {
+    #[serde(default)]
+    pub metric: TopOrder,
+    pub buckets: Option<i32>,
+}
+
+impl DistributionParams {
+    pub fn buckets(&self) -> i32 {
+        self.buckets
+            .unwrap_or(DEFAULT_DISTRIBUTION_BUCKETS)
+            .clamp(1, MAX_DISTRIBUTION_BUCKETS)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Distribution {
+    pub metric: TopOrder,
+    /// Empty if there are no ranked medics
+    pub buckets: Vec<DistributionBucket>,
+}
+
+/// The medics with a value from `from` up to, but not including, `to`
+///
+/// The last bucket includes `to`, so the highest value is counted.
+#[derive(Debug, Serialize)]
+pub struct DistributionBucket {
+    pub from: f64,
+    pub to: f64,
+    pub count: i64,
+}
+
+/// All options for spreading the ranked medics over the range of a stat, also used as the key of
+/// the cached distributions
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct DistributionQuery {
+    metric: TopOrder,
+    buckets: i32,
+    min_ubers: i64,
+}
+
+impl DistributionQuery {
+    pub fn new(metric: TopOrder, buckets: i32) -> Self {
+        DistributionQuery {
+            metric,
+            buckets,
+            min_ubers: 0,
+        }
+    }
+
+    pub fn with_min_ubers(mut self, min_ubers: i64) -> Self {
+        self.min_ubers = min_ubers;
+        self
+    }
+
+    /// The stat as it's reported, drops per hour are stored per second
+    fn value(&self) -> &'static str {
+        match self.metric {
+            TopOrder::Drops => "drops::float8",
+            TopOrder::Dps => "dps * 3600",
+            TopOrder::Dpg => "dpg",
+            TopOrder::Dpu => "dpu",
+        }
+    }
+
+    /// The sql for the query, every value is bound as a parameter
+    ///
+    /// Like with [`TopStatsQuery::build`] the column comes from [`TopOrder::column`]. Medics
+    /// without a value for the stat are left out.
+    pub fn build(&self) -> QueryBuilder<'static, Postgres> {
+        let mut builder = QueryBuilder::new("WITH vals AS (SELECT ");
+        builder.push(format_args!(
+            "{} AS value FROM ranked_medic_stats WHERE {} IS NOT NULL AND ubers >= ",
+            self.value(),
+            self.metric.column()
+        ));
+        builder.push_bind(self.min_ubers);
+        builder.push(" AND steam_id NOT IN (SELECT steam_id FROM deleted_players)), ");
+        builder.push("bounds AS (SELECT MIN(value) AS low, MAX(value) AS high FROM vals) ");
+        builder.push("SELECT low, high, CASE WHEN low = high THEN 1 ELSE LEAST(width_bucket(value, low, high, ");
+        builder.push_bind(self.buckets);
+        builder.push("), ");
+        builder.push_bind(self.buckets);
+        builder.push(") END AS bucket, COUNT(*) AS count FROM vals, bounds GROUP BY 1, 2, 3");
+        builder
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct DistributionRow {
+    low: f64,
+    high: f64,
+    /// 1-based, like `width_bucket` returns it
+    bucket: i32,
+    count: i64,
+}
+
+impl Distribution {
+    /// Fill in the boundaries and the buckets without any medics, which the query leaves out
+    fn from_rows(metric: TopOrder, buckets: i32, rows: &[DistributionRow]) -> Self {
+        let Some(first) = rows.first() else {
+            return Distribution {
+                metric,
+                buckets: Vec::new(),
+            };
+        };
+        // with a single value there is nothing to divide
+        let buckets = if first.low == first.high { 1 } else { buckets };
+        let width = (first.high - first.low) / f64::from(buckets);
+        let buckets = (1..=buckets)
+            .map(|bucket| DistributionBucket {
+                from: first.low + width * f64::from(bucket - 1),
+                to: if bucket == buckets {
+                    first.high
+                } else {
+                    first.low + width * f64::from(bucket)
+                },
+                count: rows
+                    .iter()
+                    .find(|row| row.bucket == bucket)
+                    .map(|row| row.count)
+                    .unwrap_or_default(),
+            })
+            .collect();
+        Distribution { metric, buckets }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlayerExport {
+    pub stats: DropStats,
+    /// The names the player played under, most used first
+    pub aliases: Vec<Alias>,
+    pub vanity_urls: Vec<String>,
+    /// The newest logs the player played medic in, newest first
+    pub recent_logs: Vec<LogRow>,
+    /// The drops of the player on every map they played medic on, most played first
+    pub maps: Vec<MapStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alias {
+    pub name: String,
+    pub count: i64,
 }
 
-#[derive(Debug, Clone)]
-pub struct RawLog {
-    pub json: JsonValue,
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GlobalStats {
+    pub drops: i64,
+    pub ubers: i64,
+    pub games: i64,
 }
 
-#[derive(Debug, Clone)]
-pub struct RawLogId {
-    pub id: i32,
+impl GlobalStats {
+    /// Nothing has been counted yet, like on a fresh database
+    pub fn is_empty(&self) -> bool {
+        self.drops == 0 && self.ubers == 0 && self.games == 0
+    }
 }
 
-#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TopOrder {
+    #[default]
     Drops,
+    #[serde(alias = "dph")]
     Dps,
     Dpg,
     Dpu,
 }
 
+impl TopOrder {
+    pub const ALL: [TopOrder; 4] = [TopOrder::Drops, TopOrder::Dps, TopOrder::Dpg, TopOrder::Dpu];
+
+    /// The column of `ranked_medic_stats` the leaderboard is ordered by
+    pub fn column(&self) -> &'static str {
+        match self {
+            TopOrder::Drops => "drops",
+            TopOrder::Dps => "dps",
+            TopOrder::Dpg => "dpg",
+            TopOrder::Dpu => "dpu",
+        }
+    }
+
+    /// The name of the leaderboard as shown to visitors
+    pub fn title(&self) -> &'static str {
+        match self {
+            TopOrder::Drops => "Drops",
+            TopOrder::Dps => "Drops per hour",
+            TopOrder::Dpg => "Drops per game",
+            TopOrder::Dpu => "Drops per uber",
+        }
+    }
+
+    /// What the stat means, for tooltips and the api
+    pub fn description(&self) -> &'static str {
+        match self {
+            TopOrder::Drops => "Total number of ubers dropped",
+            TopOrder::Dps => "Ubers dropped per hour played as medic",
+            TopOrder::Dpg => "Ubers dropped per game played as medic",
+            TopOrder::Dpu => "Ubers dropped for every uber built",
+        }
+    }
+
+    /// The page the leaderboard is shown on, the drops leaderboard is the homepage
+    pub fn path(&self) -> &'static str {
+        match self {
+            TopOrder::Drops => "/",
+            TopOrder::Dps => "/top/dps",
+            TopOrder::Dpg => "/top/dpg",
+            TopOrder::Dpu => "/top/dpu",
+        }
+    }
+
+    /// Whether a lower value ranks higher, none of the current leaderboards do
+    pub fn lower_is_better(&self) -> bool {
+        false
+    }
+
+    pub fn info(&self) -> OrderInfo {
+        OrderInfo {
+            order: *self,
+            path: self.path(),
+            title: self.title(),
+            description: self.description(),
+            lower_is_better: self.lower_is_better(),
+        }
+    }
+}
+
+/// A leaderboard ordering along with everything a client needs to show it
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderInfo {
+    pub order: TopOrder,
+    pub path: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    pub lower_is_better: bool,
+}
+
+/// Accepts the same names as deserializing, including `dph` for drops per hour
+impl FromStr for TopOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drops" => Ok(TopOrder::Drops),
+            "dps" | "dph" => Ok(TopOrder::Dps),
+            "dpg" => Ok(TopOrder::Dpg),
+            "dpu" => Ok(TopOrder::Dpu),
+            _ => Err(format!("{s} is not one of drops, dps, dpg or dpu")),
+        }
+    }
+}
+
 impl Display for TopOrder {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
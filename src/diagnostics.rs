@@ -0,0 +1,87 @@
+use crate::{DataSource, DataSourceDiagnostics, InFlight, Readiness};
+use axum::{Extension, Json};
+use serde::Serialize;
+use std::fmt::Display;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+use tracing::instrument;
+
+/// Longest a single probe can take before the subsystem counts as unreachable
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The outcome of probing a subsystem the site depends on
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Check {
+    /// Time `probe`, any answer counts as reachable and only errors or timeouts count as failed
+    pub async fn run<T, E, F>(probe: F) -> Check
+    where
+        E: Display,
+        F: Future<Output = Result<T, E>>,
+    {
+        let start = Instant::now();
+        let error = match timeout(CHECK_TIMEOUT, probe).await {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(_) => Some(format!("timed out after {}s", CHECK_TIMEOUT.as_secs())),
+        };
+        Check {
+            ok: error.is_none(),
+            latency_ms: start.elapsed().as_millis() as u64,
+            error,
+        }
+    }
+}
+
+/// What `/admin/diagnostics` reports, for seeing what's degraded during an incident
+#[derive(Debug, Serialize)]
+pub struct Diagnostics {
+    /// Names of the failed checks, empty when everything is fine
+    pub degraded: Vec<&'static str>,
+    /// Whether the caches were warmed after startup
+    pub ready: bool,
+    pub maintenance: bool,
+    pub in_flight_requests: i64,
+    #[serde(flatten)]
+    pub data_source: DataSourceDiagnostics,
+}
+
+/// Probe the databases and steam and summarize the state of the caches
+///
+/// Unlike `/health` this does the actual work of reaching every dependency, so it's only
+/// available to admins.
+#[instrument(skip_all)]
+pub async fn admin_diagnostics(
+    Extension(data_source): Extension<DataSource>,
+    Extension(readiness): Extension<Readiness>,
+    Extension(in_flight): Extension<InFlight>,
+) -> Json<Diagnostics> {
+    let maintenance = data_source.maintenance().is_enabled();
+    let data_source = data_source.diagnostics().await;
+    let ready = readiness.is_ready();
+    let mut degraded = Vec::new();
+    for (name, ok) in [
+        ("database", data_source.database.ok),
+        ("replica", data_source.replica.ok),
+        ("steam", data_source.steam.ok),
+        ("ready", ready),
+    ] {
+        if !ok {
+            degraded.push(name);
+        }
+    }
+    Json(Diagnostics {
+        degraded,
+        ready,
+        maintenance,
+        in_flight_requests: in_flight.count(),
+        data_source,
+    })
+}
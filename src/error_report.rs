@@ -0,0 +1,146 @@
+use axum::body::Body;
+use axum::extract::{MatchedPath, State};
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use metrics::counter;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tower_http::request_id::RequestId;
+use tracing::warn;
+
+/// Reports that can be on their way to the webhook at the same time, more are dropped
+///
+/// When the database goes down every request fails, this keeps that from piling up requests to
+/// the webhook as well.
+const MAX_REPORTS_IN_FLIGHT: usize = 16;
+/// Longest a report can take to deliver
+const REPORT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest error message that is reported, in characters
+const MAX_MESSAGE_LENGTH: usize = 1000;
+
+/// Sends the unexpected server errors to a webhook, for error tracking outside of the logs
+///
+/// Only responses with a 500 status are reported, 4xx errors are the fault of the client and the
+/// 503s for maintenance or overload are expected.
+#[derive(Clone, Default)]
+pub struct ErrorReporter(Option<Arc<Webhook>>);
+
+struct Webhook {
+    url: String,
+    client: reqwest::Client,
+    in_flight: Arc<Semaphore>,
+}
+
+/// The json posted to the webhook for every reported error
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorReport {
+    pub status: u16,
+    pub method: String,
+    /// The route that failed, without the query
+    pub path: String,
+    pub error: String,
+    pub request_id: Option<String>,
+}
+
+/// The message of an error response, attached by `DropsError` so the middleware can report it
+#[derive(Debug, Clone)]
+pub(crate) struct ErrorMessage(pub String);
+
+impl ErrorReporter {
+    /// Reporting is disabled without a webhook url
+    pub fn new(url: Option<String>) -> Self {
+        ErrorReporter(url.map(|url| {
+            Arc::new(Webhook {
+                url,
+                client: reqwest::Client::new(),
+                in_flight: Arc::new(Semaphore::new(MAX_REPORTS_IN_FLIGHT)),
+            })
+        }))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Post the report in the background, failing to deliver it is only logged
+    pub fn report(&self, report: ErrorReport) {
+        let Some(webhook) = &self.0 else {
+            return;
+        };
+        let Ok(permit) = webhook.in_flight.clone().try_acquire_owned() else {
+            counter!("error_reports_dropped_total").increment(1);
+            return;
+        };
+        let webhook = webhook.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let result = webhook
+                .client
+                .post(&webhook.url)
+                .timeout(REPORT_TIMEOUT)
+                .json(&report)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            match result {
+                Ok(_) => counter!("error_reports_total").increment(1),
+                Err(e) => {
+                    // the webhook url has its secret in the path
+                    warn!(error = %e.without_url(), "failed to report error");
+                    counter!("error_reports_failed_total").increment(1);
+                }
+            }
+        });
+    }
+}
+
+/// Strip control characters and cut off overly long messages before they leave the process
+pub fn sanitize_message(message: &str) -> String {
+    message
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(MAX_MESSAGE_LENGTH)
+        .collect()
+}
+
+/// Report the 500 responses of the wrapped routes to the [`ErrorReporter`]
+pub async fn report_errors(
+    State(reporter): State<ErrorReporter>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    if !reporter.is_enabled() {
+        return next.run(req).await;
+    }
+    let path = if let Some(matched_path) = req.extensions().get::<MatchedPath>() {
+        matched_path.as_str().to_owned()
+    } else {
+        req.uri().path().to_owned()
+    };
+    let method = req.method().to_string();
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .map(String::from);
+
+    let response = next.run(req).await;
+
+    if response.status() == StatusCode::INTERNAL_SERVER_ERROR {
+        let error = match response.extensions().get::<ErrorMessage>() {
+            Some(ErrorMessage(message)) => sanitize_message(message),
+            None => "Internal Server Error".into(),
+        };
+        reporter.report(ErrorReport {
+            status: response.status().as_u16(),
+            method,
+            path,
+            error,
+            request_id,
+        });
+    }
+    response
+}
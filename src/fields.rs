@@ -0,0 +1,64 @@
+use crate::DropsError;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+/// What to do with a requested field the response doesn't have
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownFields {
+    /// Respond with a 400, so typos are noticed
+    #[default]
+    Reject,
+    /// Leave them out, for clients written against a newer version of the api
+    Ignore,
+}
+
+impl FromStr for UnknownFields {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(UnknownFields::Reject),
+            "ignore" => Ok(UnknownFields::Ignore),
+            _ => Err(format!("{s} is not one of reject or ignore")),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldsParams {
+    /// Comma separated list of the fields to include, all fields when not set
+    pub fields: Option<String>,
+}
+
+/// Serialize `value` with only the requested fields
+///
+/// `known` are all the fields `value` can have, including the ones that are skipped when empty,
+/// so asking for those isn't treated as a typo.
+pub fn select_fields<T: Serialize>(
+    value: &T,
+    fields: Option<&str>,
+    known: &[&str],
+    unknown: UnknownFields,
+) -> Result<Value, DropsError> {
+    let value = serde_json::to_value(value)?;
+    let (Some(fields), Value::Object(object)) = (fields, &value) else {
+        return Ok(value);
+    };
+
+    let mut selected = Map::new();
+    for field in fields.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        if !known.contains(&field) {
+            match unknown {
+                UnknownFields::Reject => return Err(DropsError::UnknownField(field.into())),
+                UnknownFields::Ignore => continue,
+            }
+        }
+        if let Some(field_value) = object.get(field) {
+            selected.insert(field.into(), field_value.clone());
+        }
+    }
+    Ok(Value::Object(selected))
+}
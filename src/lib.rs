@@ -1,21 +1,109 @@
-pub use crate::data::{DataSource, DropStats, GlobalStats, SearchParams, TopOrder, TopStats};
+pub use crate::admin::{
+    admin_config, admin_flag_deleted, admin_refresh_player, admin_unflag_deleted, require_admin,
+    AdminToken, EffectiveConfig,
+};
+pub use crate::assets::{asset_url, serve_asset, Asset, ASSETS};
+pub use crate::cache::{Entry, RefreshingCache};
+pub use crate::card::{
+    leaderboard_card, render_leaderboard_card, render_png, CardError, LeaderboardCardTemplate,
+};
+pub use crate::compression::{compress_response, preferred_encoding, Encoding, MIN_COMPRESS_SIZE};
+pub use crate::config::{
+    BindAddress, Buckets, Config, ConfigError, ConfigIssue, HttpSettings, Listen, RedactedConfig,
+    Secret, SECRET_FIELDS,
+};
+pub use crate::data::{
+    rank_movements, rank_percentile, rank_results, Alias, CacheHealth, CacheSettings, DataSource,
+    DataSourceDiagnostics, DataSourceSettings, Distribution, DistributionBucket,
+    DistributionParams, DistributionQuery, DropStats, GlobalStats, NameRefresh, NeighborParams,
+    OrderInfo, PlayerExport, PlayerRanks, PlayerTarget, PoolSettings, RangeStats, RankMovement,
+    RankedMedic, RankedStats, Record, Records, SearchParams, SearchRanking, SearchResult,
+    StatsRangeParams, Target, TargetParams, TopOrder, TopStats, TopStatsQuery, DEFAULT_RANK_CUTOFF,
+    DEFAULT_SEARCH_MIN_DROPS, DEFAULT_TOP_LIMIT, DEFAULT_VANITY_MAX_AGE_DAYS,
+    DEFAULT_VANITY_RATE_LIMIT, MAX_DISTRIBUTION_BUCKETS, MAX_SEARCH_LENGTH, MAX_STATS_RANGE_DAYS,
+    MAX_TOP_LIMIT, TOP_WINDOW,
+};
+pub use crate::diagnostics::{admin_diagnostics, Check, Diagnostics, CHECK_TIMEOUT};
+pub use crate::error_report::{report_errors, sanitize_message, ErrorReport, ErrorReporter};
+pub use crate::fields::{select_fields, FieldsParams, UnknownFields};
+pub use crate::listener::{bind_tcp, connection_builder, wait_for_client};
+pub use crate::log::{
+    LogDetail, LogMedic, LogRow, MapStats, SharedLog, SharedLogPlayer, MAX_EXPORT_LOGS,
+    MAX_SHARED_LOGS,
+};
+pub use crate::logging::{
+    admin_log_level, env_filter, LogFilterHandle, DEFAULT_CONSOLE_LOG_FILTER, DEFAULT_LOG_FILTER,
+};
+pub use crate::maintenance::{admin_disable_maintenance, admin_enable_maintenance, Maintenance};
+pub use crate::metrics_json::{exposition_to_json, Sample};
+pub use crate::milestone::{Milestone, Requirement, MILESTONES};
+pub use crate::page::{page, page_layer, with_page, PageContext, PageSettings};
+pub use crate::rate_limit::{ClientIp, RateLimiter, TrustedProxies};
+pub use crate::ratio::{
+    count_to_f64, format_ratio, per_hour, ratio, RateInterval, Rounding, DEFAULT_PRECISION,
+    MAX_EXACT_COUNT, MAX_PRECISION,
+};
+pub use crate::ready::{readiness_gate, Readiness};
+pub use crate::retry::{retry, Backoff, RetryError};
+pub use crate::router::{app, InFlight, RouterOptions};
+pub use crate::search::{api_search_batch, SearchBatchRequest, MAX_BATCH_SEARCHES};
+pub use crate::site::Site;
+pub use crate::snapshot::{PlayerCacheSnapshot, SnapshotEntry, SnapshotError};
 pub use crate::str::SmolStr;
+pub use crate::summary::{format_summary, SummaryFormat, SummaryParams};
+pub use crate::team::{api_team_summary, TeamSummary, TeamSummaryRequest, TeamTotals};
+pub use crate::theme::{
+    current_theme, theme_layer, vary_on_theme, with_theme, Theme, THEME_COOKIE,
+};
 use askama::Template;
+use axum::body::Body;
 use axum::extract::{Path, Query};
-use axum::http::StatusCode;
+use axum::http::header::{ALLOW, CONTENT_DISPOSITION, CONTENT_TYPE, RETRY_AFTER};
+use axum::http::{HeaderValue, Method, StatusCode, Uri};
 use axum::response::{Html, IntoResponse, Redirect, Response};
 use axum::{Extension, Json};
-use metrics::counter;
+use chrono::Utc;
+use futures_util::StreamExt;
+use metrics::{counter, histogram};
+use serde::Deserialize;
 use std::borrow::Cow;
 use std::fmt::Debug;
 use std::sync::Arc;
-pub use steam_id::SteamId;
+use std::time::{Duration, Instant};
+pub use steam_id::{IdFormat, InvalidSteamId, SteamId};
 use thiserror::Error;
-use tracing::{error, instrument};
+use tracing::{error, instrument, warn, Span};
 
+mod admin;
+mod assets;
+mod cache;
+mod card;
+mod compression;
+mod config;
 mod data;
+mod diagnostics;
+mod error_report;
+mod fields;
+mod listener;
+mod log;
+mod logging;
+mod maintenance;
+mod metrics_json;
+mod milestone;
+mod page;
+mod rate_limit;
+mod ratio;
+mod ready;
+mod retry;
+mod router;
+mod search;
+mod site;
+mod snapshot;
 mod steam_id;
 mod str;
+mod summary;
+mod team;
+mod theme;
 
 #[derive(Debug, Error)]
 pub enum DropsError {
@@ -25,55 +113,385 @@ pub enum DropsError {
     Database(#[from] sqlx::Error),
     #[error(transparent)]
     DatabaseArc(#[from] Arc<sqlx::Error>),
-    #[error("Error while resolving steam url")]
-    Steam(#[from] steam_resolve_vanity::Error),
+    #[error("Steam couldn't be reached to look up the player, try again in a moment")]
+    Steam(#[source] steam_resolve_vanity::Error),
+    #[error("Steam took too long to look up the player, try again in a moment")]
+    SteamTimeout,
+    #[error("Steam rejected the api key")]
+    SteamApiKey,
+    #[error("Error while fetching steam profile")]
+    SteamApi(#[source] reqwest::Error),
     #[error("Error while rendering template")]
     Template(#[from] askama::Error),
+    #[error("Error while encoding the response")]
+    Json(#[from] serde_json::Error),
+    #[error("Error while rendering image")]
+    Card(#[from] Arc<CardError>),
     #[error("404 - Page not found")]
     NotFound,
+    #[error("405 - Method not allowed")]
+    MethodNotAllowed,
     #[error("User not found or no drops")]
     UserNotFound,
+    #[error("{name} hasn't played medic yet")]
+    NoMedicStats { steam_id: SteamId, name: String },
+    #[error("Only individual steam accounts have drops")]
+    NotIndividual,
+    #[error("drops.tf is starting up, try again in a moment")]
+    StartingUp,
+    #[error("This player has been removed from drops.tf")]
+    PlayerGone,
+    #[error("401 - Unauthorized")]
+    Unauthorized,
+    #[error("A team can have at most {} players", team::MAX_TEAM_SIZE)]
+    TeamTooLarge,
+    #[error("A batch can have at most {} searches", search::MAX_BATCH_SEARCHES)]
+    TooManySearches,
+    #[error("A search can be at most {} characters", data::MAX_SEARCH_LENGTH)]
+    SearchTooLong,
+    #[error("drops.tf is busy, try again in a moment")]
+    Overloaded,
+    #[error("The export was cut off, try again later")]
+    ExportIncomplete,
+    #[error("Pass either a rank or a number of drops as target")]
+    InvalidTarget,
+    #[error("Pick two different players to compare")]
+    SamePlayer,
+    #[error("Invalid date range, {0}")]
+    InvalidRange(&'static str),
+    #[error("Unknown field {0}")]
+    UnknownField(String),
+    #[error("Slow down a bit")]
+    TooManyRequests { retry_after: Duration },
+    #[error("drops.tf is in maintenance, try again later")]
+    Maintenance,
+    #[error("Invalid log filter: {0}")]
+    LogFilter(#[from] tracing_subscriber::filter::ParseError),
+    #[error("Failed to update log filter")]
+    LogReload(#[from] tracing_subscriber::reload::Error),
+}
+
+// the urls of steam api requests contain the api key, which shouldn't end up in the logs or the
+// error reports, so it's stripped from every steam error
+impl From<reqwest::Error> for DropsError {
+    fn from(e: reqwest::Error) -> Self {
+        DropsError::SteamApi(e.without_url())
+    }
+}
+
+// a rejected api key is a problem with the configuration, not with steam
+impl From<steam_resolve_vanity::Error> for DropsError {
+    fn from(e: steam_resolve_vanity::Error) -> Self {
+        match e {
+            steam_resolve_vanity::Error::InvalidKey => DropsError::SteamApiKey,
+            steam_resolve_vanity::Error::Request(e) => {
+                DropsError::Steam(steam_resolve_vanity::Error::Request(e.without_url()))
+            }
+            e => DropsError::Steam(e),
+        }
+    }
+}
+
+impl DropsError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            DropsError::SteamId(_)
+            | DropsError::NotIndividual
+            | DropsError::TeamTooLarge
+            | DropsError::TooManySearches
+            | DropsError::SearchTooLong
+            | DropsError::UnknownField(_)
+            | DropsError::InvalidTarget
+            | DropsError::InvalidRange(_)
+            | DropsError::SamePlayer
+            | DropsError::LogFilter(_) => StatusCode::BAD_REQUEST,
+            DropsError::NotFound | DropsError::UserNotFound | DropsError::NoMedicStats { .. } => {
+                StatusCode::NOT_FOUND
+            }
+            DropsError::StartingUp
+            | DropsError::Maintenance
+            | DropsError::Overloaded
+            | DropsError::Steam(_)
+            | DropsError::SteamTimeout => StatusCode::SERVICE_UNAVAILABLE,
+            DropsError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            DropsError::PlayerGone => StatusCode::GONE,
+            DropsError::Unauthorized => StatusCode::UNAUTHORIZED,
+            DropsError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Whole seconds the client should wait before trying again, if waiting helps
+    ///
+    /// Rounded up so a client following `Retry-After` doesn't come back just too early.
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            DropsError::TooManyRequests { retry_after } => {
+                Some(retry_after.as_secs_f64().ceil().max(1.0) as u64)
+            }
+            _ => None,
+        }
+    }
+
+    /// The error with the chain of its sources, as much detail as is reported for server errors
+    pub fn detailed_message(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(error) = source {
+            message.push_str(": ");
+            message.push_str(&error.to_string());
+            source = error.source();
+        }
+        message
+    }
+
+    /// Respond with the status of the error and `body`, with `Retry-After` set when it applies
+    ///
+    /// Server errors carry their message along for [`report_errors`].
+    fn response(&self, body: impl IntoResponse) -> Response {
+        let status = self.status();
+        let mut response = (status, body).into_response();
+        if let Some(retry_after) = self.retry_after() {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        if status == StatusCode::INTERNAL_SERVER_ERROR {
+            response
+                .extensions_mut()
+                .insert(error_report::ErrorMessage(self.detailed_message()));
+        }
+        response
+    }
 }
 
 impl IntoResponse for DropsError {
     fn into_response(self) -> Response {
-        let status = match &self {
-            DropsError::SteamId(_) => StatusCode::BAD_REQUEST,
-            DropsError::NotFound | DropsError::UserNotFound => StatusCode::NOT_FOUND,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
-        };
         let template = ErrorTemplate {
             error: Cow::Owned(format!("{}", self)),
+            suggestions: Vec::new(),
+            retry_after: self.retry_after(),
         };
-        (
-            status,
-            Html(
-                template
-                    .render()
-                    .unwrap_or_else(|_| "Error rendering error".into()),
-            ),
-        )
-            .into_response()
+        let html = render(&template).unwrap_or_else(|_| fallback_error_page(&self));
+        self.response(Html(html))
+    }
+}
+
+/// A bare error page that doesn't depend on any template, for when the error template itself
+/// fails to render
+fn fallback_error_page(error: &DropsError) -> String {
+    let message = escape_html(&error.to_string());
+    let name = escape_html(&page().site.name);
+    format!(
+        "<!DOCTYPE html><html><head><title>{name}</title></head>\
+        <body><h1>{name}</h1><p>{message}</p><p><a href=\"/\">Back to the leaderboard</a></p>\
+        </body></html>"
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders taking longer than this many milliseconds are logged by default
+pub const DEFAULT_SLOW_RENDER_MS: u64 = 50;
+
+/// Render a template, timing the render and counting the failures per template
+///
+/// Every template should be rendered through this, so broken or slow templates show up in the
+/// metrics. Renders slower than the threshold of the [`PageContext`] are logged.
+fn render<T: Template>(template: &T) -> Result<String, askama::Error> {
+    // `dropstf::IndexTemplate<'_>` becomes `IndexTemplate`
+    let name = std::any::type_name::<T>()
+        .rsplit("::")
+        .next()
+        .and_then(|name| name.split('<').next())
+        .unwrap_or_default();
+
+    let start = Instant::now();
+    let result = template.render();
+    let elapsed = start.elapsed();
+
+    histogram!("template_render_duration_seconds", "template" => name)
+        .record(elapsed.as_secs_f64());
+    let threshold = page().slow_render;
+    if !threshold.is_zero() && elapsed >= threshold {
+        warn!(
+            template = name,
+            duration_ms = elapsed.as_millis() as u64,
+            "slow template render"
+        );
     }
+
+    result.inspect_err(|e| {
+        error!(error = %e, template = name, "failed to render template");
+        counter!("template_render_errors", "template" => name).increment(1);
+    })
 }
 
 #[derive(Template)]
 #[template(path = "index.html")]
 pub struct IndexTemplate<'a> {
     pub top: &'a [TopStats],
+    /// How the rank of every player in `top` moved, empty if that isn't known
+    pub movements: Vec<RankMovement>,
     pub stats: GlobalStats,
+    pub ranked: GlobalStats,
+    pub featured: Option<DropStats>,
+    pub order: TopOrder,
+    /// Medics need more than this many drops to be ranked
+    pub rank_cutoff: i64,
+}
+
+impl IndexTemplate<'_> {
+    /// How the rank of the medic at `index` on the leaderboard moved
+    ///
+    /// Takes a reference because askama passes the loop index by reference
+    pub fn movement(&self, index: &usize) -> RankMovement {
+        self.movements
+            .get(*index)
+            .copied()
+            .unwrap_or(RankMovement::Same)
+    }
+}
+
+/// One record on the records page
+pub struct RecordRow {
+    pub title: &'static str,
+    pub record: Record,
+    /// The value of the record formatted for the page
+    pub value: String,
+}
+
+impl RecordRow {
+    /// The rows of every record that is held by someone, in the order they're shown
+    pub fn from_records(records: &Records) -> Vec<RecordRow> {
+        fn count(value: f64) -> String {
+            format!("{:.0}", value)
+        }
+        fn ratio(value: f64) -> String {
+            format!("{:.2}", value)
+        }
+        fn hours(value: f64) -> String {
+            format!("{:.0} hours", value / 3600.0)
+        }
+        let rows = [
+            (
+                "Most drops in a single game",
+                &records.most_drops_single_game,
+                count as fn(f64) -> String,
+            ),
+            ("Most drops", &records.most_drops, count),
+            ("Most drops per uber", &records.highest_dpu, ratio),
+            ("Fewest drops per uber", &records.lowest_dpu, ratio),
+            ("Most time on medic", &records.longest_medic_time, hours),
+        ];
+        rows.iter()
+            .filter_map(|&(title, record, format)| {
+                let record = record.clone()?;
+                Some(RecordRow {
+                    title,
+                    value: format(record.value),
+                    record,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Template)]
+#[template(path = "records.html")]
+pub struct RecordsTemplate {
+    pub records: Vec<RecordRow>,
+}
+
+/// A player that appears in logs, but never played medic
+#[derive(Template)]
+#[template(path = "known_player.html")]
+pub struct KnownPlayerTemplate {
+    pub steam_id: SteamId,
+    pub name: String,
 }
 
 #[derive(Template)]
 #[template(path = "player.html")]
 pub struct PlayerTemplate {
     pub stats: DropStats,
+    /// Other names the player is known by
+    pub aliases: Vec<Alias>,
+    /// Ranked medics with a similar play style, see [`DataSource::similar_players`]
+    pub similar: Vec<TopStats>,
+    pub milestones: Vec<Milestone>,
+    pub id_format: IdFormat,
+    pub rate: RateInterval,
 }
 
+impl PlayerTemplate {
+    /// The drops per interval the visitor picked
+    pub fn drop_rate(&self) -> f64 {
+        self.rate.rate(self.stats.drops, self.stats.medic_time)
+    }
+
+    /// The steam id of the player in the format the visitor picked
+    pub fn display_id(&self) -> String {
+        self.stats.steam_id.format(self.id_format)
+    }
+}
+
+/// Unknown parameters are allowed here, shared profile links often get tracking parameters added
+#[derive(Debug, Deserialize)]
+pub struct PlayerParams {
+    #[serde(default)]
+    pub id: IdFormat,
+    /// The time the drop rate is shown per
+    #[serde(default)]
+    pub rate: RateInterval,
+}
+
+/// Maximum number of other names shown on a profile
+const MAX_ALIASES: usize = 5;
+
 #[derive(Template)]
 #[template(path = "error.html")]
 pub struct ErrorTemplate {
     pub error: Cow<'static, str>,
+    /// Players the visitor might have been looking for
+    pub suggestions: Vec<SearchResult>,
+    /// Seconds until the visitor can try again after making too many requests
+    pub retry_after: Option<u64>,
+}
+
+/// The leaderboard for any ordering
+///
+/// Alternative names for an ordering, like `dph`, redirect to the canonical page of the ordering.
+#[instrument(skip(data_source))]
+pub async fn page_top_order(
+    data_source: Extension<DataSource>,
+    Path(order): Path<String>,
+) -> Result<Response, DropsError> {
+    let top_order: TopOrder = order.parse().map_err(|_| DropsError::NotFound)?;
+    if top_order.path() != format!("/top/{order}") {
+        return Ok(Redirect::permanent(top_order.path()).into_response());
+    }
+    Ok(page_top_stats(data_source, top_order)
+        .await?
+        .into_response())
+}
+
+/// The short paths the leaderboards used to be on, which are kept working for old links
+pub async fn legacy_top_redirect(order: TopOrder) -> Redirect {
+    Redirect::permanent(order.path())
 }
 
 #[instrument(skip(data_source))]
@@ -81,47 +499,436 @@ pub async fn page_top_stats(
     Extension(data_source): Extension<DataSource>,
     order: TopOrder,
 ) -> Result<impl IntoResponse, DropsError> {
-    let top = data_source.top_stats(order).await?;
+    let query = data_source.top_query(order);
+    let top = data_source.top_stats(query).await?;
     let stats = data_source.global_stats().await?;
+    let ranked = data_source.ranked_global_stats().await?;
+    // the featured player is a nice to have, the leaderboards should still show without it
+    let featured = data_source
+        .featured_player(Utc::now().date_naive())
+        .await
+        .unwrap_or_else(|e| {
+            warn!(error = %e, "failed to load featured player");
+            None
+        });
+    let movements = data_source.rank_movements(&query, &top);
     let template = IndexTemplate {
         top: top.as_slice(),
+        movements,
         stats,
+        ranked,
+        featured,
+        order,
+        rank_cutoff: data_source.rank_cutoff(),
     };
 
-    Ok(Html(template.render()?))
+    Ok(Html(render(&template)?))
 }
 
+/// The all-time records, like the most drops in a single game
 #[instrument(skip(data_source))]
-pub async fn page_player(
+pub async fn page_records(
     Extension(data_source): Extension<DataSource>,
-    Path(steam_id): Path<String>,
 ) -> Result<impl IntoResponse, DropsError> {
-    let steam_id = match steam_id.parse().map_err(DropsError::from) {
-        Ok(steam_id) => steam_id,
-        Err(e) => data_source
-            .resolve_vanity_url(&steam_id)
-            .await?
-            .ok_or(e)
-            .inspect_err(|_| {
-                error!(steam_id = display(steam_id), "user not found");
-            })?,
+    let records = data_source.records().await?;
+    let template = RecordsTemplate {
+        records: RecordRow::from_records(&records),
     };
-    let stats = data_source.stats_for_user(steam_id).await.map_err(|_| {
-        error!(steam_id = u64::from(steam_id), "no logs found for user");
-        DropsError::UserNotFound
-    })?;
+    Ok(Html(render(&template)?))
+}
+
+#[instrument(skip(data_source), fields(steam_id64))]
+pub async fn page_player(
+    Extension(data_source): Extension<DataSource>,
+    Path(input): Path<String>,
+    Query(params): Query<PlayerParams>,
+    client: ClientIp,
+) -> Result<Response, DropsError> {
+    match render_player(&data_source, &input, params, client).await {
+        Err(
+            e @ (DropsError::UserNotFound
+            | DropsError::SteamId(_)
+            | DropsError::Steam(_)
+            | DropsError::SteamTimeout),
+        ) => Ok(player_not_found(&data_source, &input, e).await),
+        Err(DropsError::NoMedicStats { steam_id, name }) => {
+            let template = KnownPlayerTemplate { steam_id, name };
+            Ok((StatusCode::NOT_FOUND, Html(render(&template)?)).into_response())
+        }
+        result => result,
+    }
+}
+
+/// Maximum number of players suggested when a profile can't be found
+const MAX_SUGGESTIONS: usize = 5;
+
+/// The error page for an unknown player, with players whose name matches the input
+///
+/// The suggestions are optional, if the search fails the plain error page is shown.
+async fn player_not_found(data_source: &DataSource, input: &str, error: DropsError) -> Response {
+    // searching for a steam id only finds the player we already know has no stats
+    let suggestions = if !is_plausible_player(input)
+        || input.chars().count() > MAX_SEARCH_LENGTH
+        || SteamId::from_any(input).is_ok()
+    {
+        Vec::new()
+    } else {
+        data_source
+            .player_search(input, SearchRanking::Precise, MAX_SUGGESTIONS, 0)
+            .await
+            .unwrap_or_else(|e| {
+                warn!(error = %e, "failed to search for suggestions");
+                Vec::new()
+            })
+    };
+    let template = ErrorTemplate {
+        error: Cow::Owned(error.to_string()),
+        suggestions,
+        retry_after: error.retry_after(),
+    };
+    match render(&template) {
+        Ok(html) => error.response(Html(html)),
+        Err(_) => error.into_response(),
+    }
+}
+
+async fn render_player(
+    data_source: &DataSource,
+    input: &str,
+    params: PlayerParams,
+    client: ClientIp,
+) -> Result<Response, DropsError> {
+    let steam_id = resolve_player(data_source, input, client).await?;
+    let stats = player_stats(data_source, steam_id).await?;
 
     let counter = counter!(
         "player_stats",
         &[
-            ("steam_id", format!("{}", u64::from(steam_id))),
-            ("name", stats.name.to_string())
+            ("steam_id", steam_id.to_string()),
+            ("name", metric_label(stats.name.as_str()))
         ]
     );
     counter.increment(1);
 
-    let template = PlayerTemplate { stats };
-    Ok(Html(template.render()?))
+    let aliases = data_source
+        .aliases_for_user(steam_id)
+        .await?
+        .iter()
+        .filter(|alias| alias.name != stats.name.as_str())
+        .take(MAX_ALIASES)
+        .cloned()
+        .collect();
+
+    // like the featured player, the recommendations shouldn't keep the profile from showing
+    let similar = data_source
+        .similar_players(steam_id)
+        .await
+        .map(|similar| similar.as_ref().clone())
+        .unwrap_or_else(|e| {
+            warn!(error = %e, "failed to load similar players");
+            Vec::new()
+        });
+
+    let milestones = stats.milestones(data_source.rank_cutoff());
+    let template = PlayerTemplate {
+        stats,
+        aliases,
+        similar,
+        milestones,
+        id_format: params.id,
+        rate: params.rate,
+    };
+    Ok(Html(render(&template)?).into_response())
+}
+
+/// Maximum length of a user provided value used as metric label
+const MAX_LABEL_LENGTH: usize = 64;
+
+/// Make a user provided value, like a player name, safe to use as metric label
+///
+/// Control characters, quotes and backslashes are dropped so that a name can't corrupt the
+/// exposition format, even if the exporter would not escape it.
+fn metric_label(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"' && *c != '\\')
+        .take(MAX_LABEL_LENGTH)
+        .collect()
+}
+
+/// Longest vanity url steam allows
+const MAX_VANITY_LENGTH: usize = 32;
+/// Longest input that can refer to a player, which is a profile or invite link with room to spare
+const MAX_PLAYER_INPUT_LENGTH: usize = 128;
+
+/// Whether the input could refer to a player at all
+///
+/// Junk like megabytes long paths or control characters is rejected before it reaches the
+/// database or steam.
+fn is_plausible_player(input: &str) -> bool {
+    input.len() <= MAX_PLAYER_INPUT_LENGTH && !input.chars().any(char::is_control)
+}
+
+/// Resolve the steam id or vanity url a player was linked by
+///
+/// The resolved id is recorded in the `steam_id64` field of the current span, so traces can be
+/// correlated regardless of the format the player was linked by.
+async fn resolve_player(
+    data_source: &DataSource,
+    input: &str,
+    client: ClientIp,
+) -> Result<SteamId, DropsError> {
+    if !is_plausible_player(input) {
+        return Err(DropsError::UserNotFound);
+    }
+    let steam_id = match SteamId::from_any(input) {
+        Ok(steam_id) => steam_id,
+        Err(_) if input.len() > MAX_VANITY_LENGTH => return Err(DropsError::UserNotFound),
+        // steam knowing nobody by the url is a 404, steam failing to answer is a 503
+        Err(_) => data_source
+            .resolve_vanity_url(input, client)
+            .await?
+            .ok_or(DropsError::UserNotFound)
+            .inspect_err(|_| {
+                error!(steam_id = display(input), "user not found");
+            })?,
+    };
+    Span::current().record("steam_id64", steam_id.as_u64());
+    if !steam_id.is_valid_individual() {
+        return Err(DropsError::NotIndividual);
+    }
+    Ok(steam_id)
+}
+
+async fn player_stats(
+    data_source: &DataSource,
+    steam_id: SteamId,
+) -> Result<DropStats, DropsError> {
+    data_source
+        .stats_for_user(steam_id)
+        .await
+        .map_err(|e| match e {
+            DropsError::PlayerGone | DropsError::NoMedicStats { .. } | DropsError::Overloaded => e,
+            _ => {
+                error!(steam_id = steam_id.as_u64(), "no logs found for user");
+                DropsError::UserNotFound
+            }
+        })
+}
+
+/// The drops rank of a player as plain text, for stream overlays and scripts
+///
+/// Unlike the profile page these don't count towards the `player_stats` metric.
+#[instrument(skip(data_source), fields(steam_id64))]
+pub async fn text_player_rank(
+    Extension(data_source): Extension<DataSource>,
+    client: ClientIp,
+    Path(steam_id): Path<String>,
+) -> Response {
+    text_player_response(&data_source, &steam_id, client, |stats| {
+        stats.drops_rank.to_string()
+    })
+    .await
+}
+
+/// The drop count of a player as plain text
+#[instrument(skip(data_source), fields(steam_id64))]
+pub async fn text_player_drops(
+    Extension(data_source): Extension<DataSource>,
+    client: ClientIp,
+    Path(steam_id): Path<String>,
+) -> Response {
+    text_player_response(&data_source, &steam_id, client, |stats| {
+        stats.drops.to_string()
+    })
+    .await
+}
+
+/// A ready to paste summary of a players stats in either markdown or bbcode
+#[instrument(skip(data_source), fields(steam_id64))]
+pub async fn text_player_summary(
+    Extension(data_source): Extension<DataSource>,
+    client: ClientIp,
+    Path(steam_id): Path<String>,
+    Query(params): Query<SummaryParams>,
+) -> Response {
+    text_player_response(&data_source, &steam_id, client, |stats| {
+        format_summary(stats, &page().site, &params)
+    })
+    .await
+}
+
+async fn text_player_response(
+    data_source: &DataSource,
+    steam_id: &str,
+    client: ClientIp,
+    render: impl FnOnce(&DropStats) -> String,
+) -> Response {
+    let result = async {
+        let steam_id = resolve_player(data_source, steam_id, client)
+            .await
+            .map_err(|e| match e {
+                DropsError::SteamId(_) => DropsError::UserNotFound,
+                e => e,
+            })?;
+        player_stats(data_source, steam_id).await
+    }
+    .await;
+    match result {
+        Ok(stats) => render(&stats).into_response(),
+        Err(e) => e.response(e.to_string()),
+    }
+}
+
+/// Download everything drops.tf knows about a player as json
+#[instrument(skip(data_source), fields(steam_id64))]
+pub async fn api_player_export(
+    Extension(data_source): Extension<DataSource>,
+    client: ClientIp,
+    Path(steam_id): Path<String>,
+) -> Result<Response, DropsError> {
+    let steam_id = resolve_player(&data_source, &steam_id, client).await?;
+    let stats = player_stats(&data_source, steam_id).await?;
+    let export = data_source.player_export(stats).await?;
+    let disposition = format!("attachment; filename=\"drops-{steam_id}.json\"");
+    Ok(([(CONTENT_DISPOSITION, disposition)], Json(&*export)).into_response())
+}
+
+/// The stats of a player as json, `?fields=` picks which stats to include
+#[instrument(skip(data_source), fields(steam_id64))]
+pub async fn api_player(
+    Extension(data_source): Extension<DataSource>,
+    Extension(unknown_fields): Extension<UnknownFields>,
+    client: ClientIp,
+    Path(steam_id): Path<String>,
+    Query(params): Query<FieldsParams>,
+) -> Result<impl IntoResponse, DropsError> {
+    let steam_id = resolve_player(&data_source, &steam_id, client).await?;
+    let stats = player_stats(&data_source, steam_id).await?;
+    let stats = select_fields(
+        &stats,
+        params.fields.as_deref(),
+        DropStats::FIELDS,
+        unknown_fields,
+    )?;
+    Ok(Json(stats))
+}
+
+/// All leaderboard orderings, so clients don't have to hardcode them
+pub async fn api_orders() -> impl IntoResponse {
+    let orders: Vec<OrderInfo> = TopOrder::ALL.iter().map(TopOrder::info).collect();
+    Json(orders)
+}
+
+/// Every ranked medic as newline delimited json, for bulk exports
+///
+/// The response is streamed, an error halfway through aborts it instead of ending it early so a
+/// partial export can't be mistaken for a complete one.
+#[instrument(skip(data_source))]
+pub async fn api_ranks_ndjson(
+    Extension(data_source): Extension<DataSource>,
+) -> Result<Response, DropsError> {
+    let lines = data_source.ranked_medics()?.map(|medic| {
+        let mut line = serde_json::to_vec(&medic?)?;
+        line.push(b'\n');
+        Ok::<_, DropsError>(line)
+    });
+    Ok((
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+/// The stats of the player at a specific rank, to see what it takes to get there
+#[instrument(skip(data_source))]
+pub async fn api_player_at_rank(
+    Extension(data_source): Extension<DataSource>,
+    Path((order, rank)): Path<(TopOrder, i64)>,
+) -> Result<impl IntoResponse, DropsError> {
+    let player = data_source
+        .player_at_rank(order, rank)
+        .await?
+        .ok_or(DropsError::NotFound)?;
+    Ok(Json(player))
+}
+
+/// The players ranked directly above and below a player, for expanding the list on the profile
+#[instrument(skip(data_source), fields(steam_id64))]
+pub async fn api_player_neighbors(
+    Extension(data_source): Extension<DataSource>,
+    client: ClientIp,
+    Path(steam_id): Path<String>,
+    Query(params): Query<NeighborParams>,
+) -> Result<impl IntoResponse, DropsError> {
+    let steam_id = resolve_player(&data_source, &steam_id, client).await?;
+    let stats = player_stats(&data_source, steam_id).await?;
+    let neighbors = data_source
+        .rank_neighbors(&stats, params.order, params.radius())
+        .await?;
+    Ok(Json(neighbors))
+}
+
+/// How many drops a player needs for a rank, or the rank they'd have with a number of drops
+#[instrument(skip(data_source), fields(steam_id64))]
+pub async fn api_player_target(
+    Extension(data_source): Extension<DataSource>,
+    client: ClientIp,
+    Path(steam_id): Path<String>,
+    Query(params): Query<TargetParams>,
+) -> Result<impl IntoResponse, DropsError> {
+    let target = params.target()?;
+    let steam_id = resolve_player(&data_source, &steam_id, client).await?;
+    let stats = player_stats(&data_source, steam_id).await?;
+    let target = data_source.player_target(&stats, target).await?;
+    Ok(Json(target))
+}
+
+/// The player's rank and percentile on every leaderboard, without the rest of the stats
+#[instrument(skip(data_source), fields(steam_id64))]
+pub async fn api_player_ranks(
+    Extension(data_source): Extension<DataSource>,
+    client: ClientIp,
+    Path(steam_id): Path<String>,
+) -> Result<impl IntoResponse, DropsError> {
+    let steam_id = resolve_player(&data_source, &steam_id, client).await?;
+    let stats = player_stats(&data_source, steam_id).await?;
+    let ranks = data_source.player_ranks(&stats).await?;
+    Ok(Json(ranks))
+}
+
+/// The vanity urls that resolve to a player, to check for urls that changed owner
+#[instrument(skip(data_source), fields(steam_id64))]
+pub async fn api_player_vanities(
+    Extension(data_source): Extension<DataSource>,
+    client: ClientIp,
+    Path(steam_id): Path<String>,
+) -> Result<impl IntoResponse, DropsError> {
+    let steam_id = resolve_player(&data_source, &steam_id, client).await?;
+    if data_source.is_deleted(steam_id).await? {
+        return Err(DropsError::PlayerGone);
+    }
+    let vanities = data_source.vanities_for_user(steam_id).await?;
+    Ok(Json(vanities))
+}
+
+/// The logs two players played in together, for following a rivalry
+#[instrument(skip(data_source))]
+pub async fn api_shared_logs(
+    Extension(data_source): Extension<DataSource>,
+    client: ClientIp,
+    Path((a, b)): Path<(String, String)>,
+) -> Result<impl IntoResponse, DropsError> {
+    let a = resolve_player(&data_source, &a, client).await?;
+    let b = resolve_player(&data_source, &b, client).await?;
+    if a == b {
+        return Err(DropsError::SamePlayer);
+    }
+    if data_source.is_deleted(a).await? || data_source.is_deleted(b).await? {
+        return Err(DropsError::PlayerGone);
+    }
+    let logs = data_source.shared_logs(a, b).await?;
+    Ok(Json(logs))
 }
 
 #[instrument(skip(data_source))]
@@ -129,10 +936,41 @@ pub async fn api_search(
     Extension(data_source): Extension<DataSource>,
     Query(query): Query<SearchParams>,
 ) -> Result<impl IntoResponse, DropsError> {
-    let result = data_source.player_search(&query.search).await?;
+    let result = data_source
+        .player_search(&query.search, query.rank, query.limit(), query.offset())
+        .await?;
     Ok(Json(result))
 }
 
+/// How the ranked medics are spread over a stat, for drawing a histogram
+#[instrument(skip(data_source))]
+pub async fn api_distribution(
+    Extension(data_source): Extension<DataSource>,
+    Query(params): Query<DistributionParams>,
+) -> Result<impl IntoResponse, DropsError> {
+    let distribution = data_source
+        .distribution(params.metric, params.buckets())
+        .await?;
+    Ok(Json(distribution))
+}
+
+/// Totals of the medics over the logs of a date range, like the current season
+#[instrument(skip(data_source))]
+pub async fn api_global_stats_range(
+    Extension(data_source): Extension<DataSource>,
+    client: ClientIp,
+    Query(params): Query<StatsRangeParams>,
+) -> Result<impl IntoResponse, DropsError> {
+    let totals = data_source
+        .global_stats_range(params.since, params.until, client)
+        .await?;
+    Ok(Json(RangeStats {
+        since: params.since,
+        until: params.until,
+        totals,
+    }))
+}
+
 #[instrument(skip(data_source))]
 pub async fn get_log(
     Extension(data_source): Extension<DataSource>,
@@ -142,6 +980,16 @@ pub async fn get_log(
     Ok(Json(result))
 }
 
+/// The medics of a log and their drops
+#[instrument(skip(data_source))]
+pub async fn get_log_detail(
+    Extension(data_source): Extension<DataSource>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, DropsError> {
+    let result = data_source.log_detail(id).await?;
+    Ok(Json(result))
+}
+
 #[instrument(skip(data_source))]
 pub async fn last_log(
     Extension(data_source): Extension<DataSource>,
@@ -150,6 +998,36 @@ pub async fn last_log(
     Ok(Redirect::temporary(&format!("/api/log/{result}")))
 }
 
-pub async fn handler_404() -> impl IntoResponse {
-    DropsError::NotFound
+pub async fn health() -> &'static str {
+    "ok"
+}
+
+/// Every method any of the routes respond to, advertised for `OPTIONS *`
+const SERVER_METHODS: &str = "GET, HEAD, POST, DELETE, OPTIONS";
+
+pub async fn handler_404(method: Method, uri: Uri, theme: Theme) -> Response {
+    // `OPTIONS *` asks about the server as a whole instead of a route
+    if method == Method::OPTIONS && uri == "*" {
+        return (
+            StatusCode::NO_CONTENT,
+            [(ALLOW, HeaderValue::from_static(SERVER_METHODS))],
+        )
+            .into_response();
+    }
+    // the fallback isn't behind the theme layer, which would also apply to the api routes
+    let mut response = with_theme(theme, || DropsError::NotFound.into_response());
+    vary_on_theme(&mut response);
+    response
+}
+
+/// Fallback for a route that exists but doesn't handle the method of the request
+///
+/// axum adds the `Allow` header with the methods of the route, which is all `OPTIONS` needs.
+pub async fn handler_405(method: Method, theme: Theme) -> Response {
+    if method == Method::OPTIONS {
+        return StatusCode::NO_CONTENT.into_response();
+    }
+    let mut response = with_theme(theme, || DropsError::MethodNotAllowed.into_response());
+    vary_on_theme(&mut response);
+    response
 }
@@ -0,0 +1,67 @@
+use crate::HttpSettings;
+use hyper_util::rt::{TokioExecutor, TokioTimer};
+use hyper_util::server::conn::auto;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Number of connections that can wait to be accepted
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Listen for tcp connections on an ipv4 or ipv6 address
+///
+/// Listening on the unspecified ipv6 address `::` explicitly accepts ipv4 connections as well,
+/// as ipv4-mapped addresses, instead of depending on the `net.ipv6.bindv6only` setting of the host.
+/// Any other ipv6 address only accepts ipv6 connections.
+pub fn bind_tcp(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if let SocketAddr::V6(v6) = addr {
+        socket.set_only_v6(!v6.ip().is_unspecified())?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Seconds as a timeout, with 0 meaning no timeout
+fn seconds(seconds: u64) -> Option<Duration> {
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+/// The builder for serving client connections over http/1 or http/2
+///
+/// The same settings apply to every way of listening.
+pub fn connection_builder(settings: &HttpSettings) -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(settings.keep_alive)
+        .header_read_timeout(seconds(settings.header_read_timeout));
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(settings.http2_max_concurrent_streams)
+        .keep_alive_interval(seconds(settings.http2_keep_alive_interval));
+    builder
+}
+
+/// Wait for the first bytes from a client, false if it stays silent for the header read timeout
+///
+/// Hyper can only start its header read timeout once the first bytes tell it whether the client
+/// speaks http/1 or http/2, so without this a client that never sends anything keeps its
+/// connection open forever. `readable` is the `readable()` of the tcp or unix stream.
+pub async fn wait_for_client(
+    readable: impl Future<Output = io::Result<()>>,
+    settings: &HttpSettings,
+) -> bool {
+    match seconds(settings.header_read_timeout) {
+        Some(timeout) => matches!(tokio::time::timeout(timeout, readable).await, Ok(Ok(()))),
+        None => readable.await.is_ok(),
+    }
+}
@@ -0,0 +1,104 @@
+use crate::steam_id::SteamId;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::types::JsonValue;
+
+/// Most logs returned for a head-to-head, see [`DataSource::shared_logs`](crate::DataSource::shared_logs)
+pub const MAX_SHARED_LOGS: i64 = 100;
+/// Most logs in the export of a player, see [`DataSource::player_export`](crate::DataSource::player_export)
+pub const MAX_EXPORT_LOGS: i64 = 100;
+/// Number of the newest medic logs of a player that the maps in their export are counted from
+pub const MAX_EXPORT_MAP_LOGS: i64 = 1000;
+
+/// The stats of one medic in one log, as read from `logs_raw`
+///
+/// Every query over the per-log data reads into this, so the endpoints built on it agree on what
+/// a log looks like.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogRow {
+    pub log_id: i32,
+    pub date: DateTime<Utc>,
+    /// Empty for old logs that don't have the map
+    pub map: String,
+    pub steam_id: SteamId,
+    pub name: String,
+    pub drops: i64,
+    pub ubers: i64,
+}
+
+/// A log with every medic that played in it
+#[derive(Debug, Clone, Serialize)]
+pub struct LogDetail {
+    pub log_id: i32,
+    pub date: DateTime<Utc>,
+    pub map: String,
+    pub medics: Vec<LogMedic>,
+}
+
+/// One of the medics of a [`LogDetail`]
+#[derive(Debug, Clone, Serialize)]
+pub struct LogMedic {
+    pub steam_id: SteamId,
+    pub name: String,
+    pub drops: i64,
+    pub ubers: i64,
+}
+
+impl LogDetail {
+    /// Group the rows of a single log, `None` if there are no rows to take the log from
+    pub fn from_rows(rows: Vec<LogRow>) -> Option<LogDetail> {
+        let first = rows.first()?;
+        let mut detail = LogDetail {
+            log_id: first.log_id,
+            date: first.date,
+            map: first.map.clone(),
+            medics: Vec::with_capacity(rows.len()),
+        };
+        detail.medics.extend(rows.into_iter().map(|row| LogMedic {
+            steam_id: row.steam_id,
+            name: row.name,
+            drops: row.drops,
+            ubers: row.ubers,
+        }));
+        Some(detail)
+    }
+}
+
+/// The drops of a medic on one map
+#[derive(Debug, Clone, Serialize)]
+pub struct MapStats {
+    /// Empty for the old logs that don't have the map
+    pub map: String,
+    pub games: i64,
+    pub drops: i64,
+    pub ubers: i64,
+}
+
+/// A log two players both played in
+#[derive(Debug, Clone, Serialize)]
+pub struct SharedLog {
+    pub log_id: i32,
+    pub date: DateTime<Utc>,
+    pub map: String,
+    pub a: SharedLogPlayer,
+    pub b: SharedLogPlayer,
+}
+
+/// How one of the players of a [`SharedLog`] did in it
+#[derive(Debug, Clone, Serialize)]
+pub struct SharedLogPlayer {
+    /// Whether they played medic, the drops and ubers are 0 if they didn't
+    pub medic: bool,
+    pub drops: i64,
+    pub ubers: i64,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RawLog {
+    pub json: JsonValue,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RawLogId {
+    pub id: i32,
+}
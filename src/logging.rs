@@ -0,0 +1,40 @@
+use crate::DropsError;
+use axum::http::StatusCode;
+use axum::Extension;
+use tracing::{info, instrument};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Filter for the traces exported over otlp when `RUST_LOG` isn't set
+pub const DEFAULT_LOG_FILTER: &str = "dropstf=debug,tower_http=debug,sqlx=debug";
+/// Filter for the logs printed to stdout when `RUST_LOG` isn't set
+pub const DEFAULT_CONSOLE_LOG_FILTER: &str = "warn";
+
+/// Build a filter from `RUST_LOG`, falling back to the provided default
+pub fn env_filter(default: &str) -> EnvFilter {
+    EnvFilter::new(std::env::var("RUST_LOG").unwrap_or_else(|_| default.into()))
+}
+
+/// Handle to swap out the log filter at runtime, empty when tracing isn't enabled
+#[derive(Clone, Default)]
+pub struct LogFilterHandle(Option<reload::Handle<EnvFilter, Registry>>);
+
+impl LogFilterHandle {
+    pub fn new(handle: reload::Handle<EnvFilter, Registry>) -> Self {
+        LogFilterHandle(Some(handle))
+    }
+}
+
+/// Replace the log filter with the directives from the request body, e.g. `dropstf=trace,sqlx=debug`
+#[instrument(skip(handle))]
+pub async fn admin_log_level(
+    Extension(handle): Extension<LogFilterHandle>,
+    directives: String,
+) -> Result<StatusCode, DropsError> {
+    let Some(handle) = handle.0 else {
+        return Err(DropsError::NotFound);
+    };
+    let filter = EnvFilter::try_new(directives.trim())?;
+    handle.reload(filter)?;
+    info!(filter = directives.trim(), "log filter updated");
+    Ok(StatusCode::NO_CONTENT)
+}
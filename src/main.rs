@@ -1,18 +1,16 @@
-use axum::body::Body;
-use axum::extract::{connect_info, MatchedPath};
+use axum::extract::{connect_info, ConnectInfo};
 use axum::http::Request;
-use axum::middleware::Next;
-use axum::response::IntoResponse;
-use axum::routing::get;
-use axum::{middleware, Extension, Router};
+use axum::response::Response;
+use axum::Router;
 use dropstf::{
-    api_search, get_log, handler_404, last_log, page_player, page_top_stats, DataSource, TopOrder,
+    app, bind_tcp, connection_builder, env_filter, wait_for_client, AdminToken, Buckets, Config,
+    DataSource, ErrorReporter, HttpSettings, Listen, LogFilterHandle, Readiness, RouterOptions,
+    SnapshotError, DEFAULT_CONSOLE_LOG_FILTER, DEFAULT_LOG_FILTER,
 };
 use hyper::body::Incoming;
 use hyper_util::rt::{TokioExecutor, TokioIo};
-use hyper_util::server;
+use hyper_util::server::conn::auto;
 use main_error::MainError;
-use metrics::{counter, histogram};
 use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
 use opentelemetry::trace::TracerProvider;
 use opentelemetry::KeyValue;
@@ -21,30 +19,48 @@ use opentelemetry_sdk::trace::SdkTracerProvider;
 use opentelemetry_sdk::Resource;
 use sqlx::postgres::PgPool;
 use std::convert::Infallible;
-use std::fs::{set_permissions, Permissions};
-use std::future::ready;
+use std::fs::{set_permissions, File, Permissions};
+use std::io::{BufReader, ErrorKind};
 use std::net::SocketAddr;
 use std::os::unix::fs::PermissionsExt;
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::unix::UCred;
 use tokio::net::{UnixListener, UnixStream};
-use tokio::time::Instant;
-use tower_http::trace::TraceLayer;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::timeout;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
 use tower_service::Service;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::Layer;
+use tracing_subscriber::{reload, Layer};
 
-enum Listen {
-    Port(u16),
-    Socket(String),
+#[derive(Debug, Error)]
+enum TlsConfigError {
+    #[error("Failed to read {}: {error}", path.display())]
+    Read {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("No certificates found in {}", .0.display())]
+    NoCertificates(PathBuf),
+    #[error("No private key found in {}", .0.display())]
+    NoKey(PathBuf),
+    #[error("Invalid tls certificate or key: {0}")]
+    Invalid(#[from] tokio_rustls::rustls::Error),
 }
 
 #[tokio::main]
 async fn main() -> Result<(), MainError> {
-    if let Ok(tracing_endpoint) = dotenvy::var("TRACING_ENDPOINT") {
+    let config = Config::from_env()?;
+    let redacted_config = config.redacted();
+
+    let mut log_filter = LogFilterHandle::default();
+    if let Some(tracing_endpoint) = config.tracing_endpoint {
         let tls_config = tonic::transport::ClientTlsConfig::new().with_native_roots();
         let otlp_exporter = SpanExporter::builder()
             .with_tonic()
@@ -60,69 +76,159 @@ async fn main() -> Result<(), MainError> {
             .build()
             .tracer("drops.tf");
         let open_telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
+        let (filter, handle) = reload::Layer::new(env_filter(DEFAULT_LOG_FILTER));
         tracing_subscriber::registry()
-            .with(tracing_subscriber::EnvFilter::new(
-                std::env::var("RUST_LOG")
-                    .unwrap_or_else(|_| "dropstf=debug,tower_http=debug,sqlx=debug".into()),
-            ))
+            .with(filter)
             .with(open_telemetry)
             .with(
-                tracing_subscriber::fmt::layer().with_filter(tracing_subscriber::EnvFilter::new(
-                    std::env::var("RUST_LOG").unwrap_or_else(|_| "warn".into()),
-                )),
+                tracing_subscriber::fmt::layer()
+                    .with_filter(env_filter(DEFAULT_CONSOLE_LOG_FILTER)),
             )
             .try_init()?;
+        log_filter = LogFilterHandle::new(handle);
     }
 
-    let database_url = dotenvy::var("DATABASE_URL")?;
-    let api_key = dotenvy::var("STEAM_API_KEY")?;
-    let listen = match dotenvy::var("SOCKET") {
-        Ok(socket) => Listen::Socket(socket),
-        _ => Listen::Port(u16::from_str(&dotenvy::var("PORT")?)?),
-    };
+    let pool = PgPool::connect(&config.database_url).await?;
+    let mut data_source = DataSource::new(pool, config.steam_api_key)
+        .with_maintenance(config.maintenance)
+        .with_min_dpu_ubers(config.min_dpu_ubers)
+        .with_rank_cutoff(config.rank_cutoff)
+        .with_recompute_concurrency(config.recompute_concurrency)
+        .with_vanity_rate_limit(config.vanity_rate_limit)
+        .with_vanity_max_age(Duration::from_secs(
+            u64::from(config.vanity_max_age_days) * 24 * 60 * 60,
+        ))
+        .with_featured_pool(config.featured_pool)
+        .with_search_min_drops(config.search_min_drops);
+    if let Some(replica_url) = &config.database_url_replica {
+        data_source = data_source.with_replica(PgPool::connect(replica_url).await?);
+    }
+    data_source.verify_schema().await?;
 
-    let pool = PgPool::connect(&database_url).await?;
-    let data_source = DataSource::new(pool, api_key);
+    if let Some(snapshot) = &config.player_cache_snapshot {
+        match data_source.load_player_cache(snapshot).await {
+            Ok(count) => tracing::info!(count, "loaded player cache snapshot"),
+            Err(SnapshotError::Io(e)) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!(error = display(e), "failed to load player cache snapshot"),
+        }
+    }
 
-    let recorder_handle = setup_metrics_recorder();
+    let recorder_handle =
+        setup_metrics_recorder(&config.http_duration_buckets, &config.db_duration_buckets);
 
-    let app = Router::new()
-        .route(
-            "/",
-            get(|data_source| page_top_stats(data_source, TopOrder::Drops)),
-        )
-        .route(
-            "/dpg",
-            get(|data_source| page_top_stats(data_source, TopOrder::Dpg)),
-        )
-        .route(
-            "/dph",
-            get(|data_source| page_top_stats(data_source, TopOrder::Dps)),
-        )
-        .route(
-            "/dpu",
-            get(|data_source| page_top_stats(data_source, TopOrder::Dpu)),
-        )
-        .route("/profile/{steam_id}", get(page_player))
-        .route("/search", get(api_search))
-        .route("/metrics", get(move || ready(recorder_handle.render())))
-        .route("/api/log/last", get(last_log))
-        .route("/api/log/{id}", get(get_log))
-        .route_layer(middleware::from_fn(track_metrics))
-        .layer(Extension(data_source))
-        .layer(TraceLayer::new_for_http())
-        .fallback(handler_404);
+    let readiness = Readiness::default();
+    tokio::spawn({
+        let data_source = data_source.clone();
+        let readiness = readiness.clone();
+        async move {
+            if let Err(e) = data_source.warm_cache().await {
+                tracing::error!(error = display(e), "failed to warm caches");
+            }
+            readiness.set_ready();
+        }
+    });
+
+    let app = app(
+        data_source.clone(),
+        recorder_handle,
+        RouterOptions {
+            readiness,
+            admin_token: AdminToken::new(config.admin_token),
+            log_filter,
+            metrics_json: config.metrics_json,
+            unknown_fields: config.unknown_fields,
+            config: Some(redacted_config),
+            error_reporter: ErrorReporter::new(config.error_webhook),
+            trusted_proxies: config.trusted_proxies.clone(),
+            slow_render: Duration::from_millis(config.slow_render_ms),
+            site: config.site.clone(),
+        },
+    );
+
+    tokio::select! {
+        result = serve(config.listen, &config.http, app) => result?,
+        _ = shutdown_signal() => tracing::info!("shutting down"),
+    }
+
+    if let Some(snapshot) = &config.player_cache_snapshot {
+        match data_source.save_player_cache(snapshot) {
+            Ok(count) => tracing::info!(count, "saved player cache snapshot"),
+            Err(e) => tracing::error!(error = display(e), "failed to save player cache snapshot"),
+        }
+    }
+    Ok(())
+}
+
+/// Wait for ctrl-c or the `SIGTERM` from the service manager
+async fn shutdown_signal() {
+    let mut terminate =
+        signal(SignalKind::terminate()).expect("failed to install the SIGTERM handler");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate.recv() => {},
+    }
+}
 
+/// Accept connections until listening fails
+///
+/// Connections that are still open on shutdown are dropped without waiting for them.
+async fn serve(listen: Listen, http: &HttpSettings, app: Router) -> Result<(), MainError> {
+    let builder = connection_builder(http);
     match listen {
-        Listen::Port(port) => {
-            let addr = SocketAddr::from(([0, 0, 0, 0], port));
+        Listen::Tcp(addr) => {
             tracing::info!("listening on {}", addr);
-            let listener = tokio::net::TcpListener::bind(addr).await?;
-            axum::serve(listener, app).await?;
+            let listener = bind_tcp(addr)?;
+
+            loop {
+                let (stream, remote_addr) = listener.accept().await?;
+                let builder = builder.clone();
+                let tower_service = app.clone();
+                let http = http.clone();
+
+                tokio::spawn(async move {
+                    if wait_for_client(stream.readable(), &http).await {
+                        serve_connection(builder, stream, Some(remote_addr), tower_service).await
+                    }
+                });
+            }
         }
-        Listen::Socket(socket) => {
-            tracing::info!("listening on {}", socket);
-            let socket_path: PathBuf = socket.into();
+        Listen::Tls { addr, cert, key } => {
+            let acceptor = TlsAcceptor::from(load_tls_config(&cert, &key)?);
+            tracing::info!("listening on {} with tls", addr);
+            let listener = bind_tcp(addr)?;
+
+            loop {
+                let (stream, remote_addr) = listener.accept().await?;
+                let acceptor = acceptor.clone();
+                let builder = builder.clone();
+                let tower_service = app.clone();
+                let http = http.clone();
+
+                tokio::spawn(async move {
+                    if !wait_for_client(stream.readable(), &http).await {
+                        return;
+                    }
+                    // the handshake gets the same time as the headers
+                    let handshake = acceptor.accept(stream);
+                    let handshake = match http.header_read_timeout {
+                        0 => handshake.await,
+                        secs => match timeout(Duration::from_secs(secs), handshake).await {
+                            Ok(result) => result,
+                            Err(_) => return,
+                        },
+                    };
+                    match handshake {
+                        Ok(stream) => {
+                            serve_connection(builder, stream, Some(remote_addr), tower_service)
+                                .await
+                        }
+                        Err(err) => tracing::debug!("tls handshake failed: {err:#}"),
+                    }
+                });
+            }
+        }
+        Listen::Socket(socket_path) => {
+            tracing::info!("listening on {}", socket_path.display());
             if socket_path.exists() {
                 std::fs::remove_file(&socket_path)?;
             }
@@ -131,33 +237,51 @@ async fn main() -> Result<(), MainError> {
 
             let mut make_service = app.into_make_service_with_connect_info::<UdsConnectInfo>();
 
-            // See https://github.com/tokio-rs/axum/blob/main/examples/serve-with-hyper/src/main.rs for
-            // more details about this setup
             loop {
                 let (socket, _remote_addr) = listener.accept().await?;
-
                 let tower_service = unwrap_infallible(make_service.call(&socket).await);
+                let builder = builder.clone();
+                let http = http.clone();
 
                 tokio::spawn(async move {
-                    let socket = TokioIo::new(socket);
-
-                    let hyper_service =
-                        hyper::service::service_fn(move |request: Request<Incoming>| {
-                            tower_service.clone().call(request)
-                        });
-
-                    if let Err(err) = server::conn::auto::Builder::new(TokioExecutor::new())
-                        .serve_connection_with_upgrades(socket, hyper_service)
-                        .await
-                    {
-                        eprintln!("failed to serve connection: {err:#}");
+                    if wait_for_client(socket.readable(), &http).await {
+                        serve_connection(builder, socket, None, tower_service).await
                     }
                 });
             }
         }
     }
+}
 
-    Ok(())
+/// Serve a single connection over http/1 or http/2, depending on what the client speaks
+///
+/// The address of tcp clients is made available to the handlers as [`ConnectInfo`].
+///
+/// See https://github.com/tokio-rs/axum/blob/main/examples/serve-with-hyper/src/main.rs for
+/// more details about this setup
+async fn serve_connection<I, S>(
+    builder: auto::Builder<TokioExecutor>,
+    io: I,
+    remote_addr: Option<SocketAddr>,
+    tower_service: S,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Service<Request<Incoming>, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    let hyper_service = hyper::service::service_fn(move |mut request: Request<Incoming>| {
+        if let Some(remote_addr) = remote_addr {
+            request.extensions_mut().insert(ConnectInfo(remote_addr));
+        }
+        tower_service.clone().call(request)
+    });
+
+    if let Err(err) = builder
+        .serve_connection_with_upgrades(TokioIo::new(io), hyper_service)
+        .await
+    {
+        eprintln!("failed to serve connection: {err:#}");
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -179,6 +303,40 @@ impl connect_info::Connected<&UnixStream> for UdsConnectInfo {
     }
 }
 
+/// Load the PEM encoded certificate chain and private key for serving over https
+fn load_tls_config(cert: &Path, key: &Path) -> Result<Arc<ServerConfig>, TlsConfigError> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|error| TlsConfigError::Read {
+                path: path.into(),
+                error,
+            })
+    };
+
+    let certs = rustls_pemfile::certs(&mut open(cert)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| TlsConfigError::Read {
+            path: cert.into(),
+            error,
+        })?;
+    if certs.is_empty() {
+        return Err(TlsConfigError::NoCertificates(cert.into()));
+    }
+    let key = rustls_pemfile::private_key(&mut open(key)?)
+        .map_err(|error| TlsConfigError::Read {
+            path: key.into(),
+            error,
+        })?
+        .ok_or_else(|| TlsConfigError::NoKey(key.into()))?;
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
 fn unwrap_infallible<T>(result: Result<T, Infallible>) -> T {
     match result {
         Ok(value) => value,
@@ -186,45 +344,18 @@ fn unwrap_infallible<T>(result: Result<T, Infallible>) -> T {
     }
 }
 
-fn setup_metrics_recorder() -> PrometheusHandle {
-    const EXPONENTIAL_SECONDS: &[f64] = &[
-        0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
-    ];
-
+fn setup_metrics_recorder(http_buckets: &Buckets, db_buckets: &Buckets) -> PrometheusHandle {
     PrometheusBuilder::new()
         .set_buckets_for_metric(
             Matcher::Full("http_requests_duration_seconds".to_string()),
-            EXPONENTIAL_SECONDS,
+            &http_buckets.0,
+        )
+        .unwrap()
+        .set_buckets_for_metric(
+            Matcher::Full("db_query_duration_seconds".to_string()),
+            &db_buckets.0,
         )
         .unwrap()
         .install_recorder()
         .unwrap()
 }
-
-async fn track_metrics(req: Request<Body>, next: Next) -> impl IntoResponse {
-    let start = Instant::now();
-    let path = if let Some(matched_path) = req.extensions().get::<MatchedPath>() {
-        matched_path.as_str().to_owned()
-    } else {
-        req.uri().path().to_owned()
-    };
-    let method = req.method().clone();
-
-    let response = next.run(req).await;
-
-    let latency = start.elapsed().as_secs_f64();
-    let status = response.status().as_u16().to_string();
-
-    if path != "/metrics" {
-        let labels = [
-            ("method", method.to_string()),
-            ("path", path),
-            ("status", status),
-        ];
-
-        counter!("http_requests_total", &labels).increment(1);
-        histogram!("http_requests_duration_seconds", &labels).record(latency);
-    }
-
-    response
-}
@@ -0,0 +1,48 @@
+use crate::{DataSource, DropsError};
+use axum::http::StatusCode;
+use axum::Extension;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{info, instrument};
+
+/// Whether the site is in maintenance mode, shared by every clone of the data source
+///
+/// In maintenance mode the site only serves reads and shows a banner on every page, writes to
+/// the database are skipped or refused.
+#[derive(Debug, Clone, Default)]
+pub struct Maintenance(Arc<AtomicBool>);
+
+impl Maintenance {
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Release);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Refuse a write to the database during maintenance
+    pub fn ensure_writable(&self) -> Result<(), DropsError> {
+        if self.is_enabled() {
+            Err(DropsError::Maintenance)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn admin_enable_maintenance(Extension(data_source): Extension<DataSource>) -> StatusCode {
+    data_source.maintenance().set(true);
+    info!("maintenance mode enabled");
+    StatusCode::NO_CONTENT
+}
+
+#[instrument(skip_all)]
+pub async fn admin_disable_maintenance(
+    Extension(data_source): Extension<DataSource>,
+) -> StatusCode {
+    data_source.maintenance().set(false);
+    info!("maintenance mode disabled");
+    StatusCode::NO_CONTENT
+}
@@ -0,0 +1,69 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Serialize)]
+pub struct Sample {
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
+/// Convert the prometheus exposition format into json, keyed by metric name
+///
+/// Histograms and summaries are not grouped, their `_bucket`, `_sum` and `_count` series are
+/// listed as separate metrics like they are in the exposition format.
+pub fn exposition_to_json(exposition: &str) -> BTreeMap<String, Vec<Sample>> {
+    let mut metrics: BTreeMap<String, Vec<Sample>> = BTreeMap::new();
+    for line in exposition.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some((name, sample)) = parse_line(line) {
+            metrics.entry(name.to_string()).or_default().push(sample);
+        }
+    }
+    metrics
+}
+
+fn parse_line(line: &str) -> Option<(&str, Sample)> {
+    let name_end = line.find(|c: char| c == '{' || c.is_whitespace())?;
+    let name = &line[..name_end];
+    let (labels, rest) = if line[name_end..].starts_with('{') {
+        parse_labels(&line[name_end + 1..])?
+    } else {
+        (BTreeMap::new(), &line[name_end..])
+    };
+    // an optional timestamp can follow the value
+    let value = rest.split_whitespace().next()?;
+    let value = match value {
+        "+Inf" => f64::INFINITY,
+        "-Inf" => f64::NEG_INFINITY,
+        value => value.parse().ok()?,
+    };
+    Some((name, Sample { labels, value }))
+}
+
+/// Parse `key="value",...}`, returning the labels and the remainder after the closing brace
+fn parse_labels(mut input: &str) -> Option<(BTreeMap<String, String>, &str)> {
+    let mut labels = BTreeMap::new();
+    loop {
+        input = input.trim_start_matches([',', ' ']);
+        if let Some(rest) = input.strip_prefix('}') {
+            return Some((labels, rest));
+        }
+        let (key, rest) = input.split_once("=\"")?;
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        let end = loop {
+            match chars.next()? {
+                (_, '\\') => match chars.next()?.1 {
+                    'n' => value.push('\n'),
+                    c => value.push(c),
+                },
+                (i, '"') => break i,
+                (_, c) => value.push(c),
+            }
+        };
+        labels.insert(key.trim().to_string(), value);
+        input = &rest[end + 1..];
+    }
+}
@@ -0,0 +1,84 @@
+use crate::data::{DropStats, TopOrder};
+
+/// A badge shown on the profile of players that reached a certain stat or rank
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Milestone {
+    pub title: &'static str,
+    pub requirement: Requirement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Requirement {
+    Drops(i64),
+    Ubers(i64),
+    Games(i64),
+    /// A rank of at most this on the leaderboard, only for ranked players
+    Rank(TopOrder, i64),
+}
+
+/// All milestones, in the order they're displayed
+pub const MILESTONES: &[Milestone] = &[
+    Milestone {
+        title: "100 drops",
+        requirement: Requirement::Drops(100),
+    },
+    Milestone {
+        title: "1000 drops",
+        requirement: Requirement::Drops(1000),
+    },
+    Milestone {
+        title: "1000 ubers",
+        requirement: Requirement::Ubers(1000),
+    },
+    Milestone {
+        title: "10,000 ubers",
+        requirement: Requirement::Ubers(10_000),
+    },
+    Milestone {
+        title: "1000 games",
+        requirement: Requirement::Games(1000),
+    },
+    Milestone {
+        title: "Top 10 in drops",
+        requirement: Requirement::Rank(TopOrder::Drops, 10),
+    },
+    Milestone {
+        title: "Top 10 in drops per hour",
+        requirement: Requirement::Rank(TopOrder::Dps, 10),
+    },
+    Milestone {
+        title: "Top 10 in drops per game",
+        requirement: Requirement::Rank(TopOrder::Dpg, 10),
+    },
+    Milestone {
+        title: "Top 10 in drops per uber",
+        requirement: Requirement::Rank(TopOrder::Dpu, 10),
+    },
+];
+
+impl Milestone {
+    /// Rank milestones are only reached by players with more than `rank_cutoff` drops
+    pub fn reached(&self, stats: &DropStats, rank_cutoff: i64) -> bool {
+        match self.requirement {
+            Requirement::Drops(drops) => stats.drops >= drops,
+            Requirement::Ubers(ubers) => stats.ubers >= ubers,
+            Requirement::Games(games) => stats.games >= games,
+            // unranked players get a rank computed relative to the ranked players which can be
+            // misleadingly high for the ratios
+            Requirement::Rank(order, rank) => {
+                stats.drops > rank_cutoff && stats.rank(order) <= rank
+            }
+        }
+    }
+}
+
+impl DropStats {
+    /// The milestones reached by the player, see [`Milestone::reached`]
+    pub fn milestones(&self, rank_cutoff: i64) -> Vec<Milestone> {
+        MILESTONES
+            .iter()
+            .filter(|milestone| milestone.reached(self, rank_cutoff))
+            .copied()
+            .collect()
+    }
+}
@@ -0,0 +1,58 @@
+use crate::maintenance::Maintenance;
+use crate::site::Site;
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What every page is rendered with besides the data of the page itself
+///
+/// Like the theme this isn't passed into the templates, so the error page, which is rendered
+/// without access to the request, gets it too. It's set for every request by [`page_layer`] from
+/// the state of the router.
+#[derive(Debug, Clone, Default)]
+pub struct PageContext {
+    /// Show the maintenance banner
+    pub maintenance: bool,
+    /// Renders taking longer than this are logged, zero disables the log
+    pub slow_render: Duration,
+    /// Branding of the pages
+    pub site: Arc<Site>,
+}
+
+/// The state of the router the [`PageContext`] of every request is made from
+#[derive(Debug, Clone, Default)]
+pub struct PageSettings {
+    pub maintenance: Maintenance,
+    pub slow_render: Duration,
+    pub site: Arc<Site>,
+}
+
+tokio::task_local! {
+    static PAGE: PageContext;
+}
+
+/// The context of the page being rendered, the defaults outside of [`page_layer`]
+pub fn page() -> PageContext {
+    PAGE.try_with(PageContext::clone).unwrap_or_default()
+}
+
+/// Run `f` with `page` as the context, for rendering outside of [`page_layer`]
+pub fn with_page<R>(page: PageContext, f: impl FnOnce() -> R) -> R {
+    PAGE.sync_scope(page, f)
+}
+
+/// Render the pages of a request with the current state of the router
+pub async fn page_layer(
+    State(settings): State<PageSettings>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let page = PageContext {
+        maintenance: settings.maintenance.is_enabled(),
+        slow_render: settings.slow_render,
+        site: settings.site,
+    };
+    PAGE.scope(page, next.run(req)).await
+}
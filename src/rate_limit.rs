@@ -0,0 +1,158 @@
+use crate::DropsError;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+use moka::future::Cache;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The address a request came from
+///
+/// Behind a reverse proxy this is the last address in `X-Forwarded-For`, the one added by the
+/// proxy itself, earlier addresses are set by the client and can't be trusted. The header is only
+/// used for connections from one of the [`TrustedProxies`] and over the unix socket, which only
+/// the proxy can reach. Otherwise anyone could pick their own address, so the address of the
+/// connection is used. Clients connecting over the unix socket without the header have no address
+/// and share a single limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let connected = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip());
+        // only tcp connections have an address
+        let behind_proxy = match connected {
+            Some(address) => parts
+                .extensions
+                .get::<TrustedProxies>()
+                .is_some_and(|proxies| proxies.contains(address)),
+            None => true,
+        };
+        if !behind_proxy {
+            return Ok(ClientIp(connected));
+        }
+        let forwarded = parts
+            .headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|header| header.to_str().ok())
+            .flat_map(|header| header.split(','))
+            .next_back()
+            .and_then(|address| address.trim().parse().ok());
+        Ok(ClientIp(forwarded.or(connected)))
+    }
+}
+
+/// Addresses of the reverse proxies whose `X-Forwarded-For` is trusted for tcp connections,
+/// written as a comma separated list like `127.0.0.1,::1`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+impl TrustedProxies {
+    pub fn contains(&self, address: IpAddr) -> bool {
+        self.0.contains(&address)
+    }
+}
+
+impl FromStr for TrustedProxies {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|address| {
+                let address = address.trim();
+                address
+                    .parse()
+                    .map_err(|_| format!("{address:?} is not an ip address"))
+            })
+            .collect::<Result<_, _>>()
+            .map(TrustedProxies)
+    }
+}
+
+/// Number of clients tracked at most, the least recently limited are forgotten first
+const MAX_TRACKED_CLIENTS: u64 = 10_000;
+
+/// Allow every client a number of requests per minute, with bursts up to that same number
+///
+/// Every client gets a bucket of tokens which refills continuously, so a client that used up its
+/// limit can make its next request after a fraction of a minute instead of waiting for a window.
+#[derive(Clone)]
+pub struct RateLimiter {
+    per_minute: u32,
+    buckets: Cache<ClientIp, Arc<Mutex<Bucket>>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// A limit of 0 disables the limiter
+    pub fn new(per_minute: u32) -> Self {
+        RateLimiter {
+            per_minute,
+            // an idle bucket is full again after a minute, so it can be dropped
+            buckets: Cache::builder()
+                .time_to_idle(Duration::from_secs(60))
+                .max_capacity(MAX_TRACKED_CLIENTS)
+                .build(),
+        }
+    }
+
+    pub fn per_minute(&self) -> u32 {
+        self.per_minute
+    }
+
+    /// Take a token from the bucket of the client
+    ///
+    /// If the bucket is empty this fails with [`DropsError::TooManyRequests`], telling the client
+    /// how long it takes until the next token is available.
+    pub async fn check(&self, client: ClientIp) -> Result<(), DropsError> {
+        self.check_many(client, 1).await
+    }
+
+    /// Take `count` tokens from the bucket of the client at once
+    ///
+    /// Either all tokens are taken or none are, so a batch that doesn't fit doesn't use up the
+    /// tokens for the part that would.
+    pub async fn check_many(&self, client: ClientIp, count: usize) -> Result<(), DropsError> {
+        if self.per_minute == 0 || count == 0 {
+            return Ok(());
+        }
+        let capacity = f64::from(self.per_minute);
+        let bucket = self
+            .buckets
+            .get_with(client, async {
+                Arc::new(Mutex::new(Bucket {
+                    tokens: capacity,
+                    updated: Instant::now(),
+                }))
+            })
+            .await;
+        let mut bucket = bucket.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * capacity / 60.0;
+        bucket.tokens = (bucket.tokens + refill).min(capacity);
+        bucket.updated = now;
+        let count = count as f64;
+        if bucket.tokens >= count {
+            bucket.tokens -= count;
+            Ok(())
+        } else {
+            let retry_after = (count - bucket.tokens) * 60.0 / capacity;
+            Err(DropsError::TooManyRequests {
+                retry_after: Duration::from_secs_f64(retry_after),
+            })
+        }
+    }
+}
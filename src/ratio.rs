@@ -0,0 +1,106 @@
+use serde::Deserialize;
+
+/// Number of decimals ratios are shown with unless asked otherwise
+pub const DEFAULT_PRECISION: usize = 2;
+
+/// Most decimals a ratio can be requested with, anything beyond is noise
+pub const MAX_PRECISION: usize = 6;
+
+/// How a ratio is rounded to the requested number of decimals
+///
+/// Only values exactly halfway are affected, which is decided on the exact value of the float.
+/// `1.005` is stored as `1.00499999...` so it becomes `1.00` with either mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rounding {
+    /// Ties round to the even digit, `0.125` becomes `0.12`, the same as `format!("{:.2}")`
+    #[default]
+    HalfEven,
+    /// Ties round towards positive infinity, `0.125` becomes `0.13`
+    HalfUp,
+}
+
+/// Largest count that converts to a float without losing precision, 2^53
+///
+/// Drops, ubers, games and seconds of medic time are all many orders of magnitude below this.
+pub const MAX_EXACT_COUNT: i64 = 1 << 53;
+
+/// Convert a count from the database into a float
+///
+/// All conversions of counts into floats should go through here. Counts above
+/// [`MAX_EXACT_COUNT`] would be rounded, and negative counts mean the stats are broken, so both
+/// are caught in debug builds.
+pub fn count_to_f64(count: i64) -> f64 {
+    debug_assert!(
+        (0..=MAX_EXACT_COUNT).contains(&count),
+        "count {} can't be converted to a float exactly",
+        count
+    );
+    count as f64
+}
+
+/// `count` per `per`, or 0 if there is nothing to divide by
+pub fn ratio(count: i64, per: i64) -> f64 {
+    if per > 0 {
+        count_to_f64(count) / count_to_f64(per)
+    } else {
+        0.0
+    }
+}
+
+/// `count` per hour of `seconds`, or 0 without any time
+pub fn per_hour(count: i64, seconds: i64) -> f64 {
+    if seconds > 0 {
+        count_to_f64(count) / (count_to_f64(seconds) / 3600.0)
+    } else {
+        0.0
+    }
+}
+
+/// The time a drop rate is shown per
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum RateInterval {
+    #[default]
+    #[serde(rename = "hour")]
+    Hour,
+    /// Preferred by some communities, as it's closer to the length of a match
+    #[serde(rename = "30m")]
+    HalfHour,
+}
+
+impl RateInterval {
+    /// `count` per interval of `seconds`, or 0 without any time
+    pub fn rate(&self, count: i64, seconds: i64) -> f64 {
+        match self {
+            RateInterval::Hour => per_hour(count, seconds),
+            RateInterval::HalfHour => per_hour(count, seconds) / 2.0,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RateInterval::Hour => "hour",
+            RateInterval::HalfHour => "30 minutes",
+        }
+    }
+}
+
+/// Format a ratio with a fixed number of decimals
+pub fn format_ratio(value: f64, precision: usize, rounding: Rounding) -> String {
+    let value = match rounding {
+        // the formatter rounds ties to even, anything above the tie rounds up
+        Rounding::HalfUp if is_tie(value, precision) => value.next_up(),
+        _ => value,
+    };
+    format!("{:.*}", precision, value)
+}
+
+/// Whether the value lies exactly halfway between two numbers with `precision` decimals
+///
+/// A tie has exactly one decimal more than the precision, which is a 5. The closest float that
+/// isn't a tie still differs from it well within the 25 decimals checked.
+fn is_tie(value: f64, precision: usize) -> bool {
+    let digits = format!("{:.*}", precision + 25, value.abs());
+    let tail = &digits[digits.len() - 25..];
+    tail.starts_with('5') && tail[1..].bytes().all(|digit| digit == b'0')
+}
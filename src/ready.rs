@@ -0,0 +1,43 @@
+use crate::DropsError;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Tracks whether the startup tasks have finished and the service can handle requests
+#[derive(Clone, Default)]
+pub struct Readiness(Arc<AtomicBool>);
+
+impl Readiness {
+    pub fn set_ready(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Answer with a 503 "starting up" page until the service is ready
+///
+/// `/health` and the metrics endpoints are always served so orchestrators can keep probing during startup,
+/// the static assets don't need the database so they are served too.
+pub async fn readiness_gate(
+    State(readiness): State<Readiness>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let path = req.uri().path();
+    if readiness.is_ready()
+        || path == "/health"
+        || path.starts_with("/metrics")
+        || path.starts_with("/assets/")
+    {
+        next.run(req).await
+    } else {
+        DropsError::StartingUp.into_response()
+    }
+}
@@ -0,0 +1,55 @@
+use std::future::Future;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::{sleep, timeout};
+
+/// How a request to an outside service is tried again after it failed or took too long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    /// Tries in total, including the first
+    pub attempts: u32,
+    /// Wait before the second try, doubled before every try after that
+    pub delay: Duration,
+    /// Longest a single try can take before it counts as failed
+    pub timeout: Duration,
+}
+
+/// Why the last try of [`retry`] failed
+#[derive(Debug, Error)]
+pub enum RetryError<E> {
+    #[error("timed out")]
+    TimedOut,
+    #[error(transparent)]
+    Failed(E),
+}
+
+/// Run `f` until it succeeds or the tries of `backoff` run out
+///
+/// Only timeouts and the errors `retryable` accepts are tried again, a successful answer that
+/// there's nothing to find or an error that won't go away is returned right away.
+pub async fn retry<T, E, F, Fut>(
+    backoff: Backoff,
+    retryable: impl Fn(&E) -> bool,
+    mut f: F,
+) -> Result<T, RetryError<E>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut delay = backoff.delay;
+    let mut attempt = 1;
+    loop {
+        let error = match timeout(backoff.timeout, f()).await {
+            Ok(Ok(result)) => return Ok(result),
+            Ok(Err(e)) if !retryable(&e) => return Err(RetryError::Failed(e)),
+            Ok(Err(e)) => RetryError::Failed(e),
+            Err(_) => RetryError::TimedOut,
+        };
+        if attempt >= backoff.attempts {
+            return Err(error);
+        }
+        sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+    }
+}
@@ -0,0 +1,274 @@
+use crate::{
+    admin_config, admin_diagnostics, admin_disable_maintenance, admin_enable_maintenance,
+    admin_flag_deleted, admin_log_level, admin_refresh_player, admin_unflag_deleted,
+    api_distribution, api_global_stats_range, api_orders, api_player, api_player_at_rank,
+    api_player_export, api_player_neighbors, api_player_ranks, api_player_target,
+    api_player_vanities, api_ranks_ndjson, api_search, api_search_batch, api_shared_logs,
+    api_team_summary, compress_response, exposition_to_json, get_log, get_log_detail, handler_404,
+    handler_405, health, last_log, leaderboard_card, legacy_top_redirect, page_layer, page_player,
+    page_records, page_top_order, page_top_stats, readiness_gate, report_errors, require_admin,
+    serve_asset, text_player_drops, text_player_rank, text_player_summary, theme_layer, AdminToken,
+    DataSource, ErrorReporter, LogFilterHandle, PageSettings, Readiness, RedactedConfig, Site,
+    TopOrder, TrustedProxies, UnknownFields,
+};
+use axum::body::Body;
+use axum::extract::{MatchedPath, State};
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{middleware, Extension, Json, Router};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::PrometheusHandle;
+use std::future::ready;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tower_http::request_id::{
+    MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer,
+};
+use tower_http::trace::TraceLayer;
+use tracing::Span;
+
+/// Everything the router needs besides the data source and metrics
+#[derive(Clone, Default)]
+pub struct RouterOptions {
+    pub readiness: Readiness,
+    pub admin_token: AdminToken,
+    pub log_filter: LogFilterHandle,
+    /// Also serve the metrics as json on `/metrics.json`
+    pub metrics_json: bool,
+    /// The configuration shown on `/admin/config`
+    pub config: Option<RedactedConfig>,
+    /// How `?fields=` treats fields that don't exist
+    pub unknown_fields: UnknownFields,
+    /// Where server errors are reported to, disabled by default
+    pub error_reporter: ErrorReporter,
+    /// Proxies allowed to set the client address of tcp connections
+    pub trusted_proxies: TrustedProxies,
+    /// Template renders taking longer than this are logged, zero disables the log
+    pub slow_render: Duration,
+    /// Branding of the pages
+    pub site: Site,
+}
+
+/// All routes, layers and the fallback of the service
+///
+/// The binary only wires the configuration and listeners around this, so the router can be
+/// tested or embedded in another service as a whole.
+pub fn app(data_source: DataSource, metrics: PrometheusHandle, options: RouterOptions) -> Router {
+    let in_flight = InFlight::default();
+    let admin = Router::new()
+        .route(
+            "/admin/deleted/{steam_id}",
+            post(admin_flag_deleted).delete(admin_unflag_deleted),
+        )
+        .route("/admin/config", get(admin_config))
+        .route("/admin/diagnostics", get(admin_diagnostics))
+        .route("/admin/log-level", post(admin_log_level))
+        .route(
+            "/admin/refresh-player/{steam_id}",
+            post(admin_refresh_player),
+        )
+        .route(
+            "/admin/maintenance",
+            post(admin_enable_maintenance).delete(admin_disable_maintenance),
+        )
+        // set before the token check wraps it, so a disabled admin route stays a 404 for any method
+        .method_not_allowed_fallback(handler_405)
+        .layer(Extension(options.log_filter))
+        .layer(Extension(options.config))
+        .layer(Extension(options.readiness.clone()))
+        .layer(Extension(in_flight.clone()))
+        .route_layer(middleware::from_fn_with_state(
+            options.admin_token,
+            require_admin,
+        ));
+
+    // only the html pages are themed, the api and metrics routes don't need the cookie
+    let pages = Router::new()
+        .route(
+            "/",
+            get(|data_source| page_top_stats(data_source, TopOrder::Drops)),
+        )
+        .route("/top/{order}", get(page_top_order))
+        .route("/profile/{steam_id}", get(page_player))
+        .route("/records", get(page_records))
+        .route_layer(middleware::from_fn(theme_layer));
+
+    let page_settings = PageSettings {
+        maintenance: data_source.maintenance().clone(),
+        slow_render: options.slow_render,
+        site: Arc::new(options.site),
+    };
+    let mut app = Router::new()
+        .route("/top/{order}/card.png", get(leaderboard_card))
+        .route("/dpg", get(|| legacy_top_redirect(TopOrder::Dpg)))
+        .route("/dph", get(|| legacy_top_redirect(TopOrder::Dps)))
+        .route("/dpu", get(|| legacy_top_redirect(TopOrder::Dpu)))
+        .route("/assets/{file}", get(serve_asset))
+        .route("/profile/{steam_id}/rank.txt", get(text_player_rank))
+        .route("/profile/{steam_id}/drops.txt", get(text_player_drops))
+        .route("/profile/{steam_id}/summary", get(text_player_summary))
+        .route("/profile/{steam_id}/export.json", get(api_player_export))
+        .route(
+            "/api/player/{steam_id}/neighbors",
+            get(api_player_neighbors),
+        )
+        .route("/api/player/{steam_id}", get(api_player))
+        .route("/api/player/{steam_id}/target", get(api_player_target))
+        .route("/api/player/{steam_id}/ranks", get(api_player_ranks))
+        .route("/api/player/{steam_id}/vanities", get(api_player_vanities))
+        .route("/api/compare/{a}/{b}/logs", get(api_shared_logs))
+        .route("/rank/{order}/{rank}", get(api_player_at_rank))
+        .route("/search", get(api_search))
+        .route("/api/search/batch", post(api_search_batch))
+        .route("/api/distribution", get(api_distribution))
+        .route("/api/stats/global", get(api_global_stats_range))
+        .route("/api/orders", get(api_orders))
+        .route("/api/ranks.ndjson", get(api_ranks_ndjson))
+        .route("/api/team/summary", post(api_team_summary))
+        .route("/health", get(health))
+        .route("/api/log/last", get(last_log))
+        .route("/api/log/{id}", get(get_log))
+        .route("/api/log/{id}/detail", get(get_log_detail))
+        .merge(pages)
+        .merge(admin);
+
+    if options.metrics_json {
+        let metrics = metrics.clone();
+        app = app.route(
+            "/metrics.json",
+            get(move || ready(Json(exposition_to_json(&metrics.render())))),
+        );
+    }
+    app = app.route("/metrics", get(move || ready(metrics.render())));
+    // after all routes, it only applies to the routes that exist when it's set
+    app = app.method_not_allowed_fallback(handler_405);
+
+    app.route_layer(middleware::from_fn_with_state(
+        options.readiness,
+        readiness_gate,
+    ))
+    .route_layer(middleware::from_fn_with_state(in_flight, track_metrics))
+    .route_layer(middleware::from_fn_with_state(
+        options.error_reporter,
+        report_errors,
+    ))
+    .layer(Extension(data_source))
+    .layer(Extension(options.unknown_fields))
+    .layer(Extension(options.trusted_proxies))
+    .layer(TraceLayer::new_for_http().make_span_with(request_span))
+    .fallback(handler_404)
+    // after the fallback, so the error pages of unknown routes get the banner too
+    .layer(middleware::from_fn_with_state(page_settings, page_layer))
+    // outside of the router so error responses from the fallback get the id too
+    .layer(PropagateRequestIdLayer::x_request_id())
+    .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+    .layer(middleware::from_fn(compress_response))
+}
+
+/// Span for every request, tagged with the `X-Request-Id` from the proxy or generated for the request
+fn request_span(req: &Request<Body>) -> Span {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default();
+    tracing::debug_span!(
+        "request",
+        method = %req.method(),
+        uri = %req.uri(),
+        version = ?req.version(),
+        request_id,
+    )
+}
+
+async fn track_metrics(
+    State(in_flight): State<InFlight>,
+    req: Request<Body>,
+    next: Next,
+) -> impl IntoResponse {
+    let start = Instant::now();
+    let path = if let Some(matched_path) = req.extensions().get::<MatchedPath>() {
+        matched_path.as_str().to_owned()
+    } else {
+        req.uri().path().to_owned()
+    };
+    let method = req.method().clone();
+
+    let _in_flight = InFlightGuard::new(in_flight);
+    let mut canceled = CancelGuard {
+        labels: Some([("method", method.to_string()), ("path", path.clone())]),
+    };
+    let response = next.run(req).await;
+    canceled.labels = None;
+
+    let latency = start.elapsed().as_secs_f64();
+    let status = response.status().as_u16().to_string();
+
+    if path != "/metrics" && path != "/metrics.json" {
+        let labels = [
+            ("method", method.to_string()),
+            ("path", path),
+            ("status", status),
+        ];
+
+        counter!("http_requests_total", &labels).increment(1);
+        histogram!("http_requests_duration_seconds", &labels).record(latency);
+    }
+
+    response
+}
+
+/// Counts the request as canceled if it's dropped before the handler finished
+///
+/// Hyper drops the handler when the client disconnects, which also drops the queries it was
+/// waiting on instead of reading results nobody will see. This makes those requests visible.
+struct CancelGuard {
+    labels: Option<[(&'static str, String); 2]>,
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if let Some(labels) = self.labels.take() {
+            counter!("http_requests_canceled_total", &labels).increment(1);
+        }
+    }
+}
+
+/// Number of requests a router is handling right now
+///
+/// The same count as the `http_requests_in_flight` gauge, which can't be read back from the
+/// recorder. Unlike the gauge it only counts the requests of its own router.
+#[derive(Debug, Clone, Default)]
+pub struct InFlight(Arc<AtomicI64>);
+
+impl InFlight {
+    pub fn count(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Counts the request in `http_requests_in_flight` for as long as it's alive
+///
+/// The count only goes down on drop, so requests that panic or are canceled by the client
+/// disconnecting don't stay counted forever.
+struct InFlightGuard(InFlight);
+
+impl InFlightGuard {
+    fn new(in_flight: InFlight) -> Self {
+        gauge!("http_requests_in_flight").increment(1);
+        in_flight.0.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard(in_flight)
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        gauge!("http_requests_in_flight").decrement(1);
+        let InFlightGuard(in_flight) = self;
+        in_flight.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,68 @@
+use crate::data::{SearchRanking, SearchResult, MAX_SEARCH_LIMIT};
+use crate::{DataSource, DropsError};
+use axum::extract::Json;
+use axum::response::IntoResponse;
+use axum::Extension;
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use tracing::instrument;
+
+/// Maximum number of names that can be searched for in a single batch
+pub const MAX_BATCH_SEARCHES: usize = 32;
+/// Number of matches per name by default, enough to pick the right player from
+const DEFAULT_BATCH_LIMIT: usize = 5;
+/// Number of searches running at the same time
+const BATCH_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SearchBatchRequest {
+    /// The names to search for, duplicates are only searched once
+    pub searches: Vec<String>,
+    #[serde(default)]
+    pub rank: SearchRanking,
+    /// Number of matches per name
+    pub limit: Option<usize>,
+}
+
+impl SearchBatchRequest {
+    pub fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(DEFAULT_BATCH_LIMIT)
+            .clamp(1, MAX_SEARCH_LIMIT)
+    }
+}
+
+/// Search for multiple names at once, for entering a whole roster
+///
+/// The matches are keyed by the name they were searched for.
+#[instrument(skip(data_source))]
+pub async fn api_search_batch(
+    Extension(data_source): Extension<DataSource>,
+    Json(request): Json<SearchBatchRequest>,
+) -> Result<impl IntoResponse, DropsError> {
+    if request.searches.len() > MAX_BATCH_SEARCHES {
+        return Err(DropsError::TooManySearches);
+    }
+
+    let limit = request.limit();
+    let rank = request.rank;
+    let mut searches = request.searches;
+    searches.sort_unstable();
+    searches.dedup();
+
+    let results: BTreeMap<String, Vec<SearchResult>> = stream::iter(searches)
+        .map(|search| {
+            let data_source = &data_source;
+            async move {
+                let matches = data_source.player_search(&search, rank, limit, 0).await?;
+                Ok::<_, DropsError>((search, matches))
+            }
+        })
+        .buffer_unordered(BATCH_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+    Ok(Json(results))
+}
@@ -0,0 +1,27 @@
+use serde::Serialize;
+
+/// Branding of the pages, for instances that aren't hosted as drops.tf
+///
+/// Pages get it from their [`crate::PageContext`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Site {
+    /// Shown in the title, banners and footer of every page
+    pub name: String,
+    /// Public url of the site without a trailing slash, for the links shared outside the site
+    pub url: String,
+    /// Url of an icon used instead of the default one
+    pub logo: Option<String>,
+    /// Text shown in the footer instead of the credits
+    pub footer: Option<String>,
+}
+
+impl Default for Site {
+    fn default() -> Self {
+        Site {
+            name: "drops.tf".into(),
+            url: "https://drops.tf".into(),
+            logo: None,
+            footer: None,
+        }
+    }
+}
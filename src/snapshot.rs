@@ -0,0 +1,43 @@
+use crate::data::DropStats;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("Failed to read or write the cache snapshot: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid cache snapshot: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// The player stats cache as stored on disk between restarts
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PlayerCacheSnapshot {
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    /// Wall clock time the stats were computed, the monotonic clock doesn't survive a restart
+    pub cached_at: DateTime<Utc>,
+    pub stats: DropStats,
+}
+
+impl PlayerCacheSnapshot {
+    pub fn read(path: &Path) -> Result<Self, SnapshotError> {
+        let data = fs::read(path)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Write the snapshot to a temporary file first, so a crash halfway doesn't leave a broken one
+    pub fn write(&self, path: &Path) -> Result<(), SnapshotError> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
@@ -1,37 +1,135 @@
-use serde::{Serialize, Serializer};
+use serde::de::{self, Unexpected, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use sqlx::error::BoxDynError;
 use sqlx::{Database, Decode, Type};
 use std::convert::TryFrom;
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use std::str::FromStr;
-use steamid_ng::SteamID;
+use steamid_ng::{AccountType, SteamID, SteamIDError, Universe};
+use thiserror::Error;
+
+/// Steam64 id of the individual account with account id 0
+const INDIVIDUAL_BASE: u64 = 76561197960265728;
+
+/// Friend invite codes are the hex account id with every hex digit substituted by these letters
+const INVITE_CODE_ALPHABET: &[u8; 16] = b"bcdfghjkmnpqrtvw";
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
 #[repr(transparent)]
 pub struct SteamId(u64);
 
+/// The format a steam id is shown in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdFormat {
+    /// `STEAM_0:0:32114630`
+    Steam2,
+    /// `[U:1:64229260]`
+    Steam3,
+    /// `76561198024494988`
+    #[default]
+    Steam64,
+}
+
 impl SteamId {
     pub const fn new(id: u64) -> SteamId {
         SteamId(id)
     }
 
+    pub const fn as_u64(&self) -> u64 {
+        self.0
+    }
+
+    pub fn as_steamid(&self) -> SteamID {
+        SteamID::from(self.0)
+    }
+
     pub fn steam3(&self) -> String {
-        SteamID::from(self.0).steam3()
+        self.as_steamid().steam3()
     }
 
     pub fn steam2(&self) -> String {
-        SteamID::from(self.0).steam2()
+        self.as_steamid().steam2()
+    }
+
+    pub fn format(&self, format: IdFormat) -> String {
+        match format {
+            IdFormat::Steam2 => self.steam2(),
+            IdFormat::Steam3 => self.steam3(),
+            IdFormat::Steam64 => self.to_string(),
+        }
     }
 
     pub fn from_steam3(s: &str) -> Result<Self, steamid_ng::SteamIDError> {
         let id = SteamID::from_steam3(s)?;
         Ok(SteamId(id.into()))
     }
+
+    /// Parse any of the formats users paste to refer to a steam account
+    ///
+    /// In addition to the formats supported by `FromStr` this accepts links to steam profiles and
+    /// friend invite links (`s.team/p/cvcw-dgh` or `steamcommunity.com/user/cvcw-dgh`)
+    pub fn from_any(input: &str) -> Result<Self, SteamIDError> {
+        let input = input.trim();
+        let link = input
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_end_matches('/');
+        let invite_code = link
+            .strip_prefix("s.team/p/")
+            .or_else(|| link.strip_prefix("steamcommunity.com/user/"));
+        if let Some(invite_code) = invite_code {
+            // invite links can have an additional token after the code
+            let code = invite_code.split('/').next().unwrap_or_default();
+            if let Some(steam_id) = SteamId::from_invite_code(code) {
+                return Ok(steam_id);
+            }
+        }
+        if let Some(profile) = link.strip_prefix("steamcommunity.com/profiles/") {
+            return profile.parse();
+        }
+        input.parse()
+    }
+
+    /// Whether this is the id of an individual account in the public universe
+    ///
+    /// Clan, game server and other account types can be parsed but never have any stats.
+    pub fn is_valid_individual(&self) -> bool {
+        let id = self.as_steamid();
+        matches!(id.account_type(), AccountType::Individual)
+            && matches!(id.universe(), Universe::Public)
+            && id.account_id() != 0
+    }
+
+    /// Decode a friend invite code like `cvcw-dgh` into the steam id of the individual account
+    pub fn from_invite_code(code: &str) -> Option<Self> {
+        let mut account_id: u32 = 0;
+        let mut digits = 0;
+        for c in code.bytes().filter(|c| *c != b'-') {
+            let digit = INVITE_CODE_ALPHABET
+                .iter()
+                .position(|valid| *valid == c.to_ascii_lowercase())?;
+            account_id = account_id.checked_mul(16)?.checked_add(digit as u32)?;
+            digits += 1;
+        }
+        if digits == 0 {
+            None
+        } else {
+            Some(SteamId(INDIVIDUAL_BASE + account_id as u64))
+        }
+    }
 }
 
 impl Debug for SteamId {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        SteamID::from(self.0).fmt(f)
+        self.as_steamid().fmt(f)
+    }
+}
+
+/// The steam64 id, the same as the serialized form
+impl Display for SteamId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
     }
 }
 
@@ -40,7 +138,43 @@ impl Serialize for SteamId {
     where
         S: Serializer,
     {
-        serializer.collect_str(&self.0)
+        serializer.collect_str(self)
+    }
+}
+
+/// Accepts the serialized steam64 id, as a string or a number, or any other format supported by
+/// `FromStr`
+impl<'de> Deserialize<'de> for SteamId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(SteamIdVisitor)
+    }
+}
+
+struct SteamIdVisitor;
+
+impl Visitor<'_> for SteamIdVisitor {
+    type Value = SteamId;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a steam64, steam3 or steam2 id")
+    }
+
+    fn visit_u64<E: de::Error>(self, id: u64) -> Result<SteamId, E> {
+        Ok(SteamId(id))
+    }
+
+    fn visit_i64<E: de::Error>(self, id: i64) -> Result<SteamId, E> {
+        u64::try_from(id)
+            .map(SteamId)
+            .map_err(|_| E::invalid_value(Unexpected::Signed(id), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, id: &str) -> Result<SteamId, E> {
+        id.parse()
+            .map_err(|_| E::invalid_value(Unexpected::Str(id), &self))
     }
 }
 
@@ -62,6 +196,18 @@ impl From<SteamId> for u64 {
     }
 }
 
+impl From<&SteamId> for u64 {
+    fn from(id: &SteamId) -> Self {
+        id.0
+    }
+}
+
+impl From<&SteamId> for SteamID {
+    fn from(id: &SteamId) -> Self {
+        id.as_steamid()
+    }
+}
+
 impl FromStr for SteamId {
     type Err = steamid_ng::SteamIDError;
 
@@ -85,6 +231,17 @@ where
     }
 }
 
+/// A steam id column in the database that doesn't hold a valid steam3 id
+///
+/// sqlx adds the index of the column, this adds the value so the dirty row can be found.
+#[derive(Debug, Error)]
+#[error("invalid steam3 id {value:?} in the database: {source}")]
+pub struct InvalidSteamId {
+    pub value: String,
+    #[source]
+    pub source: SteamIDError,
+}
+
 impl<'r, DB> Decode<'r, DB> for SteamId
 where
     DB: Database,
@@ -92,6 +249,12 @@ where
 {
     fn decode(value: DB::ValueRef<'r>) -> Result<Self, BoxDynError> {
         let str = <&str as Decode<DB>>::decode(value)?;
-        Ok(Self::from_steam3(str)?)
+        Self::from_steam3(str).map_err(|source| {
+            InvalidSteamId {
+                value: str.into(),
+                source,
+            }
+            .into()
+        })
     }
 }
@@ -1,9 +1,9 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::error::BoxDynError;
 use sqlx::{Database, Decode, Type};
 use std::fmt::{Debug, Display, Formatter};
 
-#[derive(Clone, PartialEq, Eq, Hash, Default, Serialize)]
+#[derive(Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 #[repr(transparent)]
 pub struct SmolStr(smol_str::SmolStr);
 
@@ -27,6 +27,10 @@ impl SmolStr {
     pub const fn new_inline(str: &str) -> Self {
         SmolStr(smol_str::SmolStr::new_inline(str))
     }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
 }
 
 impl<'a> From<&'a str> for SmolStr {
@@ -0,0 +1,128 @@
+use crate::data::DropStats;
+use crate::ratio::{format_ratio, Rounding, DEFAULT_PRECISION, MAX_PRECISION};
+use crate::site::Site;
+use serde::Deserialize;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SummaryFormat {
+    BbCode,
+    #[default]
+    Markdown,
+}
+
+/// Unknown parameters are allowed here like on the profile page, the summary links are shared
+/// in chats just as often
+#[derive(Debug, Deserialize)]
+pub struct SummaryParams {
+    #[serde(default)]
+    pub format: SummaryFormat,
+    /// Number of decimals for the ratios, capped at [`MAX_PRECISION`]
+    #[serde(default = "default_precision")]
+    pub precision: usize,
+    #[serde(default)]
+    pub rounding: Rounding,
+}
+
+impl Default for SummaryParams {
+    fn default() -> Self {
+        SummaryParams {
+            format: SummaryFormat::default(),
+            precision: DEFAULT_PRECISION,
+            rounding: Rounding::default(),
+        }
+    }
+}
+
+fn default_precision() -> usize {
+    DEFAULT_PRECISION
+}
+
+/// Format the stats of a player as a block that can be pasted in forum posts or signatures
+///
+/// Markdown:
+///
+/// ```text
+/// **Icewind** - [drops\.tf](https://drops.tf/profile/76561198024494988)
+/// - Drops: 100 (#1)
+/// - Drops per game: 10.00 (#4)
+/// - Drops per hour: 3600.00 (#3)
+/// - Drops per uber: 2.00 (#2)
+/// ```
+///
+/// BBCode:
+///
+/// ```text
+/// [b]Icewind[/b] - [url=https://drops.tf/profile/76561198024494988]drops.tf[/url]
+/// Drops: 100 (#1)
+/// Drops per game: 10.00 (#4)
+/// Drops per hour: 3600.00 (#3)
+/// Drops per uber: 2.00 (#2)
+/// ```
+pub fn format_summary(stats: &DropStats, site: &Site, params: &SummaryParams) -> String {
+    let format = params.format;
+    let ratio = |value| format_ratio(value, params.precision.min(MAX_PRECISION), params.rounding);
+    let url = stats.profile_url(site);
+    let lines = [
+        ("Drops", stats.drops.to_string(), stats.drops_rank),
+        ("Drops per game", ratio(stats.dpg()), stats.dpg_rank),
+        ("Drops per hour", ratio(stats.dph()), stats.dps_rank),
+        ("Drops per uber", ratio(stats.dpu()), stats.dpu_rank),
+    ];
+
+    let site = &site.name;
+    let mut summary = match format {
+        SummaryFormat::Markdown => format!(
+            "**{}** - [{}]({url})\n",
+            escape_markdown(stats.name.as_str()),
+            escape_markdown(site)
+        ),
+        SummaryFormat::BbCode => format!(
+            "[b]{}[/b] - [url={url}]{}[/url]\n",
+            escape_bbcode(stats.name.as_str()),
+            escape_bbcode(site)
+        ),
+    };
+    for (label, value, rank) in lines {
+        let bullet = match format {
+            SummaryFormat::Markdown => "- ",
+            SummaryFormat::BbCode => "",
+        };
+        let _ = writeln!(summary, "{bullet}{label}: {value} (#{rank})");
+    }
+    summary
+}
+
+/// Backslash escape everything that markdown could interpret as formatting
+///
+/// Line breaks and other control characters become spaces, so a name can't start a line of its
+/// own that would be read as another stat.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_control() {
+            escaped.push(' ');
+            continue;
+        }
+        if "\\`*_{}[]()<>#+-.!|~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// BBCode has no escape sequences, so replace the brackets that could open a tag
+///
+/// Control characters become spaces like they do for markdown.
+fn escape_bbcode(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '[' => '(',
+            ']' => ')',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect()
+}
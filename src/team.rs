@@ -0,0 +1,124 @@
+use crate::data::DropStats;
+use crate::ratio::{per_hour, ratio};
+use crate::{player_stats, ClientIp, DataSource, DropsError, SteamId};
+use axum::extract::Json;
+use axum::response::IntoResponse;
+use axum::Extension;
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::instrument;
+
+/// Maximum number of players that can be requested for a single team
+pub const MAX_TEAM_SIZE: usize = 32;
+/// Number of players looked up at the same time
+const TEAM_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TeamSummaryRequest {
+    /// Steam ids, profile links or vanity urls of the players
+    pub players: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamSummary {
+    pub totals: TeamTotals,
+    pub members: Vec<DropStats>,
+    /// The requested players for which no stats are known, these don't count towards the totals
+    pub not_found: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TeamTotals {
+    pub drops: i64,
+    pub ubers: i64,
+    pub games: i64,
+    pub medic_time: i64,
+    /// Drops per hour, named `dpm` to keep the api stable
+    pub dpm: f64,
+    pub dpu: f64,
+    pub dpg: f64,
+}
+
+impl TeamTotals {
+    fn new(members: &[DropStats]) -> Self {
+        let mut totals = TeamTotals::default();
+        for member in members {
+            totals.drops += member.drops;
+            totals.ubers += member.ubers;
+            totals.games += member.games;
+            totals.medic_time += member.medic_time;
+        }
+        totals.dpm = per_hour(totals.drops, totals.medic_time);
+        totals.dpu = ratio(totals.drops, totals.ubers);
+        totals.dpg = ratio(totals.drops, totals.games);
+        totals
+    }
+}
+
+/// Combined stats for a team roster
+#[instrument(skip(data_source))]
+pub async fn api_team_summary(
+    Extension(data_source): Extension<DataSource>,
+    client: ClientIp,
+    Json(request): Json<TeamSummaryRequest>,
+) -> Result<impl IntoResponse, DropsError> {
+    if request.players.len() > MAX_TEAM_SIZE {
+        return Err(DropsError::TeamTooLarge);
+    }
+
+    // resolve all vanity urls up front instead of one request at a time
+    let vanity_urls: Vec<&str> = request
+        .players
+        .iter()
+        .map(String::as_str)
+        .filter(|player| SteamId::from_any(player).is_err())
+        .collect();
+    // the urls that did resolve are stored, so trying again only asks steam for the failed ones
+    let mut resolved = HashMap::new();
+    for (url, steam_id) in data_source
+        .resolve_vanity_urls(&vanity_urls, client)
+        .await?
+    {
+        resolved.insert(url, steam_id?);
+    }
+
+    let results: Vec<_> = stream::iter(request.players)
+        .map(|player| {
+            let data_source = &data_source;
+            let steam_id = SteamId::from_any(&player)
+                .ok()
+                .or_else(|| resolved.get(&player).copied().flatten());
+            async move {
+                let stats = match steam_id {
+                    Some(steam_id) if steam_id.is_valid_individual() => {
+                        player_stats(data_source, steam_id).await
+                    }
+                    _ => Err(DropsError::UserNotFound),
+                };
+                (player, stats)
+            }
+        })
+        .buffered(TEAM_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut members = Vec::with_capacity(results.len());
+    let mut not_found = Vec::new();
+    for (player, stats) in results {
+        match stats {
+            Ok(stats) => members.push(stats),
+            Err(
+                DropsError::UserNotFound | DropsError::PlayerGone | DropsError::NoMedicStats { .. },
+            ) => not_found.push(player),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(Json(TeamSummary {
+        totals: TeamTotals::new(&members),
+        members,
+        not_found,
+    }))
+}
@@ -0,0 +1,137 @@
+use axum::extract::{FromRequestParts, Query, Request};
+use axum::http::header::{COOKIE, SET_COOKIE, VARY};
+use axum::http::request::Parts;
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Name of the cookie the picked theme is stored in
+pub const THEME_COOKIE: &str = "theme";
+
+/// How long the picked theme is remembered
+const THEME_COOKIE_MAX_AGE: u64 = 365 * 24 * 60 * 60;
+
+/// Color scheme of the pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// Follow the `prefers-color-scheme` of the browser
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Theme::System => "system",
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        })
+    }
+}
+
+impl FromStr for Theme {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "system" => Ok(Theme::System),
+            "light" => Ok(Theme::Light),
+            "dark" => Ok(Theme::Dark),
+            _ => Err(()),
+        }
+    }
+}
+
+tokio::task_local! {
+    /// The theme for the request being handled
+    ///
+    /// Like the maintenance flag this isn't passed into the templates, so the error page, which
+    /// is rendered without access to the request, is themed too.
+    static THEME: Theme;
+}
+
+/// The theme of the page being rendered, [`Theme::System`] outside of a themed request
+pub fn current_theme() -> Theme {
+    THEME.try_with(|theme| *theme).unwrap_or_default()
+}
+
+/// Run `f` with `theme` as the current theme, for rendering outside of [`theme_layer`]
+pub fn with_theme<R>(theme: Theme, f: impl FnOnce() -> R) -> R {
+    THEME.sync_scope(theme, f)
+}
+
+/// Unknown parameters are allowed, the pages have parameters of their own
+#[derive(Debug, Deserialize)]
+struct ThemeParams {
+    theme: Option<Theme>,
+}
+
+/// The theme picked with `?theme=`, if any
+fn requested_theme(parts: &Parts) -> Option<Theme> {
+    Query::<ThemeParams>::try_from_uri(&parts.uri)
+        .ok()
+        .and_then(|Query(params)| params.theme)
+}
+
+/// The theme stored in the cookie, invalid values are ignored
+fn cookie_theme(parts: &Parts) -> Option<Theme> {
+    parts
+        .headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == THEME_COOKIE)
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+/// The theme picked with `?theme=`, falling back to the one stored in the cookie
+impl<S: Send + Sync> FromRequestParts<S> for Theme {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(requested_theme(parts)
+            .or_else(|| cookie_theme(parts))
+            .unwrap_or_default())
+    }
+}
+
+/// Mark a response as depending on the theme cookie, so shared caches don't serve one visitor's
+/// theme to everyone
+pub fn vary_on_theme(response: &mut Response) {
+    response
+        .headers_mut()
+        .append(VARY, HeaderValue::from_static("Cookie"));
+}
+
+/// Render the pages with the theme of the visitor, remembering a theme picked with `?theme=`
+pub async fn theme_layer(req: Request, next: Next) -> Response {
+    let (parts, body) = req.into_parts();
+    let requested = requested_theme(&parts);
+    let theme = requested
+        .or_else(|| cookie_theme(&parts))
+        .unwrap_or_default();
+
+    let mut response = THEME
+        .scope(theme, next.run(Request::from_parts(parts, body)))
+        .await;
+    vary_on_theme(&mut response);
+
+    if let Some(theme) = requested {
+        let cookie =
+            format!("{THEME_COOKIE}={theme}; Path=/; Max-Age={THEME_COOKIE_MAX_AGE}; SameSite=Lax");
+        if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+            response.headers_mut().append(SET_COOKIE, cookie);
+        }
+    }
+    response
+}
@@ -0,0 +1,117 @@
+//! Stale-while-revalidate behaviour of the cache used for the hottest queries
+
+use dropstf::RefreshingCache;
+use moka::future::Cache;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+#[tokio::test]
+async fn test_concurrent_refresh_runs_once_without_blocking() {
+    let cache = RefreshingCache::new(Cache::builder().build(), Duration::from_millis(50));
+    let queries = Arc::new(AtomicUsize::new(0));
+    // the refresh can't finish until the test adds a permit
+    let gate = Arc::new(Semaphore::new(0));
+
+    let init = {
+        let queries = queries.clone();
+        let gate = gate.clone();
+        move || {
+            let queries = queries.clone();
+            let gate = gate.clone();
+            async move {
+                let query = queries.fetch_add(1, Ordering::SeqCst);
+                if query > 0 {
+                    gate.acquire().await.unwrap().forget();
+                }
+                Ok::<_, std::fmt::Error>(query)
+            }
+        }
+    };
+
+    assert_eq!(cache.try_get_with((), init.clone()).await.unwrap(), 0);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // all lookups during the refresh get the old value while the refresh is still blocked
+    let lookups: Vec<_> = (0..32)
+        .map(|_| {
+            let cache = cache.clone();
+            let init = init.clone();
+            tokio::spawn(async move { cache.try_get_with((), init).await.unwrap() })
+        })
+        .collect();
+    for lookup in lookups {
+        let value = tokio::time::timeout(Duration::from_secs(1), lookup)
+            .await
+            .expect("lookup blocked on the refresh")
+            .unwrap();
+        assert_eq!(value, 0);
+    }
+    assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+    gate.add_permits(1);
+    for _ in 0..100 {
+        if cache.try_get_with((), init.clone()).await.unwrap() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(cache.try_get_with((), init.clone()).await.unwrap(), 1);
+    assert_eq!(queries.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_aged_entries_are_refreshed() {
+    let cache = RefreshingCache::new(Cache::builder().build(), Duration::from_secs(60));
+    cache.insert_aged(1, "fresh", Duration::from_secs(10)).await;
+    cache
+        .insert_aged(2, "stale", Duration::from_secs(120))
+        .await;
+
+    let mut entries = cache.entries();
+    entries.sort_by_key(|(key, _, _)| *key);
+    assert_eq!(entries.len(), 2);
+    assert!(entries[0].2 >= Duration::from_secs(10) && entries[0].2 < Duration::from_secs(60));
+    assert!(entries[1].2 >= Duration::from_secs(120));
+
+    let refresh = || async { Ok::<_, std::fmt::Error>("refreshed") };
+    // both are served from the cache, but only the stale one is refreshed in the background
+    assert_eq!(cache.try_get_with(1, refresh).await.unwrap(), "fresh");
+    assert_eq!(cache.try_get_with(2, refresh).await.unwrap(), "stale");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(cache.try_get_with(1, refresh).await.unwrap(), "fresh");
+    assert_eq!(cache.try_get_with(2, refresh).await.unwrap(), "refreshed");
+}
+
+#[tokio::test]
+async fn test_panicking_refresh_is_retried() {
+    let cache = RefreshingCache::new(Cache::builder().build(), Duration::from_millis(50));
+    let queries = Arc::new(AtomicUsize::new(0));
+    let init = {
+        let queries = queries.clone();
+        move || {
+            let queries = queries.clone();
+            async move {
+                let query = queries.fetch_add(1, Ordering::SeqCst);
+                if query == 1 {
+                    panic!("refresh failed");
+                }
+                Ok::<_, std::fmt::Error>(query)
+            }
+        }
+    };
+
+    assert_eq!(cache.try_get_with((), init.clone()).await.unwrap(), 0);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    // starts the refresh that panics
+    assert_eq!(cache.try_get_with((), init.clone()).await.unwrap(), 0);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(cache.refreshing(), 0);
+
+    // the next lookup starts another refresh instead of the key being stuck
+    assert_eq!(cache.try_get_with((), init.clone()).await.unwrap(), 0);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(cache.try_get_with((), init.clone()).await.unwrap(), 2);
+    assert_eq!(queries.load(Ordering::SeqCst), 3);
+}
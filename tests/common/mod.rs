@@ -0,0 +1,134 @@
+//! Helpers shared between the integration tests
+//!
+//! Every test file only uses some of these, the rest would warn as unused in that file.
+#![allow(dead_code)]
+
+use axum::Router;
+use dropstf::{app, DataSource, DropStats, RouterOptions, SmolStr, SteamId};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::PgPool;
+use std::str::FromStr;
+use tokio::sync::OnceCell;
+
+static FIXTURES: OnceCell<()> = OnceCell::const_new();
+
+/// Connect to the postgres database in `TEST_DATABASE_URL`, `None` when it isn't set so the test
+/// can be skipped
+///
+/// With a `schema` the tables are looked up in that schema first, so a test can have tables of
+/// its own without disturbing the fixtures of the other tests.
+pub async fn connect(schema: Option<&str>) -> Option<PgPool> {
+    let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+        eprintln!("TEST_DATABASE_URL not set, skipping");
+        return None;
+    };
+    let mut options = PgConnectOptions::from_str(&database_url).unwrap();
+    if let Some(schema) = schema {
+        options = options.options([("search_path", format!("{schema},public"))]);
+    }
+    let pool = PgPoolOptions::new().connect_with(options).await.unwrap();
+    if let Some(schema) = schema {
+        sqlx::raw_sql(&format!("CREATE SCHEMA IF NOT EXISTS {schema}"))
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+    Some(pool)
+}
+
+/// Connect like [`connect`] and fill the database with `tests/fixtures.sql`, migrated with the
+/// scripts from `sql/`, followed by `setup`
+///
+/// The tests run in parallel, so the database is only reset once per test file.
+pub async fn test_pool(schema: Option<&str>, setup: &[&str]) -> Option<PgPool> {
+    let pool = connect(schema).await?;
+    FIXTURES
+        .get_or_init(|| async {
+            for script in [
+                include_str!("../fixtures.sql"),
+                include_str!("../../sql/deleted_players.sql"),
+                include_str!("../../sql/logs_raw_players.sql"),
+                include_str!("../../sql/logs_raw_date.sql"),
+                include_str!("../../sql/vanity_resolved_at.sql"),
+                include_str!("../../sql/medic_names_normalized.sql"),
+            ]
+            .iter()
+            .chain(setup)
+            {
+                sqlx::raw_sql(script).execute(&pool).await.unwrap();
+            }
+        })
+        .await;
+    Some(pool)
+}
+
+/// The whole site on top of `data_source`, ready to serve right away
+pub fn test_app(data_source: DataSource, options: RouterOptions) -> Router {
+    options.readiness.set_ready();
+    let metrics = PrometheusBuilder::new().build_recorder().handle();
+    app(data_source, metrics, options)
+}
+
+/// Stats for a ranked medic to build on, the #1 on every leaderboard
+pub fn stats() -> StatsBuilder {
+    StatsBuilder(DropStats {
+        steam_id: SteamId::new(76561198024494988),
+        name: SmolStr::new_inline("Icewind"),
+        drops: 150,
+        ubers: 1000,
+        games: 300,
+        medic_time: 360000,
+        drops_rank: 1,
+        dpu_rank: 1,
+        dps_rank: 1,
+        dpg_rank: 1,
+        last_active: None,
+    })
+}
+
+pub struct StatsBuilder(DropStats);
+
+impl StatsBuilder {
+    pub fn name(mut self, name: &str) -> Self {
+        self.0.name = SmolStr::new(name);
+        self
+    }
+
+    pub fn drops(mut self, drops: i64) -> Self {
+        self.0.drops = drops;
+        self
+    }
+
+    pub fn ubers(mut self, ubers: i64) -> Self {
+        self.0.ubers = ubers;
+        self
+    }
+
+    pub fn games(mut self, games: i64) -> Self {
+        self.0.games = games;
+        self
+    }
+
+    pub fn medic_time(mut self, medic_time: i64) -> Self {
+        self.0.medic_time = medic_time;
+        self
+    }
+
+    /// The same rank on every leaderboard
+    pub fn rank(self, rank: i64) -> Self {
+        self.ranks(rank, rank, rank, rank)
+    }
+
+    pub fn ranks(mut self, drops: i64, dpu: i64, dps: i64, dpg: i64) -> Self {
+        self.0.drops_rank = drops;
+        self.0.dpu_rank = dpu;
+        self.0.dps_rank = dps;
+        self.0.dpg_rank = dpg;
+        self
+    }
+
+    pub fn build(self) -> DropStats {
+        self.0
+    }
+}
@@ -0,0 +1,165 @@
+use axum::body::{to_bytes, Body, Bytes, HttpBody};
+use axum::http::header::{
+    ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY,
+};
+use axum::http::{HeaderMap, HeaderValue, Request, StatusCode};
+use axum::routing::get;
+use axum::{middleware, Router};
+use dropstf::{compress_response, preferred_encoding, Encoding};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use hyper::body::{Frame, SizeHint};
+use std::io::Read;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::ServiceExt;
+
+fn text() -> String {
+    "medic dropped uber ".repeat(100)
+}
+
+/// A body that claims a size but fails to be read, like a proxied response that's cut off
+struct BrokenBody;
+
+impl HttpBody for BrokenBody {
+    type Data = Bytes;
+    type Error = std::io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        Poll::Ready(Some(Err(std::io::Error::other("connection reset"))))
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(4096)
+    }
+}
+
+fn router() -> Router {
+    Router::new()
+        .route("/text", get(|| async { text() }))
+        .route("/small", get(|| async { "medic" }))
+        .route(
+            "/png",
+            get(|| async { ([(CONTENT_TYPE, "image/png")], text()) }),
+        )
+        .route(
+            "/broken",
+            get(|| async {
+                (
+                    [(CONTENT_TYPE, "text/plain"), (CONTENT_LENGTH, "4096")],
+                    Body::new(BrokenBody),
+                )
+            }),
+        )
+        .route(
+            "/no-transform",
+            get(|| async { ([(CACHE_CONTROL, "public, no-transform")], text()) }),
+        )
+        .layer(middleware::from_fn(compress_response))
+}
+
+async fn fetch(uri: &str, accept: &str) -> (Option<String>, Vec<String>, Vec<u8>) {
+    let response = router()
+        .oneshot(
+            Request::get(uri)
+                .header(ACCEPT_ENCODING, accept)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let encoding = response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .map(|value| value.to_str().unwrap().to_string());
+    let vary = response
+        .headers()
+        .get_all(VARY)
+        .iter()
+        .map(|value| value.to_str().unwrap().to_string())
+        .collect();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (encoding, vary, body.to_vec())
+}
+
+#[test]
+fn test_preferred_encoding() {
+    let preferred = |accept: &str| {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_str(accept).unwrap());
+        preferred_encoding(&headers)
+    };
+
+    assert_eq!(preferred("gzip, deflate, br"), Some(Encoding::Gzip));
+    assert_eq!(preferred("deflate, gzip"), Some(Encoding::Gzip));
+    assert_eq!(preferred("deflate"), Some(Encoding::Deflate));
+    assert_eq!(preferred("gzip;q=0.5, deflate"), Some(Encoding::Deflate));
+    assert_eq!(preferred("gzip;q=0, deflate;q=0"), None);
+    assert_eq!(preferred("br, identity"), None);
+    assert_eq!(preferred("*"), Some(Encoding::Gzip));
+    // the wildcard doesn't override an encoding the client refused
+    assert_eq!(preferred("gzip;q=0, *"), Some(Encoding::Deflate));
+    assert_eq!(preferred("*, gzip;q=0"), Some(Encoding::Deflate));
+    assert_eq!(preferred("gzip;q=0, deflate;q=0, *"), None);
+    assert_eq!(preferred("deflate;q=0.5, *;q=0.8"), Some(Encoding::Gzip));
+}
+
+#[tokio::test]
+async fn test_compress() {
+    let (encoding, vary, body) = fetch("/text", "gzip").await;
+    assert_eq!(encoding.as_deref(), Some("gzip"));
+    assert_eq!(vary, ["Accept-Encoding"]);
+    let mut decoded = String::new();
+    GzDecoder::new(body.as_slice())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, text());
+
+    let (encoding, _, body) = fetch("/text", "br, deflate").await;
+    assert_eq!(encoding.as_deref(), Some("deflate"));
+    let mut decoded = String::new();
+    ZlibDecoder::new(body.as_slice())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, text());
+
+    // the response still depends on the encoding when it's sent as is
+    let (encoding, vary, body) = fetch("/text", "br").await;
+    assert_eq!(encoding, None);
+    assert_eq!(vary, ["Accept-Encoding"]);
+    assert_eq!(body, text().as_bytes());
+}
+
+#[tokio::test]
+async fn test_skip_compression() {
+    for uri in ["/small", "/png", "/no-transform"] {
+        let (encoding, vary, body) = fetch(uri, "gzip, deflate").await;
+        assert_eq!(encoding, None, "{}", uri);
+        assert!(vary.is_empty(), "{}", uri);
+        assert!(body == text().as_bytes() || body == b"medic", "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn test_unreadable_body() {
+    let response = router()
+        .oneshot(
+            Request::get("/broken")
+                .header(ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    // not an empty body that claims to be 4096 bytes
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_ne!(
+        response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .map(|value| value.as_bytes()),
+        Some(b"4096".as_slice())
+    );
+}
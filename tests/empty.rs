@@ -0,0 +1,52 @@
+//! The pages of a fresh instance that hasn't imported any logs yet
+//!
+//! Needs a postgres database in `TEST_DATABASE_URL`, the tables are created empty in their own
+//! schema so the fixtures of the other tests aren't disturbed.
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use dropstf::{DataSource, RouterOptions};
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn test_empty_leaderboard() {
+    let Some(pool) = common::test_pool(
+        Some("empty_leaderboard"),
+        &[
+            "TRUNCATE user_names, medic_names, medic_stats, ranked_medic_stats, global_stats,
+            vanity_urls, logs_raw, deleted_players",
+        ],
+    )
+    .await
+    else {
+        return;
+    };
+    let router = common::test_app(
+        DataSource::new(pool, String::new()),
+        RouterOptions::default(),
+    );
+
+    for uri in ["/", "/top/dpu"] {
+        let response = router
+            .clone()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("No ubers dropped yet."), "{}", body);
+        assert!(
+            body.contains("No medics on the leaderboard yet"),
+            "{}",
+            body
+        );
+        assert!(
+            !body.contains(r#"<table class="leaderboards">"#),
+            "{}",
+            body
+        );
+    }
+}
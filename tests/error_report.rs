@@ -0,0 +1,170 @@
+//! Reporting server errors to the error webhook
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::routing::{get, post};
+use axum::{middleware, Json, Router};
+use dropstf::{report_errors, sanitize_message, DataSource, DropsError, ErrorReporter};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tower::ServiceExt;
+
+/// Start a webhook that forwards everything posted to it
+async fn webhook() -> (String, mpsc::UnboundedReceiver<Value>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let receiver = Router::new().route(
+        "/hook",
+        post(move |Json(report): Json<Value>| async move {
+            tx.send(report).ok();
+        }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, receiver).await });
+    (format!("http://{}/hook", addr), rx)
+}
+
+fn failing_router(reporter: ErrorReporter) -> Router {
+    Router::new()
+        .route(
+            "/broken/{id}",
+            get(|| async {
+                Err::<(), _>(DropsError::from(
+                    serde_json::from_str::<u8>("x").unwrap_err(),
+                ))
+            }),
+        )
+        .route(
+            "/missing",
+            get(|| async { Err::<(), _>(DropsError::NotFound) }),
+        )
+        .route(
+            "/busy",
+            get(|| async { Err::<(), _>(DropsError::Overloaded) }),
+        )
+        .route(
+            "/steam",
+            get(|| async { Err::<(), _>(DropsError::from(steam_request_error().await)) }),
+        )
+        .route_layer(middleware::from_fn_with_state(reporter, report_errors))
+}
+
+/// A failed request with an api key in its url, like the requests to the steam api
+async fn steam_request_error() -> reqwest::Error {
+    reqwest::get("http://127.0.0.1:1/ISteamUser/GetPlayerSummaries/v0002/?key=hunter2")
+        .await
+        .unwrap_err()
+}
+
+async fn status(router: &Router, uri: &str) -> StatusCode {
+    router
+        .clone()
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn test_only_server_errors_reported() {
+    let (url, mut reports) = webhook().await;
+    let router = failing_router(ErrorReporter::new(Some(url)));
+
+    assert_eq!(status(&router, "/missing").await, StatusCode::NOT_FOUND);
+    assert_eq!(
+        status(&router, "/busy").await,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+    assert_eq!(
+        status(&router, "/broken/12?secret=hunter2").await,
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+
+    let report = timeout(Duration::from_secs(5), reports.recv())
+        .await
+        .expect("no report received")
+        .unwrap();
+    assert_eq!(report["status"], 500);
+    assert_eq!(report["method"], "GET");
+    // the matched route, without the ids or query of the request
+    assert_eq!(report["path"], "/broken/{id}");
+    let error = report["error"].as_str().unwrap();
+    assert!(
+        error.starts_with("Error while encoding the response: "),
+        "{}",
+        error
+    );
+
+    // the 404 and 503 were never reported
+    assert!(timeout(Duration::from_millis(200), reports.recv())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_steam_errors_hide_api_key() {
+    let (url, mut reports) = webhook().await;
+    let router = failing_router(ErrorReporter::new(Some(url)));
+
+    assert_eq!(
+        status(&router, "/steam").await,
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+    let report = timeout(Duration::from_secs(5), reports.recv())
+        .await
+        .expect("no report received")
+        .unwrap();
+    let error = report["error"].as_str().unwrap();
+    assert!(
+        error.starts_with("Error while fetching steam profile"),
+        "{}",
+        error
+    );
+    assert!(!error.contains("key="), "{}", error);
+
+    // steam isn't reachable from the tests, so resolving a vanity url fails the same way
+    let error = steam_resolve_vanity::resolve_vanity_url("gabelogannewell", "hunter2")
+        .await
+        .unwrap_err();
+    let message = DropsError::from(error).detailed_message();
+    assert!(!message.contains("key="), "{}", message);
+}
+
+#[tokio::test]
+async fn test_diagnostics_hide_api_key() {
+    // nothing listens there, the diagnostics only need the steam check to fail
+    let pool = PgPool::connect_lazy("postgres://127.0.0.1:1/drops").unwrap();
+    let data_source = DataSource::new(pool, "hunter2".into());
+
+    let steam = data_source.diagnostics().await.steam;
+    assert!(!steam.ok);
+    let error = steam.error.unwrap();
+    assert!(!error.contains("hunter2"), "{}", error);
+}
+
+#[test]
+fn test_invalid_api_key_is_server_error() {
+    // nothing to wait for, the configuration has to be fixed
+    let error = DropsError::from(steam_resolve_vanity::Error::InvalidKey);
+    assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(error.retry_after(), None);
+}
+
+#[tokio::test]
+async fn test_reporting_disabled() {
+    let router = failing_router(ErrorReporter::default());
+    assert_eq!(
+        status(&router, "/broken/12").await,
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+}
+
+#[test]
+fn test_sanitize_message() {
+    assert_eq!(sanitize_message("line\nbreak\u{1b}[31m"), "line break [31m");
+    assert_eq!(sanitize_message(&"a".repeat(5000)).len(), 1000);
+}
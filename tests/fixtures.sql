@@ -0,0 +1,56 @@
+-- Minimal schema and data for the integration tests, replacing whatever is in the test database
+
+DROP TABLE IF EXISTS user_names, medic_names, medic_stats, ranked_medic_stats, global_stats,
+    vanity_urls, logs_raw, deleted_players;
+
+CREATE TABLE user_names (steam_id TEXT PRIMARY KEY, name TEXT NOT NULL);
+CREATE TABLE medic_names (steam_id TEXT NOT NULL, name TEXT NOT NULL, count BIGINT NOT NULL);
+CREATE TABLE medic_stats (
+    steam_id TEXT PRIMARY KEY,
+    games BIGINT NOT NULL,
+    ubers BIGINT NOT NULL,
+    drops BIGINT NOT NULL,
+    medic_time BIGINT NOT NULL,
+    dpu FLOAT8,
+    dps FLOAT8,
+    dpg FLOAT8
+);
+CREATE TABLE ranked_medic_stats (
+    steam_id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    games BIGINT NOT NULL,
+    ubers BIGINT NOT NULL,
+    drops BIGINT NOT NULL,
+    medic_time BIGINT NOT NULL,
+    dpu FLOAT8,
+    dps FLOAT8,
+    dpg FLOAT8,
+    drops_rank BIGINT NOT NULL,
+    dpu_rank BIGINT NOT NULL,
+    dps_rank BIGINT NOT NULL,
+    dpg_rank BIGINT NOT NULL
+);
+CREATE TABLE global_stats (drops BIGINT NOT NULL, ubers BIGINT NOT NULL, games BIGINT NOT NULL);
+CREATE TABLE vanity_urls (url TEXT PRIMARY KEY, steam_id TEXT NOT NULL);
+CREATE TABLE logs_raw (id INT PRIMARY KEY, json JSONB NOT NULL);
+
+-- Scout never played medic
+INSERT INTO user_names VALUES ('[U:1:64229260]', 'Icewind'), ('[U:1:1000]', 'Newbie'),
+    ('[U:1:3000]', 'Scout');
+-- a rarely used name that matches searches for "ice" exactly, and one in fullwidth letters
+INSERT INTO medic_names VALUES ('[U:1:64229260]', 'Icewind', 100), ('[U:1:1000]', 'Newbie', 3),
+    ('[U:1:2000]', 'Ice', 1), ('[U:1:2001]', 'Ice wind', 1), ('[U:1:2002]', 'Ｍｅｄｉｃ', 1);
+INSERT INTO medic_stats VALUES
+    ('[U:1:64229260]', 300, 1000, 150, 360000, 0.15, 0.0004, 0.5),
+    ('[U:1:1000]', 3, 10, 2, 3600, 0.2, 0.0005, 0.66);
+INSERT INTO ranked_medic_stats VALUES
+    ('[U:1:64229260]', 'Icewind', 300, 1000, 150, 360000, 0.15, 0.0004, 0.5, 1, 1, 1, 1);
+INSERT INTO vanity_urls VALUES ('icewind', '[U:1:64229260]');
+INSERT INTO global_stats VALUES (152, 1010, 303);
+INSERT INTO logs_raw VALUES
+    (1, '{"version": 3, "info": {"date": 1700000000}, "players": {
+        "[U:1:64229260]": {"drops": 2, "ubers": 12, "class_stats": [{"type": "medic"}]},
+        "[U:1:3000]": {"drops": 0, "ubers": 0, "class_stats": [{"type": "scout"}]}}}'),
+    -- a log without a medic
+    (3, '{"version": 3, "info": {"date": 1600000000, "map": "koth_product"}, "players": {
+        "[U:1:4000]": {"drops": 0, "ubers": 0, "class_stats": [{"type": "pyro"}]}}}');
@@ -0,0 +1,96 @@
+//! Rounding of the ratios shown on profiles and in the summaries
+
+mod common;
+
+use common::stats;
+use dropstf::{
+    count_to_f64, format_ratio, per_hour, ratio, RateInterval, Rounding, MAX_EXACT_COUNT,
+};
+
+#[test]
+fn test_precisions() {
+    let value = 2.0 / 3.0;
+    let expected = ["1", "0.7", "0.67", "0.667", "0.6667"];
+    for (precision, expected) in expected.iter().enumerate() {
+        assert_eq!(
+            format_ratio(value, precision, Rounding::HalfEven),
+            *expected
+        );
+        assert_eq!(format_ratio(value, precision, Rounding::HalfUp), *expected);
+    }
+}
+
+#[test]
+fn test_ties() {
+    for (value, precision, half_even, half_up) in [
+        (0.125, 2, "0.12", "0.13"),
+        (0.375, 2, "0.38", "0.38"),
+        (0.5, 0, "0", "1"),
+        (2.5, 0, "2", "3"),
+        (0.0625, 3, "0.062", "0.063"),
+        (0.0625, 4, "0.0625", "0.0625"),
+        // not actually a tie, 1.005 is stored as 1.00499999...
+        (1.005, 2, "1.00", "1.00"),
+    ] {
+        assert_eq!(
+            format_ratio(value, precision, Rounding::HalfEven),
+            half_even,
+            "{value} at {precision}"
+        );
+        assert_eq!(
+            format_ratio(value, precision, Rounding::HalfUp),
+            half_up,
+            "{value} at {precision}"
+        );
+    }
+}
+
+#[test]
+fn test_default_matches_format() {
+    // 1 drop in 8 ubers
+    let stats = stats().drops(1).ubers(8).build();
+    assert_eq!(stats.dpu_with_precision(2), format!("{:.2}", stats.dpu()));
+    assert_eq!(stats.dpu_with_precision(2), "0.12");
+    assert_eq!(stats.dpu_with_precision(0), "0");
+    assert_eq!(stats.dpu_with_precision(4), "0.1250");
+}
+
+#[test]
+fn test_count_conversion() {
+    assert_eq!(count_to_f64(0), 0.0);
+    assert_eq!(count_to_f64(MAX_EXACT_COUNT), 9007199254740992.0);
+    assert_eq!(
+        count_to_f64(MAX_EXACT_COUNT - 1) as i64,
+        MAX_EXACT_COUNT - 1
+    );
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "can't be converted to a float exactly")]
+fn test_count_conversion_too_large() {
+    count_to_f64(MAX_EXACT_COUNT + 1);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "can't be converted to a float exactly")]
+fn test_count_conversion_negative() {
+    count_to_f64(-1);
+}
+
+#[test]
+fn test_ratios() {
+    assert_eq!(ratio(150, 1000), 0.15);
+    assert_eq!(ratio(150, 0), 0.0);
+    assert_eq!(per_hour(150, 360000), 1.5);
+    assert_eq!(per_hour(150, 0), 0.0);
+
+    let stats = stats().drops(3).ubers(0).games(10).medic_time(3600).build();
+    assert_eq!(stats.dpu(), 0.0);
+    assert_eq!(stats.dpg(), 0.3);
+    assert_eq!(stats.dph(), 3.0);
+    assert_eq!(stats.drops_per_30(), 1.5);
+    assert_eq!(RateInterval::HalfHour.rate(150, 360000), 0.75);
+    assert_eq!(RateInterval::HalfHour.rate(150, 0), 0.0);
+}
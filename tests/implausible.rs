@@ -0,0 +1,66 @@
+//! Stats from dirty upstream logs that are flagged instead of shown with misleading ratios
+
+mod common;
+
+use common::stats;
+
+#[test]
+fn test_plausible() {
+    assert!(!stats().build().is_implausible());
+    assert!(!stats()
+        .drops(0)
+        .ubers(0)
+        .games(0)
+        .medic_time(0)
+        .build()
+        .is_implausible());
+    // medics can die with uber ready more often than they use it
+    assert!(!stats()
+        .drops(400)
+        .ubers(20)
+        .games(40)
+        .medic_time(100000)
+        .build()
+        .is_implausible());
+}
+
+#[test]
+fn test_negative_values() {
+    assert!(stats().medic_time(-1).build().is_implausible());
+    assert!(stats().drops(-1).build().is_implausible());
+    assert!(stats().ubers(-1).build().is_implausible());
+    assert!(stats().games(-1).build().is_implausible());
+}
+
+#[test]
+fn test_oversized_medic_time() {
+    let ten_years = 10 * 365 * 24 * 3600;
+    assert!(!stats().medic_time(ten_years).build().is_implausible());
+    assert!(stats().medic_time(ten_years + 1).build().is_implausible());
+    assert!(stats().medic_time(i64::MAX).build().is_implausible());
+}
+
+#[test]
+fn test_drops_without_playing() {
+    assert!(stats()
+        .drops(5)
+        .ubers(10)
+        .games(0)
+        .medic_time(3600)
+        .build()
+        .is_implausible());
+    assert!(stats()
+        .drops(5)
+        .ubers(10)
+        .games(3)
+        .medic_time(0)
+        .build()
+        .is_implausible());
+    assert!(!stats()
+        .drops(0)
+        .ubers(10)
+        .games(3)
+        .medic_time(0)
+        .build()
+        .is_implausible());
+}
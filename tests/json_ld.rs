@@ -0,0 +1,41 @@
+//! The structured data embedded in profile pages for search engines
+
+mod common;
+
+use common::stats;
+use dropstf::Site;
+use serde_json::Value;
+
+#[test]
+fn test_profile_page() {
+    let json_ld: Value = serde_json::from_str(&stats().build().json_ld(&Site::default())).unwrap();
+    assert_eq!(json_ld["@context"], "https://schema.org");
+    assert_eq!(json_ld["@type"], "ProfilePage");
+    assert_eq!(json_ld["url"], "https://drops.tf/profile/76561198024494988");
+
+    let person = &json_ld["mainEntity"];
+    assert_eq!(person["@type"], "Person");
+    assert_eq!(person["name"], "Icewind");
+    assert_eq!(person["identifier"], "76561198024494988");
+    assert_eq!(
+        person["sameAs"],
+        "https://steamcommunity.com/profiles/76561198024494988"
+    );
+    assert_eq!(
+        person["description"],
+        "150 ubers dropped in 300 games, 0.50 drops per game"
+    );
+}
+
+#[test]
+fn test_name_cant_close_the_script() {
+    let name = "</script><script>alert(1)</script> & co";
+    let json_ld = stats().name(name).build().json_ld(&Site::default());
+    assert!(!json_ld.contains('<'), "{}", json_ld);
+    assert!(!json_ld.contains('>'), "{}", json_ld);
+    assert!(!json_ld.contains('&'), "{}", json_ld);
+
+    // the escapes are still the same json
+    let json_ld: Value = serde_json::from_str(&json_ld).unwrap();
+    assert_eq!(json_ld["mainEntity"]["name"], name);
+}
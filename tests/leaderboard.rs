@@ -0,0 +1,178 @@
+//! Building the leaderboard queries and the rank movements between two generations of a leaderboard
+//!
+//! The windowed leaderboard test needs a postgres database in `TEST_DATABASE_URL`, it creates its
+//! own schema so it doesn't disturb the fixtures of the other tests.
+
+mod common;
+
+use dropstf::{
+    rank_movements, DataSource, DistributionQuery, RankMovement, SteamId, TopOrder, TopStats,
+    TopStatsQuery, DEFAULT_TOP_LIMIT, MAX_TOP_LIMIT, TOP_WINDOW,
+};
+
+fn medic(id: u32) -> TopStats {
+    TopStats {
+        steam_id: SteamId::new(76561197960265728 + u64::from(id)),
+        name: format!("medic {id}"),
+        drops: 0,
+        ubers: 0,
+        games: 0,
+        medic_time: 0,
+    }
+}
+
+fn board(ids: &[u32]) -> Vec<TopStats> {
+    ids.iter().copied().map(medic).collect()
+}
+
+#[test]
+fn test_rank_movements() {
+    let previous = board(&[1, 2, 3, 4]);
+    // 3 moved up, 4 dropped off and 5 entered the board
+    let current = board(&[3, 1, 2, 5]);
+    assert_eq!(
+        rank_movements(&previous, &current),
+        [
+            RankMovement::Up(2),
+            RankMovement::Down(1),
+            RankMovement::Down(1),
+            RankMovement::New,
+        ]
+    );
+
+    assert_eq!(
+        rank_movements(&previous, &previous),
+        [RankMovement::Same; 4]
+    );
+    assert_eq!(rank_movements(&[], &board(&[1])), [RankMovement::New]);
+}
+
+#[test]
+fn test_rank_movement_display() {
+    assert_eq!(RankMovement::Up(2).to_string(), "▲2");
+    assert_eq!(RankMovement::Down(1).to_string(), "▼1");
+    assert_eq!(RankMovement::New.to_string(), "new");
+    assert_eq!(RankMovement::Same.to_string(), "");
+}
+
+#[test]
+fn test_distribution_query() {
+    let query = DistributionQuery::new(TopOrder::Dpu, 10).with_min_ubers(50);
+    assert_eq!(
+        query.build().sql(),
+        "WITH vals AS (SELECT dpu AS value FROM ranked_medic_stats WHERE dpu IS NOT NULL \
+        AND ubers >= $1 AND steam_id NOT IN (SELECT steam_id FROM deleted_players)), \
+        bounds AS (SELECT MIN(value) AS low, MAX(value) AS high FROM vals) \
+        SELECT low, high, CASE WHEN low = high THEN 1 \
+        ELSE LEAST(width_bucket(value, low, high, $2), $3) END AS bucket, COUNT(*) AS count \
+        FROM vals, bounds GROUP BY 1, 2, 3"
+    );
+
+    // drops per hour are reported per hour, drops are counted as floats like the ratios
+    let query = DistributionQuery::new(TopOrder::Dps, 10);
+    assert!(query.build().sql().contains("SELECT dps * 3600 AS value"));
+    let query = DistributionQuery::new(TopOrder::Drops, 10);
+    assert!(query
+        .build()
+        .sql()
+        .contains("SELECT drops::float8 AS value"));
+
+    // the cache key covers every option
+    assert_ne!(
+        DistributionQuery::new(TopOrder::Dpu, 10),
+        DistributionQuery::new(TopOrder::Dpu, 10).with_min_ubers(50)
+    );
+}
+
+#[test]
+fn test_top_stats_query() {
+    let query = TopStatsQuery::new(TopOrder::Dpu).with_min_ubers(50);
+    assert_eq!(
+        query.build().sql(),
+        "SELECT steam_id, games, ubers, drops, medic_time, name FROM ranked_medic_stats \
+        WHERE ubers >= $1 AND games >= $2 AND steam_id NOT IN (SELECT steam_id FROM deleted_players) \
+        ORDER BY dpu DESC NULLS LAST, steam_id LIMIT $3 OFFSET $4"
+    );
+
+    let query = TopStatsQuery::new(TopOrder::Dps).ascending();
+    assert!(query.build().sql().contains("ORDER BY dps ASC NULLS FIRST"));
+
+    // the cache key covers every option
+    assert_eq!(
+        TopStatsQuery::new(TopOrder::Drops),
+        TopStatsQuery::new(TopOrder::Drops).with_limit(DEFAULT_TOP_LIMIT)
+    );
+    assert_ne!(
+        TopStatsQuery::new(TopOrder::Drops),
+        TopStatsQuery::new(TopOrder::Drops).with_offset(25)
+    );
+    assert_eq!(
+        TopStatsQuery::new(TopOrder::Drops).with_limit(100_000),
+        TopStatsQuery::new(TopOrder::Drops).with_limit(MAX_TOP_LIMIT)
+    );
+}
+
+#[test]
+fn test_top_window() {
+    let query = TopStatsQuery::new(TopOrder::Drops);
+    let window = query.window().unwrap();
+    assert_eq!((window.limit(), window.offset()), (TOP_WINDOW, 0));
+    // every page within the window shares the cache key
+    assert_eq!(query.with_offset(25).window(), Some(window));
+    assert_eq!(query.with_limit(50).with_offset(50).window(), Some(window));
+    assert_eq!(query.with_offset(TOP_WINDOW - 24).window(), None);
+    // but the other options still count
+    assert_ne!(query.ascending().window(), Some(window));
+
+    let rows: Vec<i64> = (0..TOP_WINDOW).collect();
+    assert_eq!(query.with_offset(25).slice(&rows), &rows[25..50]);
+    // a short leaderboard gives a short or empty page
+    assert_eq!(query.with_offset(25).slice(&rows[..30]), &rows[25..30]);
+    assert!(query.with_offset(25).slice(&rows[..10]).is_empty());
+}
+
+#[tokio::test]
+async fn test_top_window_matches_direct_query() {
+    let Some(pool) = common::connect(Some("top_window")).await else {
+        return;
+    };
+    // more medics than the window, with plenty of ties
+    sqlx::raw_sql(
+        "DROP TABLE IF EXISTS top_window.ranked_medic_stats, top_window.deleted_players;
+        CREATE TABLE top_window.deleted_players (steam_id TEXT PRIMARY KEY);
+        CREATE TABLE top_window.ranked_medic_stats (
+            steam_id TEXT PRIMARY KEY, name TEXT NOT NULL, games BIGINT NOT NULL,
+            ubers BIGINT NOT NULL, drops BIGINT NOT NULL, medic_time BIGINT NOT NULL,
+            dpu FLOAT8, dps FLOAT8, dpg FLOAT8
+        );
+        INSERT INTO top_window.ranked_medic_stats
+        SELECT '[U:1:' || i || ']', 'medic ' || i, i % 17 + 1, i % 13 + 1, i % 7, 3600,
+            (i % 7)::float8 / (i % 13 + 1), (i % 7)::float8 / 3600, (i % 7)::float8 / (i % 17 + 1)
+        FROM generate_series(1, 250) AS i;",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let data_source = DataSource::new(pool.clone(), String::new());
+    for order in TopOrder::ALL {
+        for query in [
+            TopStatsQuery::new(order),
+            TopStatsQuery::new(order).ascending(),
+        ] {
+            for (limit, offset) in [(25, 0), (25, 25), (10, 90), (100, 0), (25, 90), (25, 240)] {
+                let query = query.with_limit(limit).with_offset(offset);
+                let cached = data_source.top_stats(query).await.unwrap();
+                let direct: Vec<TopStats> = query
+                    .build()
+                    .build_query_as()
+                    .fetch_all(&pool)
+                    .await
+                    .unwrap();
+                let ids =
+                    |rows: &[TopStats]| rows.iter().map(|row| row.steam_id).collect::<Vec<_>>();
+                assert_eq!(ids(&cached), ids(&direct), "{:?}", query);
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Parsing of the bind address, listening on ipv4 and ipv6 and the connection settings
+
+use dropstf::{bind_tcp, connection_builder, wait_for_client, BindAddress, HttpSettings};
+use hyper::service::service_fn;
+use hyper::Response;
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+#[test]
+fn test_parse_bind_address() {
+    assert_eq!(
+        "0.0.0.0".parse(),
+        Ok(BindAddress(IpAddr::V4(Ipv4Addr::UNSPECIFIED)))
+    );
+    assert_eq!(
+        "::".parse(),
+        Ok(BindAddress(IpAddr::V6(Ipv6Addr::UNSPECIFIED)))
+    );
+    assert_eq!(
+        "[::1]".parse(),
+        Ok(BindAddress(IpAddr::V6(Ipv6Addr::LOCALHOST)))
+    );
+    assert!("localhost".parse::<BindAddress>().is_err());
+    assert!("[::1]:8080".parse::<BindAddress>().is_err());
+    assert!("127.0.0.1:8080".parse::<BindAddress>().is_err());
+    assert!("[::1".parse::<BindAddress>().is_err());
+}
+
+/// Skip the ipv6 tests on hosts without ipv6
+fn ipv6_available() -> bool {
+    std::net::TcpListener::bind("[::1]:0").is_ok()
+}
+
+#[tokio::test]
+async fn test_bind_ipv4() {
+    let listener = bind_tcp("127.0.0.1:0".parse().unwrap()).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    assert!(TcpStream::connect(("127.0.0.1", port)).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_bind_dual_stack() {
+    if !ipv6_available() {
+        eprintln!("ipv6 not available, skipping");
+        return;
+    }
+    let listener = bind_tcp(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    assert!(TcpStream::connect(("::1", port)).await.is_ok());
+    assert!(TcpStream::connect(("127.0.0.1", port)).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_bind_ipv6_only() {
+    if !ipv6_available() {
+        eprintln!("ipv6 not available, skipping");
+        return;
+    }
+    let listener = bind_tcp(SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    assert!(TcpStream::connect(("::1", port)).await.is_ok());
+}
+
+/// Serve connections on a local port with `settings`, answering every request with "ok"
+fn serve(settings: HttpSettings) -> u16 {
+    let listener = bind_tcp("127.0.0.1:0".parse().unwrap()).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let builder = connection_builder(&settings);
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let builder = builder.clone();
+            let settings = settings.clone();
+            tokio::spawn(async move {
+                if !wait_for_client(stream.readable(), &settings).await {
+                    return;
+                }
+                let service =
+                    service_fn(|_| async { Ok::<_, Infallible>(Response::new("ok".to_string())) });
+                let _ = builder
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+    port
+}
+
+#[tokio::test]
+async fn test_header_read_timeout() {
+    let port = serve(HttpSettings {
+        header_read_timeout: 1,
+        ..HttpSettings::default()
+    });
+
+    // a client that sends its request in time is answered
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+    // a client that never sends anything is disconnected
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let mut buffer = Vec::new();
+    let read = timeout(Duration::from_secs(5), client.read_to_end(&mut buffer)).await;
+    assert!(read.is_ok(), "idle connection wasn't closed");
+
+    // and so is one that never finishes its headers
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+    let read = timeout(Duration::from_secs(5), client.read_to_end(&mut buffer)).await;
+    assert!(
+        read.is_ok(),
+        "connection with incomplete headers wasn't closed"
+    );
+}
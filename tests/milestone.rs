@@ -0,0 +1,83 @@
+//! The milestones shown on profiles for sample stat lines
+
+mod common;
+
+use common::stats;
+use dropstf::{DropStats, DEFAULT_RANK_CUTOFF};
+
+fn titles(stats: &DropStats) -> Vec<&'static str> {
+    stats
+        .milestones(DEFAULT_RANK_CUTOFF)
+        .iter()
+        .map(|milestone| milestone.title)
+        .collect()
+}
+
+#[test]
+fn test_no_milestones() {
+    assert!(titles(&stats().drops(99).ubers(999).games(999).rank(500).build()).is_empty());
+}
+
+#[test]
+fn test_thresholds_are_inclusive() {
+    assert_eq!(
+        titles(&stats().drops(100).ubers(1000).games(1000).rank(500).build()),
+        ["100 drops", "1000 ubers", "1000 games"]
+    );
+}
+
+#[test]
+fn test_multiple_milestones() {
+    assert_eq!(
+        titles(
+            &stats()
+                .drops(1500)
+                .ubers(12_000)
+                .games(800)
+                .rank(10)
+                .build()
+        ),
+        [
+            "100 drops",
+            "1000 drops",
+            "1000 ubers",
+            "10,000 ubers",
+            "Top 10 in drops",
+            "Top 10 in drops per hour",
+            "Top 10 in drops per game",
+            "Top 10 in drops per uber",
+        ]
+    );
+    assert_eq!(
+        titles(
+            &stats()
+                .drops(1500)
+                .ubers(12_000)
+                .games(800)
+                .rank(11)
+                .build()
+        ),
+        ["100 drops", "1000 drops", "1000 ubers", "10,000 ubers"]
+    );
+}
+
+#[test]
+fn test_ranks_need_a_ranked_player() {
+    // unranked players get ranks relative to the ranked ones, a low drop count can still rank high
+    // on the ratios
+    assert_eq!(
+        titles(&stats().drops(100).ubers(200).games(20).rank(1).build()),
+        ["100 drops"]
+    );
+    assert!(
+        stats()
+            .drops(100)
+            .ubers(200)
+            .games(20)
+            .rank(1)
+            .build()
+            .milestones(99)
+            .len()
+            > 1
+    );
+}
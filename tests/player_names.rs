@@ -0,0 +1,80 @@
+//! Player names that could break out of the html or the metrics they end up in
+//!
+//! Needs a postgres database in `TEST_DATABASE_URL`, the tables are created in their own schema
+//! so the fixtures of the other tests aren't disturbed. The metrics recorder is installed
+//! globally, so this is the only test in this file.
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use dropstf::{app, DataSource, RouterOptions};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tower::ServiceExt;
+
+const NAME: &str = "Ice\"wind\\\n<b>} 1\nfake_metric 1";
+
+async fn get(router: &Router, uri: &str) -> String {
+    let response = router
+        .clone()
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn test_hostile_name() {
+    let Some(pool) = common::test_pool(Some("player_names"), &[]).await else {
+        return;
+    };
+    for table in ["user_names", "medic_names", "ranked_medic_stats"] {
+        sqlx::query(&format!(
+            "UPDATE {table} SET name = $1 WHERE steam_id = '[U:1:64229260]'"
+        ))
+        .bind(NAME)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let metrics = recorder.handle();
+    metrics::set_global_recorder(recorder).unwrap();
+    let options = RouterOptions::default();
+    options.readiness.set_ready();
+    let router = app(DataSource::new(pool, String::new()), metrics, options);
+
+    let body = get(&router, "/profile/76561198024494988").await;
+    assert!(body.contains("150 ubers dropped"), "{}", body);
+    assert!(!body.contains("<b>"), "{}", body);
+    assert!(body.contains("&lt;b&gt;"), "{}", body);
+
+    let exposition = get(&router, "/metrics").await;
+    let player_stats: Vec<_> = exposition
+        .lines()
+        .filter(|line| line.starts_with("player_stats"))
+        .collect();
+    assert_eq!(player_stats.len(), 1, "{}", exposition);
+    assert!(
+        player_stats[0].ends_with("} 1")
+            && player_stats[0].contains("name=\"Icewind<b>} 1fake_metric 1\""),
+        "{}",
+        player_stats[0]
+    );
+    // nothing but the samples and their descriptions
+    for line in exposition.lines().filter(|line| !line.is_empty()) {
+        assert!(
+            line.starts_with('#')
+                || line
+                    .rsplit_once(' ')
+                    .is_some_and(|(_, value)| value.parse::<f64>().is_ok()),
+            "{}",
+            line
+        );
+        assert!(!line.starts_with("fake_metric"), "{}", exposition);
+    }
+}
@@ -0,0 +1,422 @@
+//! Ranks and leaderboards with several ranked medics, from `tests/ranks.sql`
+//!
+//! Needs a postgres database in `TEST_DATABASE_URL`, the tables are created in their own schema
+//! so the fixtures of the other tests aren't disturbed.
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use dropstf::{DataSource, RouterOptions};
+use serde_json::Value;
+use tower::ServiceExt;
+
+async fn test_router() -> Option<Router> {
+    test_router_with(|data_source| data_source).await
+}
+
+async fn test_router_with(configure: impl FnOnce(DataSource) -> DataSource) -> Option<Router> {
+    let pool = common::test_pool(Some("ranks"), &[include_str!("ranks.sql")]).await?;
+    let data_source = configure(DataSource::new(pool, String::new()));
+    Some(common::test_app(data_source, RouterOptions::default()))
+}
+
+async fn get(router: &Router, uri: &str) -> (StatusCode, String) {
+    let response = router
+        .clone()
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+async fn get_json(router: &Router, uri: &str) -> Value {
+    let (status, body) = get(router, uri).await;
+    assert_eq!(status, StatusCode::OK, "{}: {}", uri, body);
+    serde_json::from_str(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_dpu_rank_matches_leaderboard() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+
+    let (status, body) = get(&router, "/top/dpu").await;
+    assert_eq!(status, StatusCode::OK);
+    // the medic of the day is shown above the leaderboard
+    let board = &body[body.find(r#"<table class="leaderboards">"#).unwrap()..];
+    assert!(!board.contains("Fewubers"), "{}", body);
+    let positions: Vec<_> = ["Efficient", "Allround", "Steady", "Grinder"]
+        .iter()
+        .map(|name| board.find(name).unwrap())
+        .collect();
+    assert!(
+        positions.windows(2).all(|pair| pair[0] < pair[1]),
+        "{}",
+        body
+    );
+
+    // the medic with too few ubers isn't counted ahead of the others
+    for (steam_id, rank) in [
+        ("[U:1:13]", 1),
+        ("[U:1:11]", 2),
+        ("[U:1:15]", 2),
+        ("[U:1:14]", 4),
+    ] {
+        let stats = get_json(&router, &format!("/api/player/{steam_id}")).await;
+        assert_eq!(stats["dpu_rank"], rank, "{}", steam_id);
+    }
+    // the stored ranks still apply to the other leaderboards
+    let stats = get_json(&router, "/api/player/[U:1:13]").await;
+    assert_eq!(stats["drops_rank"], 3);
+
+    let neighbors = get_json(&router, "/api/player/[U:1:14]/neighbors?order=dpu&radius=1").await;
+    let neighbors: Vec<_> = neighbors
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|medic| {
+            (
+                medic["name"].as_str().unwrap(),
+                medic["rank"].as_i64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(neighbors, [("Steady", 3), ("Grinder", 4)]);
+}
+
+#[tokio::test]
+async fn test_export_ranks_match_leaderboard() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+
+    let (status, body) = get(&router, "/api/ranks.ndjson").await;
+    assert_eq!(status, StatusCode::OK);
+    let ranks: Vec<(String, i64, Option<i64>)> = body
+        .lines()
+        .map(|line| {
+            let medic: Value = serde_json::from_str(line).unwrap();
+            (
+                medic["name"].as_str().unwrap().to_string(),
+                medic["drops_rank"].as_i64().unwrap(),
+                medic["dpu_rank"].as_i64(),
+            )
+        })
+        .collect();
+    // Quit is left out without leaving a gap, Fewubers and Nogames aren't on the dpu leaderboard
+    let expected = [
+        ("Allround", 1, Some(2)),
+        ("Fewubers", 2, None),
+        ("Efficient", 3, Some(1)),
+        ("Grinder", 4, Some(4)),
+        ("Steady", 5, Some(2)),
+        ("Nogames", 6, None),
+    ];
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|(name, drops, dpu)| (name.to_string(), *drops, *dpu))
+        .collect();
+    assert_eq!(ranks, expected, "{}", body);
+}
+
+#[tokio::test]
+async fn test_player_at_rank_matches_leaderboard() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+
+    // Fewubers has the best drops per uber, but isn't on the dpu leaderboard
+    for (rank, name) in [
+        (1, "Efficient"),
+        (2, "Allround"),
+        (3, "Steady"),
+        (4, "Grinder"),
+    ] {
+        let player = get_json(&router, &format!("/rank/dpu/{rank}")).await;
+        assert_eq!(player["name"], name, "{}", rank);
+        assert_eq!(player["rank"], rank, "{}", rank);
+    }
+    let (status, _) = get(&router, "/rank/dpu/5").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let player = get_json(&router, "/rank/drops/2").await;
+    assert_eq!(player["name"], "Fewubers");
+    // Quit has more drops, but is deleted
+    let player = get_json(&router, "/rank/drops/5").await;
+    assert_eq!(player["name"], "Steady");
+}
+
+#[tokio::test]
+async fn test_deleted_players_not_counted() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+
+    // Quit's 180 drops would put them between Grinder and Steady
+    let target = get_json(&router, "/api/player/[U:1:16]/target?drops=160").await;
+    assert_eq!(target["target_rank"], 5);
+    let target = get_json(&router, "/api/player/[U:1:16]/target?drops=190").await;
+    assert_eq!(target["target_rank"], 5);
+
+    let ranks = get_json(&router, "/api/player/[U:1:11]/ranks").await;
+    assert_eq!(ranks["ranked_players"], 6);
+}
+
+#[tokio::test]
+async fn test_player_ranks_per_leaderboard() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+
+    // Steady is 5th of the 6 ranked medics, but 2nd of the 4 with enough ubers for dpu
+    let ranks = get_json(&router, "/api/player/[U:1:15]/ranks").await;
+    assert_eq!(ranks["ranked"], true);
+    assert_eq!(ranks["ranked_players"], 6);
+    assert_eq!(ranks["dpu_players"], 4);
+    assert_eq!(ranks["drops_rank"], 5);
+    assert_eq!(ranks["dpu_rank"], 2);
+    assert_eq!(ranks["dps_rank"], 3);
+    assert_eq!(ranks["dpg_rank"], 3);
+    assert_eq!(ranks["drops_percentile"].as_f64(), Some(2.0 * 100.0 / 6.0));
+    assert_eq!(ranks["dpu_percentile"].as_f64(), Some(75.0));
+    assert_eq!(ranks["dps_percentile"].as_f64(), Some(4.0 * 100.0 / 6.0));
+    assert_eq!(ranks["dpg_percentile"].as_f64(), Some(4.0 * 100.0 / 6.0));
+
+    // without ubers Nogames ranks behind everyone on the dpu leaderboard, but isn't on it
+    let ranks = get_json(&router, "/api/player/[U:1:18]/ranks").await;
+    assert_eq!(ranks["dpu_rank"], 5);
+    assert!(ranks["dpu_percentile"].is_null());
+    assert_eq!(ranks["drops_percentile"].as_f64(), Some(100.0 / 6.0));
+}
+
+#[tokio::test]
+async fn test_no_percentiles_off_the_leaderboard() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+
+    // Fewubers' drops per uber would top the dpu leaderboard, but they have too few ubers for it
+    let ranks = get_json(&router, "/api/player/[U:1:12]/ranks").await;
+    assert_eq!(ranks["ranked"], true);
+    assert_eq!(ranks["dpu_rank"], 1);
+    assert!(ranks["dpu_percentile"].is_null());
+    assert_eq!(ranks["drops_percentile"].as_f64(), Some(5.0 * 100.0 / 6.0));
+
+    // Rookie's drops per uber would top it too, but they aren't ranked at all
+    let ranks = get_json(&router, "/api/player/[U:1:16]/ranks").await;
+    assert_eq!(ranks["ranked"], false);
+    assert_eq!(ranks["dpu_rank"], 1);
+    for order in ["drops", "dpu", "dps", "dpg"] {
+        assert!(
+            ranks[format!("{}_percentile", order)].is_null(),
+            "{}",
+            order
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_similar_players_order() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+
+    // Nogames has none of Efficient's ratios, only the games played tell them apart
+    let (_, body) = get(&router, "/profile/[U:1:13]").await;
+    let similar = &body[body.find(r#"<p class="similar">"#).unwrap()..];
+    let positions: Vec<usize> = ["Steady", "Allround", "Nogames", "Grinder", "Fewubers"]
+        .iter()
+        .map(|name| similar.find(&format!(">{name}</a>")).unwrap())
+        .collect();
+    assert!(
+        positions.windows(2).all(|pair| pair[0] < pair[1]),
+        "{}",
+        similar
+    );
+    assert!(!similar.contains("Quit"), "{}", similar);
+}
+
+#[tokio::test]
+async fn test_distribution_matches_leaderboard() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+
+    // every ranked medic on the leaderboard of the stat is counted once
+    for (metric, medics) in [("drops", 6), ("dpu", 4), ("dph", 5), ("dpg", 5)] {
+        let distribution = get_json(
+            &router,
+            &format!("/api/distribution?metric={metric}&buckets=3"),
+        )
+        .await;
+        let buckets = distribution["buckets"].as_array().unwrap();
+        assert_eq!(buckets.len(), 3, "{}", metric);
+        let counted: i64 = buckets
+            .iter()
+            .map(|bucket| bucket["count"].as_i64().unwrap())
+            .sum();
+        assert_eq!(counted, medics, "{}", metric);
+    }
+}
+
+#[tokio::test]
+async fn test_missing_ratios_rank_last() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+
+    // Nogames is ranked by drops, but has no ratio for the other leaderboards
+    let stats = get_json(&router, "/api/player/[U:1:18]").await;
+    assert_eq!(stats["drops_rank"], 6);
+    assert_eq!(stats["dpu_rank"], 5);
+    assert_eq!(stats["dps_rank"], 6);
+    assert_eq!(stats["dpg_rank"], 6);
+
+    // Idle isn't ranked, so the ranks are recomputed
+    let stats = get_json(&router, "/api/player/[U:1:17]").await;
+    assert_eq!(stats["drops_rank"], 7);
+    assert_eq!(stats["dpu_rank"], 5);
+    assert_eq!(stats["dps_rank"], 6);
+    assert_eq!(stats["dpg_rank"], 6);
+
+    for order in ["dps", "dpg"] {
+        let (status, body) = get(&router, &format!("/top/{order}")).await;
+        assert_eq!(status, StatusCode::OK);
+        let board = &body[body.find(r#"<table class="leaderboards">"#).unwrap()..];
+        let last = ["Allround", "Fewubers", "Efficient", "Grinder", "Steady"]
+            .iter()
+            .map(|name| board.find(name).unwrap())
+            .max()
+            .unwrap();
+        assert!(board.find("Nogames").unwrap() > last, "{}", body);
+    }
+}
+
+#[tokio::test]
+async fn test_rank_cutoff() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+    let Some(raised) = test_router_with(|data_source| data_source.with_rank_cutoff(200)).await
+    else {
+        return;
+    };
+
+    let ranks = get_json(&router, "/api/player/[U:1:15]/ranks").await;
+    assert_eq!(ranks["ranked"], true);
+    assert_eq!(ranks["ranked_players"], 6);
+    // Steady's 150 drops and Grinder's 200 aren't enough anymore
+    let ranks = get_json(&raised, "/api/player/[U:1:15]/ranks").await;
+    assert_eq!(ranks["ranked"], false);
+    assert_eq!(ranks["ranked_players"], 3);
+
+    // the ranks of unranked medics are only counted against the ranked ones
+    let stats = get_json(&router, "/api/player/[U:1:16]").await;
+    assert_eq!(stats["drops_rank"], 7);
+    let stats = get_json(&raised, "/api/player/[U:1:16]").await;
+    assert_eq!(stats["drops_rank"], 4);
+    // that includes the medics the import ranked, but who are below the raised cutoff
+    let stats = get_json(&router, "/api/player/[U:1:15]").await;
+    assert_eq!(stats["drops_rank"], 5);
+    let stats = get_json(&raised, "/api/player/[U:1:15]").await;
+    assert_eq!(stats["drops_rank"], 4);
+
+    // Steady's drops per second are better than Efficient's, but don't count with the raised cutoff
+    let stats = get_json(&router, "/api/player/[U:1:13]").await;
+    assert_eq!(stats["dps_rank"], 4);
+    let stats = get_json(&raised, "/api/player/[U:1:13]").await;
+    assert_eq!(stats["dps_rank"], 3);
+
+    let (_, body) = get(&raised, "/").await;
+    assert!(
+        body.contains("by medics with more than 200 drops"),
+        "{}",
+        body
+    );
+}
+
+async fn search_ids(router: &Router, query: &str) -> Vec<String> {
+    let results = get_json(router, &format!("/search?search=e&{}", query)).await;
+    results
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| result["steam_id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_not_found_suggestions_are_capped() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+
+    // steam can't be reached, but the names matching the input are still suggested
+    let (_, body) = get(&router, "/profile/e").await;
+    assert!(body.contains("Did you mean"), "{}", body);
+    assert_eq!(
+        body.matches("<li><a href=\"/profile/").count(),
+        5,
+        "{}",
+        body
+    );
+}
+
+#[tokio::test]
+async fn test_search_limit_counts_players() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+
+    // 10 names of 8 players match
+    let all = search_ids(&router, "limit=50").await;
+    assert_eq!(all.len(), 8, "{:?}", all);
+    for limit in 1..=8 {
+        let page = search_ids(&router, &format!("limit={}", limit)).await;
+        assert_eq!(page, all[..limit], "limit {}", limit);
+    }
+    assert_eq!(search_ids(&router, "limit=10").await, all);
+}
+
+#[tokio::test]
+async fn test_search_candidates_match_ranking() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+
+    // the 550 candidates are the lowest ids, so the last page is [U:1:502] to [U:1:551]
+    let results = get_json(&router, "/search?search=twin&limit=50&offset=500").await;
+    let ids: Vec<&str> = results
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| result["steam_id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids.len(), 50, "{:?}", ids);
+    assert_eq!(ids.first(), Some(&"76561197960266230"));
+    assert_eq!(ids.last(), Some(&"76561197960266279"));
+}
+
+#[tokio::test]
+async fn test_search_pages_dont_overlap() {
+    let Some(router) = test_router().await else {
+        return;
+    };
+
+    let all = search_ids(&router, "limit=50").await;
+    for limit in 1..=4 {
+        let mut paged = Vec::new();
+        for offset in (0..all.len()).step_by(limit) {
+            let page = search_ids(&router, &format!("limit={}&offset={}", limit, offset)).await;
+            assert!(!page.is_empty(), "limit {} offset {}", limit, offset);
+            paged.extend(page);
+        }
+        assert_eq!(paged, all, "limit {}", limit);
+    }
+    assert!(search_ids(&router, "offset=8").await.is_empty());
+}
@@ -0,0 +1,47 @@
+-- Several ranked medics for the tests of the leaderboards and ranks, loaded after fixtures.sql in
+-- a schema of their own
+
+TRUNCATE user_names, medic_names, medic_stats, ranked_medic_stats, global_stats, vanity_urls,
+    logs_raw, deleted_players;
+
+-- Fewubers has the best drops per uber, but too few ubers to be on the dpu leaderboard, Rookie
+-- doesn't have enough drops to be ranked at all. Nogames and Idle have no ubers, time or games,
+-- so none of their ratios exist. Quit would top the dpu leaderboard, but has been flagged as deleted
+INSERT INTO user_names VALUES ('[U:1:11]', 'Allround'), ('[U:1:12]', 'Fewubers'),
+    ('[U:1:13]', 'Efficient'), ('[U:1:14]', 'Grinder'), ('[U:1:15]', 'Steady'),
+    ('[U:1:16]', 'Rookie'), ('[U:1:17]', 'Idle'), ('[U:1:18]', 'Nogames'), ('[U:1:19]', 'Quit');
+INSERT INTO medic_names SELECT steam_id, name, 10 FROM user_names;
+-- more players named Twin than a search takes as candidates, all tied, whose ids sort differently
+-- as text than as numbers
+INSERT INTO medic_names SELECT '[U:1:' || n || ']', 'Twin', 1 FROM generate_series(2, 552) AS n;
+-- some medics played under more than one name
+INSERT INTO medic_names VALUES ('[U:1:11]', 'Allrounder', 5), ('[U:1:13]', 'Efficient2', 2),
+    ('[U:1:14]', 'Grindset', 3);
+INSERT INTO medic_stats (steam_id, games, ubers, drops, medic_time) VALUES
+    ('[U:1:11]', 100, 1000, 500, 360000),
+    ('[U:1:12]', 40, 20, 400, 100000),
+    ('[U:1:13]', 150, 400, 300, 720000),
+    ('[U:1:14]', 400, 1000, 200, 1440000),
+    ('[U:1:15]', 60, 300, 150, 180000),
+    ('[U:1:16]', 10, 60, 50, 36000),
+    ('[U:1:17]', 0, 0, 0, 0),
+    ('[U:1:18]', 0, 0, 120, 0),
+    ('[U:1:19]', 20, 100, 180, 50000);
+UPDATE medic_stats SET dpu = drops::float8 / NULLIF(ubers, 0),
+    dps = drops::float8 / NULLIF(medic_time, 0), dpg = drops::float8 / NULLIF(games, 0);
+INSERT INTO deleted_players (steam_id) VALUES ('[U:1:19]');
+-- ranked like the import does, over every medic with more than 100 drops that isn't deleted
+INSERT INTO ranked_medic_stats
+SELECT steam_id, name, games, ubers, drops, medic_time, dpu, dps, dpg,
+    rank() OVER (ORDER BY drops DESC), rank() OVER (ORDER BY dpu DESC NULLS LAST),
+    rank() OVER (ORDER BY dps DESC NULLS LAST), rank() OVER (ORDER BY dpg DESC NULLS LAST)
+FROM medic_stats
+INNER JOIN user_names USING (steam_id)
+WHERE drops > 100 AND steam_id NOT IN (SELECT steam_id FROM deleted_players);
+-- the deleted medic's row is left over from the import before they were flagged
+INSERT INTO ranked_medic_stats
+SELECT steam_id, name, games, ubers, drops, medic_time, dpu, dps, dpg, 5, 2, 1, 2
+FROM medic_stats
+INNER JOIN user_names USING (steam_id)
+WHERE steam_id = '[U:1:19]';
+INSERT INTO global_stats SELECT SUM(drops), SUM(ubers), SUM(games) FROM medic_stats;
@@ -0,0 +1,141 @@
+//! Per client limit on the vanity urls resolved through steam
+
+use axum::body::to_bytes;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::header::RETRY_AFTER;
+use axum::http::Request;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use dropstf::{ClientIp, DropsError, RateLimiter, TrustedProxies};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+fn client(last: u8) -> ClientIp {
+    ClientIp(Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, last))))
+}
+
+async fn client_ip(request: Request<()>) -> ClientIp {
+    let (mut parts, _) = request.into_parts();
+    match ClientIp::from_request_parts(&mut parts, &()).await {
+        Ok(client) => client,
+        Err(never) => match never {},
+    }
+}
+
+#[tokio::test]
+async fn test_limit_per_client() {
+    let limiter = RateLimiter::new(3);
+    for _ in 0..3 {
+        assert!(limiter.check(client(1)).await.is_ok());
+    }
+    assert!(limiter.check(client(1)).await.is_err());
+
+    // other clients have a limit of their own
+    assert!(limiter.check(client(2)).await.is_ok());
+    assert!(limiter.check(ClientIp(None)).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_batch_takes_all_or_nothing() {
+    let limiter = RateLimiter::new(3);
+    assert!(limiter.check_many(client(1), 2).await.is_ok());
+    // a batch that doesn't fit leaves the remaining token for a smaller one
+    assert!(limiter.check_many(client(1), 2).await.is_err());
+    assert!(limiter.check(client(1)).await.is_ok());
+    assert!(limiter.check(client(1)).await.is_err());
+}
+
+#[tokio::test]
+async fn test_retry_after() {
+    // one token every 20 seconds
+    let limiter = RateLimiter::new(3);
+    for _ in 0..3 {
+        assert!(limiter.check(client(1)).await.is_ok());
+    }
+    let error = limiter.check(client(1)).await.unwrap_err();
+    assert_eq!(error.retry_after(), Some(20));
+
+    let response = error.into_response();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()[RETRY_AFTER], "20");
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("try again in 20 seconds"), "{}", body);
+
+    // partial seconds are rounded up
+    let error = DropsError::TooManyRequests {
+        retry_after: Duration::from_millis(100),
+    };
+    assert_eq!(error.retry_after(), Some(1));
+}
+
+#[tokio::test]
+async fn test_limit_refills() {
+    // one token every 50ms
+    let limiter = RateLimiter::new(1200);
+    for _ in 0..1200 {
+        assert!(limiter.check(client(1)).await.is_ok());
+    }
+    assert!(limiter.check(client(1)).await.is_err());
+
+    tokio::time::sleep(Duration::from_millis(120)).await;
+    assert!(limiter.check(client(1)).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_zero_disables_limit() {
+    let limiter = RateLimiter::new(0);
+    for _ in 0..100 {
+        assert!(limiter.check(client(1)).await.is_ok());
+    }
+}
+
+#[tokio::test]
+async fn test_client_ip() {
+    let connected = SocketAddr::from(([192, 168, 1, 1], 1234));
+
+    let mut request = Request::new(());
+    request.extensions_mut().insert(ConnectInfo(connected));
+    assert_eq!(client_ip(request).await, ClientIp(Some(connected.ip())));
+
+    // only the address added by the proxy can be trusted
+    let forwarded = || {
+        Request::builder()
+            .header("x-forwarded-for", "1.2.3.4, 10.0.0.1")
+            .body(())
+            .unwrap()
+    };
+    let mut request = forwarded();
+    request.extensions_mut().insert(ConnectInfo(connected));
+    request
+        .extensions_mut()
+        .insert(TrustedProxies(vec![connected.ip()]));
+    assert_eq!(client_ip(request).await, client(1));
+
+    // anyone else connecting directly can't pick their own address
+    let mut request = forwarded();
+    request.extensions_mut().insert(ConnectInfo(connected));
+    assert_eq!(client_ip(request).await, ClientIp(Some(connected.ip())));
+    let mut request = forwarded();
+    request.extensions_mut().insert(ConnectInfo(connected));
+    request
+        .extensions_mut()
+        .insert(TrustedProxies(vec!["127.0.0.1".parse().unwrap()]));
+    assert_eq!(client_ip(request).await, ClientIp(Some(connected.ip())));
+
+    // the unix socket has no address and is always behind the proxy
+    assert_eq!(client_ip(forwarded()).await, client(1));
+    assert_eq!(client_ip(Request::new(())).await, ClientIp(None));
+}
+
+#[test]
+fn test_parse_trusted_proxies() {
+    assert_eq!(
+        "127.0.0.1, ::1".parse(),
+        Ok(TrustedProxies(vec![
+            "127.0.0.1".parse().unwrap(),
+            "::1".parse().unwrap()
+        ]))
+    );
+    assert!("127.0.0.1,localhost".parse::<TrustedProxies>().is_err());
+}
@@ -0,0 +1,126 @@
+//! Refreshing the name of a player from steam, against a stub of the steam api
+//!
+//! Needs a postgres database in `TEST_DATABASE_URL`. The tables are created in their own schema,
+//! with `ranked_medic_stats` as a materialized view like upstream, which can't be written to.
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::extract::Query;
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Request, StatusCode};
+use axum::routing::get;
+use axum::{Json, Router};
+use dropstf::{AdminToken, DataSource, RouterOptions};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+
+const SCHEMA: &str = "refresh_player";
+
+/// Start a stub of the player summaries api that knows a single player
+async fn steam_stub() -> String {
+    let steam = Router::new().route(
+        "/summaries",
+        get(|Query(query): Query<HashMap<String, String>>| async move {
+            let players = if query.get("steamids").map(String::as_str) == Some("76561198024494988")
+            {
+                json!([{
+                    "personaname": "Icewind Renamed",
+                    "profileurl": "https://steamcommunity.com/id/icewind-renamed/"
+                }])
+            } else {
+                json!([])
+            };
+            Json(json!({ "response": { "players": players } }))
+        }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, steam).await });
+    format!("http://{}/summaries", addr)
+}
+
+async fn send(router: &Router, request: Request<Body>) -> (StatusCode, String) {
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+fn refresh(steam_id: &str) -> Request<Body> {
+    Request::post(format!("/admin/refresh-player/{steam_id}"))
+        .header(AUTHORIZATION, "Bearer admin-secret")
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_refresh_player_name() {
+    // the fixtures can't drop the view as a table, so start over
+    let Some(pool) = common::connect(None).await else {
+        return;
+    };
+    sqlx::raw_sql(&format!("DROP SCHEMA IF EXISTS {SCHEMA} CASCADE"))
+        .execute(&pool)
+        .await
+        .unwrap();
+    let Some(pool) = common::test_pool(
+        Some(SCHEMA),
+        &[
+            "ALTER TABLE ranked_medic_stats RENAME TO ranked_medic_stats_imported;
+        CREATE MATERIALIZED VIEW ranked_medic_stats AS SELECT * FROM ranked_medic_stats_imported;",
+        ],
+    )
+    .await
+    else {
+        return;
+    };
+
+    let data_source =
+        DataSource::new(pool.clone(), String::new()).with_player_summaries_url(steam_stub().await);
+    let router = common::test_app(
+        data_source,
+        RouterOptions {
+            admin_token: AdminToken::new(Some("admin-secret".into())),
+            ..RouterOptions::default()
+        },
+    );
+
+    let (status, body) = send(&router, refresh("76561198024494988")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let refresh_result: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(refresh_result["before"], "Icewind");
+    assert_eq!(refresh_result["after"], "Icewind Renamed");
+    assert_eq!(refresh_result["vanity_url"], "icewind-renamed");
+
+    let name: String = sqlx::query_scalar("SELECT name FROM user_names WHERE steam_id = $1")
+        .bind("[U:1:64229260]")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(name, "Icewind Renamed");
+    let owner: String = sqlx::query_scalar("SELECT steam_id FROM vanity_urls WHERE url = $1")
+        .bind("icewind-renamed")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(owner, "[U:1:64229260]");
+    // the leaderboard keeps the old name until the view is refreshed
+    let (status, body) = send(
+        &router,
+        Request::get("/top/drops").body(Body::empty()).unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        body.contains(r#"<a href="/profile/76561198024494988">Icewind</a>"#),
+        "{}",
+        body
+    );
+
+    // steam doesn't know the player
+    let (status, _) = send(&router, refresh("76561197960265729")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
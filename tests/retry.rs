@@ -0,0 +1,93 @@
+//! Trying requests to outside services again, like resolving vanity urls through steam
+
+use dropstf::{retry, Backoff, RetryError};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+const BACKOFF: Backoff = Backoff {
+    attempts: 3,
+    delay: Duration::from_millis(1),
+    timeout: Duration::from_millis(50),
+};
+
+#[tokio::test]
+async fn test_not_found_is_not_retried() {
+    let calls = AtomicU32::new(0);
+    let result = retry(
+        BACKOFF,
+        |_| true,
+        || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok::<Option<u64>, ()>(None)
+        },
+    )
+    .await;
+    assert_eq!(result.unwrap(), None);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_timeout_is_retried() {
+    let calls = AtomicU32::new(0);
+    let result = retry(
+        BACKOFF,
+        |_| true,
+        || async {
+            // the first try hangs, like a request to steam that never gets an answer
+            if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                sleep(Duration::from_secs(10)).await;
+            }
+            Ok::<_, ()>(Some(76561198024494988u64))
+        },
+    )
+    .await;
+    assert_eq!(result.unwrap(), Some(76561198024494988));
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_gives_up_after_attempts() {
+    let calls = AtomicU32::new(0);
+    let result = retry(
+        BACKOFF,
+        |_| true,
+        || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<Option<u64>, _>("connection reset")
+        },
+    )
+    .await;
+    assert!(matches!(
+        result,
+        Err(RetryError::Failed("connection reset"))
+    ));
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    let result = retry(
+        BACKOFF,
+        |_| true,
+        || async {
+            sleep(Duration::from_secs(10)).await;
+            Ok::<Option<u64>, ()>(None)
+        },
+    )
+    .await;
+    assert!(matches!(result, Err(RetryError::TimedOut)));
+}
+
+#[tokio::test]
+async fn test_permanent_errors_are_not_retried() {
+    let calls = AtomicU32::new(0);
+    let result = retry(
+        BACKOFF,
+        |e: &&str| *e != "invalid key",
+        || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<Option<u64>, _>("invalid key")
+        },
+    )
+    .await;
+    assert!(matches!(result, Err(RetryError::Failed("invalid key"))));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,103 @@
+//! Ordering of the name search results
+
+use dropstf::{rank_results, SearchRanking, SearchResult, SteamId};
+
+fn result(id: u64, name: &str, count: i64, sim: f64) -> SearchResult {
+    SearchResult {
+        steam_id: SteamId::new(76561197960265728 + id),
+        name: name.into(),
+        count,
+        sim,
+    }
+}
+
+/// Sort every rotation of the results and check they all end up in the same order
+fn sorted_ids(mut results: Vec<SearchResult>, ranking: SearchRanking) -> Vec<(u64, String)> {
+    let mut orders = Vec::new();
+    for _ in 0..results.len() {
+        results.rotate_left(1);
+        let mut sorted: Vec<&SearchResult> = results.iter().collect();
+        sorted.sort_by(|a, b| a.cmp_relevance(b, ranking));
+        orders.push(
+            sorted
+                .iter()
+                .map(|r| (r.steam_id.as_u64() - 76561197960265728, r.name.clone()))
+                .collect::<Vec<_>>(),
+        );
+    }
+    orders.dedup();
+    assert_eq!(orders.len(), 1, "{:?}", orders);
+    orders.remove(0)
+}
+
+fn ties() -> Vec<SearchResult> {
+    vec![
+        result(3, "medic", 2, 0.0),
+        result(2, "medic", 1, 0.2),
+        result(5, "medic", 1, 0.2),
+        result(1, "medic b", 1, 0.2),
+        result(1, "medic a", 1, 0.2),
+        result(4, "medic", 3, 0.0),
+    ]
+}
+
+#[test]
+fn test_popular_ties() {
+    // 3 and everyone with a similarity of 0.2 have the same weight, the similarity decides
+    assert_eq!(
+        sorted_ids(ties(), SearchRanking::Popular),
+        [
+            (4, "medic".to_string()),
+            (1, "medic a".to_string()),
+            (1, "medic b".to_string()),
+            (2, "medic".to_string()),
+            (5, "medic".to_string()),
+            (3, "medic".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_precise_ties() {
+    assert_eq!(
+        sorted_ids(ties(), SearchRanking::Precise),
+        [
+            (1, "medic a".to_string()),
+            (1, "medic b".to_string()),
+            (2, "medic".to_string()),
+            (5, "medic".to_string()),
+            (4, "medic".to_string()),
+            (3, "medic".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_rank_results() {
+    // the second name of player 1 and 2 is less relevant, so only their first one is kept
+    let rows = vec![
+        result(1, "medic b", 1, 0.1),
+        result(2, "medic", 1, 0.5),
+        result(1, "medic a", 1, 0.9),
+        result(3, "medic", 1, 0.3),
+        result(2, "medic c", 1, 0.2),
+    ];
+    let ids = |results: Vec<SearchResult>| -> Vec<(u64, String)> {
+        results
+            .into_iter()
+            .map(|r| (r.steam_id.as_u64() - 76561197960265728, r.name))
+            .collect()
+    };
+    assert_eq!(
+        ids(rank_results(rows, SearchRanking::Precise, 2, 1)),
+        [(2, "medic".to_string()), (3, "medic".to_string())]
+    );
+
+    let single = vec![result(4, "medic", 1, 0.1)];
+    assert_eq!(
+        ids(rank_results(single, SearchRanking::Popular, 10, 0)),
+        [(4, "medic".to_string())]
+    );
+    let single = vec![result(4, "medic", 1, 0.1)];
+    assert!(rank_results(single, SearchRanking::Popular, 10, 1).is_empty());
+}
@@ -0,0 +1,38 @@
+//! Branding of a self hosted instance
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use dropstf::{app, DataSource, RouterOptions, Site};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use sqlx::PgPool;
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn test_site_branding() {
+    // the error page of an unknown route doesn't need the database
+    let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+    let options = RouterOptions {
+        site: Site {
+            name: "ubers.example".into(),
+            url: "https://ubers.example".into(),
+            logo: Some("https://ubers.example/logo.png".into()),
+            footer: Some("Hosted by <the community>".into()),
+        },
+        ..RouterOptions::default()
+    };
+    let metrics = PrometheusBuilder::new().build_recorder().handle();
+    let router = app(DataSource::new(pool, String::new()), metrics, options);
+
+    let response = router
+        .oneshot(Request::get("/missing").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("<title>ubers.example</title>"), "{}", body);
+    assert!(body.contains(r#"<link rel="shortcut icon" href="https://ubers.example/logo.png" />"#));
+    assert!(body.contains(r#"<a href="https://ubers.example">ubers.example</a>"#));
+    assert!(body.contains("Hosted by &lt;the community&gt;"));
+    assert!(!body.contains("drops.tf"), "{}", body);
+}
@@ -0,0 +1,158 @@
+//! Steam ids pasted by users, in request bodies and stored in the database, the database tests
+//! need a postgres database in `TEST_DATABASE_URL`
+
+mod common;
+
+use dropstf::{IdFormat, InvalidSteamId, SteamId};
+use std::error::Error;
+use steamid_ng::SteamID;
+
+#[tokio::test]
+async fn test_decode_malformed_steam3() {
+    let Some(pool) = common::connect(None).await else {
+        return;
+    };
+
+    let steam_id: SteamId = sqlx::query_scalar("SELECT '[U:1:64229260]'::text")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(steam_id, SteamId::new(76561198024494988));
+
+    let error = sqlx::query_scalar::<_, SteamId>("SELECT '[U:1:garbage]'::text")
+        .fetch_one(&pool)
+        .await
+        .unwrap_err();
+    // the column comes from sqlx, the value from the decode error
+    let message = error.to_string();
+    assert!(message.contains("column 0"), "{}", message);
+    assert!(message.contains("[U:1:garbage]"), "{}", message);
+
+    let source = error.source().unwrap();
+    let invalid = source.downcast_ref::<InvalidSteamId>().unwrap();
+    assert_eq!(invalid.value, "[U:1:garbage]");
+}
+
+#[test]
+fn test_deserialize_any_format() {
+    let expected = SteamId::new(76561198024494988);
+    for json in [
+        "76561198024494988",
+        r#""76561198024494988""#,
+        r#""[U:1:64229260]""#,
+        r#""STEAM_0:0:32114630""#,
+    ] {
+        let steam_id: SteamId = serde_json::from_str(json).unwrap();
+        assert_eq!(steam_id, expected, "{}", json);
+    }
+
+    // serializing gives the steam64 id as a string, which reads back the same
+    let json = serde_json::to_string(&expected).unwrap();
+    assert_eq!(json, r#""76561198024494988""#);
+    assert_eq!(serde_json::from_str::<SteamId>(&json).unwrap(), expected);
+}
+
+#[test]
+fn test_deserialize_garbage() {
+    for json in [r#""garbage""#, r#""[U:1:]""#, "-1", "1.5", "null", "[]"] {
+        let error = serde_json::from_str::<SteamId>(json).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("expected a steam64, steam3 or steam2 id"),
+            "{}: {}",
+            json,
+            error
+        );
+    }
+
+    let error = serde_json::from_str::<SteamId>(r#""garbage""#).unwrap_err();
+    assert!(
+        error.to_string().contains(r#"string "garbage""#),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_invite_codes() {
+    // the hex account id with 0-f substituted by bcdfghjkmnpqrtvw
+    for (code, account_id) in [("ftgb-wmr", 64229260), ("fvm", 1000), ("qqm", 3000)] {
+        let expected = SteamId::new(76561197960265728 + account_id);
+        assert_eq!(SteamId::from_invite_code(code), Some(expected), "{}", code);
+    }
+    assert_eq!(
+        SteamId::from_invite_code("FTGB-WMR"),
+        SteamId::from_invite_code("ftgb-wmr")
+    );
+
+    // vowels and digits aren't part of the alphabet, and account ids are 32 bit
+    for code in ["", "-", "ftgb-wma", "ftgb-wm1", "wwwww-wwww"] {
+        assert_eq!(SteamId::from_invite_code(code), None, "{}", code);
+    }
+}
+
+#[test]
+fn test_from_any_links() {
+    let expected = SteamId::new(76561198024494988);
+    for input in [
+        "76561198024494988",
+        "[U:1:64229260]",
+        "STEAM_0:0:32114630",
+        "s.team/p/ftgb-wmr",
+        "https://s.team/p/ftgb-wmr/TOKEN",
+        "https://steamcommunity.com/user/ftgb-wmr/",
+        "  https://steamcommunity.com/profiles/76561198024494988/ ",
+    ] {
+        assert_eq!(SteamId::from_any(input).unwrap(), expected, "{}", input);
+    }
+
+    for input in [
+        "s.team/p/",
+        "https://steamcommunity.com/id/icewind",
+        "icewind",
+    ] {
+        assert!(SteamId::from_any(input).is_err(), "{}", input);
+    }
+}
+
+#[test]
+fn test_individual_accounts() {
+    let individual: SteamId = "[U:1:64229260]".parse().unwrap();
+    assert!(individual.is_valid_individual());
+
+    let clan = SteamId::new(103582791429521412);
+    assert!(!clan.is_valid_individual());
+    let game_server = SteamId::new(85568392920051769);
+    assert!(!game_server.is_valid_individual());
+    // the right type, but nobody has account 0
+    let nobody = SteamId::new(76561197960265728);
+    assert!(!nobody.is_valid_individual());
+}
+
+#[test]
+fn test_conversions() {
+    let steam_id = SteamId::new(76561198024494988);
+    assert_eq!(u64::from(&steam_id), 76561198024494988);
+    assert_eq!(u64::from(steam_id), steam_id.as_u64());
+    assert_eq!(SteamID::from(&steam_id), steam_id.as_steamid());
+    assert_eq!(u64::from(steam_id.as_steamid()), 76561198024494988);
+
+    assert_eq!(steam_id.steam3(), "[U:1:64229260]");
+    assert_eq!(steam_id.steam2(), "STEAM_0:0:32114630");
+    assert_eq!(steam_id.format(IdFormat::Steam64), "76561198024494988");
+    assert_eq!(steam_id.format(IdFormat::Steam3), steam_id.steam3());
+    assert_eq!(steam_id.format(IdFormat::Steam2), steam_id.steam2());
+}
+
+#[test]
+fn test_display_and_debug() {
+    let steam_id = SteamId::new(76561198024494988);
+    assert_eq!(steam_id.to_string(), "76561198024494988");
+    assert_eq!(
+        serde_json::to_string(&steam_id).unwrap(),
+        format!("\"{}\"", steam_id)
+    );
+    // the debug form shows that it's a steam id and not just any number
+    assert_eq!(format!("{:?}", steam_id), "SteamID(76561198024494988)");
+}
@@ -0,0 +1,111 @@
+//! The pasteable stat summaries, pinned exactly since users paste them into forum posts
+
+mod common;
+
+use dropstf::{format_summary, DropStats, Site, SummaryFormat, SummaryParams};
+
+/// Different numbers and ranks for every stat, so the lines can't be mixed up
+fn stats(name: &str) -> DropStats {
+    common::stats()
+        .name(name)
+        .drops(100)
+        .ubers(50)
+        .games(10)
+        .medic_time(100)
+        .ranks(1, 2, 3, 4)
+        .build()
+}
+
+fn params(format: SummaryFormat) -> SummaryParams {
+    SummaryParams {
+        format,
+        ..SummaryParams::default()
+    }
+}
+
+#[test]
+fn test_markdown() {
+    assert_eq!(
+        format_summary(
+            &stats("Icewind"),
+            &Site::default(),
+            &params(SummaryFormat::Markdown)
+        ),
+        "**Icewind** - [drops\\.tf](https://drops.tf/profile/76561198024494988)\n\
+         - Drops: 100 (#1)\n\
+         - Drops per game: 10.00 (#4)\n\
+         - Drops per hour: 3600.00 (#3)\n\
+         - Drops per uber: 2.00 (#2)\n"
+    );
+}
+
+#[test]
+fn test_bbcode() {
+    assert_eq!(
+        format_summary(
+            &stats("Icewind"),
+            &Site::default(),
+            &params(SummaryFormat::BbCode)
+        ),
+        "[b]Icewind[/b] - [url=https://drops.tf/profile/76561198024494988]drops.tf[/url]\n\
+         Drops: 100 (#1)\n\
+         Drops per game: 10.00 (#4)\n\
+         Drops per hour: 3600.00 (#3)\n\
+         Drops per uber: 2.00 (#2)\n"
+    );
+}
+
+#[test]
+fn test_site_branding() {
+    let site = Site {
+        name: "ubers.example".into(),
+        url: "https://ubers.example".into(),
+        ..Site::default()
+    };
+    let summary = format_summary(&stats("Icewind"), &site, &params(SummaryFormat::BbCode));
+    assert!(
+        summary.starts_with(
+            "[b]Icewind[/b] - [url=https://ubers.example/profile/76561198024494988]ubers.example[/url]\n"
+        ),
+        "{}",
+        summary
+    );
+}
+
+#[test]
+fn test_escaped_names() {
+    let summary = format_summary(
+        &stats("*ice* [wind](x)"),
+        &Site::default(),
+        &params(SummaryFormat::Markdown),
+    );
+    assert!(
+        summary.starts_with("**\\*ice\\* \\[wind\\]\\(x\\)** - "),
+        "{}",
+        summary
+    );
+
+    let summary = format_summary(
+        &stats("[url=x]ice[/url]"),
+        &Site::default(),
+        &params(SummaryFormat::BbCode),
+    );
+    assert!(
+        summary.starts_with("[b](url=x)ice(/url)[/b] - "),
+        "{}",
+        summary
+    );
+}
+
+#[test]
+fn test_names_stay_on_one_line() {
+    for format in [SummaryFormat::Markdown, SummaryFormat::BbCode] {
+        let summary = format_summary(
+            &stats("ice\n- Drops: 9001 (#1)\r\nwind"),
+            &Site::default(),
+            &params(format),
+        );
+        assert_eq!(summary.lines().count(), 5, "{}", summary);
+        assert!(!summary.contains('\r'), "{}", summary);
+    }
+}
@@ -0,0 +1,119 @@
+//! Re-verifying stored vanity urls once they're old, the database tests need a postgres database
+//! in `TEST_DATABASE_URL`
+
+mod common;
+
+use chrono::{TimeDelta, Utc};
+use dropstf::{ClientIp, DataSource, DropsError, SteamId};
+use sqlx::PgPool;
+use std::time::Duration;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The fixtures with a stale and a fresh vanity url of Icewind, in a schema of their own
+async fn test_pool() -> Option<PgPool> {
+    common::test_pool(
+        Some("vanity"),
+        &["INSERT INTO vanity_urls(url, steam_id, resolved_at)
+        VALUES ('stale-vanity', '[U:1:64229260]', now() - interval '400 days'),
+            ('fresh-vanity', '[U:1:64229260]', now())"],
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_staleness_threshold() {
+    let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+    let data_source = DataSource::new(pool, String::new()).with_vanity_max_age(DAY);
+    let now = Utc::now();
+
+    assert!(!data_source.is_vanity_stale(now, now));
+    assert!(!data_source.is_vanity_stale(now - TimeDelta::hours(23), now));
+    // exactly at the max age is still fresh
+    assert!(!data_source.is_vanity_stale(now - TimeDelta::days(1), now));
+    assert!(data_source.is_vanity_stale(now - TimeDelta::days(1) - TimeDelta::seconds(1), now));
+    assert!(data_source.is_vanity_stale(now - TimeDelta::days(400), now));
+
+    let data_source = data_source.with_vanity_max_age(Duration::ZERO);
+    assert!(!data_source.is_vanity_stale(now - TimeDelta::days(400), now));
+}
+
+#[tokio::test]
+async fn test_stale_vanity_kept_when_steam_fails() {
+    let Some(pool) = test_pool().await else {
+        return;
+    };
+
+    // steam isn't reachable from the tests, so re-verifying the stale url fails
+    let data_source = DataSource::new(pool.clone(), String::new())
+        .with_vanity_max_age(DAY)
+        .with_vanity_rate_limit(2);
+    let icewind = SteamId::new(76561198024494988);
+    for url in ["stale-vanity", "fresh-vanity"] {
+        let resolved = data_source
+            .resolve_vanity_url(url, ClientIp::default())
+            .await
+            .unwrap();
+        assert_eq!(resolved, Some(icewind), "{}", url);
+    }
+
+    let resolved = data_source
+        .resolve_vanity_urls(&["stale-vanity", "fresh-vanity"], ClientIp::default())
+        .await
+        .unwrap();
+    assert_eq!(resolved["stale-vanity"].as_ref().unwrap(), &Some(icewind));
+    assert_eq!(resolved["fresh-vanity"].as_ref().unwrap(), &Some(icewind));
+
+    // the failed check doesn't count as a fresh resolve, so it's tried again later
+    let age_days: f64 = sqlx::query_scalar(
+        "SELECT EXTRACT(DAY FROM now() - resolved_at)::float8 FROM vanity_urls WHERE url = 'stale-vanity'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert!(age_days >= 399.0, "{}", age_days);
+
+    // and isn't repeated right away, only the first check counted towards the rate limit
+    let error = data_source
+        .resolve_vanity_url("unknown-vanity", ClientIp::default())
+        .await
+        .unwrap_err();
+    assert!(
+        !matches!(error, DropsError::TooManyRequests { .. }),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test]
+async fn test_batch_failures_per_url() {
+    let Some(pool) = test_pool().await else {
+        return;
+    };
+
+    let data_source = DataSource::new(pool, String::new()).with_vanity_rate_limit(1);
+    let error = data_source
+        .resolve_vanity_urls(
+            &["fresh-vanity", "unknown-a", "unknown-b"],
+            ClientIp::default(),
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, DropsError::TooManyRequests { .. }),
+        "{}",
+        error
+    );
+
+    // the refused batch didn't use up the limit, and steam failing for one url doesn't fail the
+    // stored one
+    let resolved = data_source
+        .resolve_vanity_urls(&["fresh-vanity", "unknown-a"], ClientIp::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        resolved["fresh-vanity"].as_ref().unwrap(),
+        &Some(SteamId::new(76561198024494988))
+    );
+    assert!(resolved["unknown-a"].is_err());
+}